@@ -1,7 +1,3 @@
 // src/api/mod.rs
 pub mod handlers;
 pub mod routes;
-
-// src/api/routes.rs
-use super::handlers;
-use actix_web::web;
@@ -1,38 +1,154 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
 use serde::{Deserialize, Serialize};
-use crate::{models::{Wallet, Token}, AppState};
+use crate::{models::{Commitment, NftCollection, Wallet, Token}, AppState};
+use crate::services::blockchain::nft::group_by_collection;
+use crate::services::blockchain::sns;
+use crate::services::blockchain::{NATIVE_DECIMALS, NATIVE_MINT};
+use crate::services::chain::ChainClient;
+use crate::services::diagnostics::{summarize_failures, FAILURE_HISTORY_LIMIT};
+use crate::services::fees::{priority_fee_accounts, summarize_fees, NetworkFeeLevels, FEE_HISTORY_LIMIT};
+use crate::services::graph::{CounterpartyGraph, GraphEdge};
+use crate::services::history::AsOf;
+use crate::services::prices;
+use crate::services::programs::ProgramProfile;
+use crate::services::spam::SuspiciousAsset;
+use crate::utils::helpers::{format_token_amount, parse_address, AppError};
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
 use uuid::Uuid;
 
+// `address` may also be a `.sol` domain
 #[derive(Debug, Deserialize)]
 pub struct WalletAnalysisRequest {
     pub address: String,
 }
 
+// A wallet address, and the `.sol` domain it was resolved from if any
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedAddress {
+    pub address: String,
+    pub domain: Option<String>,
+}
+
+// Accepts a base58 address or a `.sol` domain, which is resolved to the
+// address that currently owns it
+async fn resolve_wallet(chain: &dyn ChainClient, input: &str) -> Result<ResolvedAddress, AppError> {
+    if !sns::is_sol_domain(input) {
+        return Ok(ResolvedAddress {
+            address: parse_address(input)?.to_string(),
+            domain: None,
+        });
+    }
+
+    let domain = input.trim().to_lowercase();
+    sns::domain_key(&domain).map_err(|e| AppError::InvalidInput(e.to_string()))?;
+    let address = chain
+        .resolve_domain(&domain)
+        .await
+        .map_err(|e| AppError::NotFound(format!("Could not resolve {}: {}", domain, e)))?;
+    // The tokenizer's escrow holds the domain on behalf of its NFT holder;
+    // analysing the escrow would mix up every tokenized domain
+    if address == sns::tokenizer_escrow().to_string() {
+        return Err(AppError::NotFound(format!(
+            "{} is tokenized and its NFT holder could not be found",
+            domain
+        )));
+    }
+    Ok(ResolvedAddress {
+        address,
+        domain: Some(domain),
+    })
+}
+
+// `?commitment=confirmed|finalized&as_of=<slot|timestamp|date>`; commitment
+// defaults to the client's, and `as_of` is only read by handlers that serve
+// past snapshots, which otherwise use the wallet's current holdings
+#[derive(Debug, Deserialize)]
+pub struct CommitmentQuery {
+    pub commitment: Option<Commitment>,
+    pub as_of: Option<AsOf>,
+}
+
+impl CommitmentQuery {
+    fn resolve(&self, state: &AppState) -> Commitment {
+        self.commitment
+            .unwrap_or_else(|| state.blockchain_client.commitment())
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct WalletAnalysisResponse {
+    #[serde(flatten)]
+    pub resolved: ResolvedAddress,
     pub wallet: Wallet,
     pub analysis: WalletAnalysis,
+    pub program_profile: ProgramProfile,
     pub recommendations: Vec<String>,
 }
 
 pub async fn analyze_wallet(
     data: web::Json<WalletAnalysisRequest>,
+    query: web::Query<CommitmentQuery>,
     state: web::Data<AppState>,
 ) -> impl Responder {
-    match state.blockchain_client.get_wallet_tokens(&data.address).await {
-        Ok(tokens) => {
-            let wallet = Wallet {
-                id: Uuid::new_v4(),
-                address: data.address.clone(),
-                tokens,
-                ..Default::default()
-            };
-
-            match state.ai_service.analyze_wallet(&wallet).await {
+    let resolved = match resolve_wallet(state.blockchain_client.as_ref(), &data.address).await {
+        Ok(resolved) => resolved,
+        Err(e) => return e.error_response(),
+    };
+    let address = &resolved.address;
+
+    let commitment = query.resolve(&state);
+    let holdings = match query.as_of {
+        // Rebuilt from stored history, which does not track stake accounts
+        Some(as_of) => state
+            .history_service
+            .balances_as_of(address, as_of, commitment)
+            .await
+            .map(|tokens| (tokens, Vec::new())),
+        None => futures::try_join!(
+            state.blockchain_client.get_wallet_tokens(address, commitment),
+            state.blockchain_client.get_stake_positions(address, commitment),
+        ),
+    };
+
+    match holdings {
+        Ok((tokens, stake_positions)) => {
+            let mut wallet = Wallet::new(address.clone());
+            wallet.tokens = tokens;
+            wallet.stake_positions = stake_positions;
+            // Past snapshots are valued at current prices
+            let prices = value_wallet(&state, &mut wallet).await;
+            let suspicious_assets = screen_wallet(&state, &mut wallet, &prices, commitment).await;
+
+            // Built from stored history, so a wallet we have not indexed yet
+            // gets an empty profile
+            let program_profile = state
+                .profile_service
+                .build_profile(address, commitment)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to build program profile for {}: {}", address, e);
+                    ProgramProfile::default()
+                });
+
+            match state
+                .ai_service
+                .analyze_wallet(&wallet, &program_profile.features, suspicious_assets)
+                .await
+            {
                 Ok(analysis) => {
+                    // Past snapshots are not what the wallet holds now
+                    if query.as_of.is_none() {
+                        wallet.risk_score = analysis.risk_score as f32;
+                        if let Err(e) = store_wallet(&state, &mut wallet).await {
+                            warn!("Failed to store wallet {}: {}", address, e);
+                        }
+                    }
                     let response = WalletAnalysisResponse {
+                        resolved,
                         wallet,
                         analysis,
+                        program_profile,
                         recommendations: vec![
                             "Consider diversifying your portfolio".to_string(),
                             "Reduce exposure to high-risk tokens".to_string(),
@@ -47,6 +163,109 @@ pub async fn analyze_wallet(
     }
 }
 
+// Stores an analysed wallet so its metrics and reports can be served by id.
+// A wallet already stored keeps its id, creation time and NFT inventory; a
+// new one is added to the live sync.
+async fn store_wallet(state: &AppState, wallet: &mut Wallet) -> anyhow::Result<()> {
+    let stored = state.db.get_wallet_by_address(&wallet.address).await?;
+    if let Some(stored) = &stored {
+        wallet.id = stored.id;
+        wallet.created_at = stored.created_at;
+        wallet.nfts = stored.nfts.clone();
+    }
+    state.db.save_wallet(wallet).await?;
+
+    if let (None, Some(tracker)) = (stored, &state.wallet_tracker) {
+        tracker.track(wallet.address.clone()).await;
+    }
+    Ok(())
+}
+
+// USD price per mint from the price provider, falling back to the price
+// cached in the `tokens` collection. Mints with neither are left out.
+async fn load_prices(state: &AppState, mints: &[String]) -> HashMap<String, f64> {
+    prices::load_prices(state.price_provider.as_ref(), state.db.as_ref(), mints).await
+}
+
+async fn load_sol_price(state: &AppState) -> f64 {
+    let native = NATIVE_MINT.to_string();
+    load_prices(state, &[native.clone()])
+        .await
+        .get(&native)
+        .copied()
+        .unwrap_or_default()
+}
+
+// Prices holdings with `load_prices`; mints without a price are left at
+// zero. Stake and liquid staking tokens are valued at the SOL price, the
+// latter through their pool's exchange rate. Returns the prices loaded.
+async fn value_wallet(state: &AppState, wallet: &mut Wallet) -> HashMap<String, f64> {
+    let mints: Vec<String> = wallet.tokens.iter().map(|token| token.token_address.clone()).collect();
+    let native = NATIVE_MINT.to_string();
+    let mut priced = mints.clone();
+    if !priced.contains(&native) {
+        priced.push(native.clone());
+    }
+    let prices = load_prices(state, &priced).await;
+    let sol_price = prices.get(&native).copied().unwrap_or_default();
+
+    let lst_rates = state
+        .blockchain_client
+        .get_liquid_staking_rates(&mints)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to load liquid staking rates: {}", e);
+            Default::default()
+        });
+
+    for token in &mut wallet.tokens {
+        if let Some(rate) = lst_rates.get(&token.token_address) {
+            token.sol_exchange_rate = Some(*rate);
+            token.value_usd = token.amount * rate * sol_price;
+        } else if let Some(price) = prices.get(&token.token_address) {
+            token.value_usd = token.amount * price;
+        }
+    }
+
+    for position in &mut wallet.stake_positions {
+        position.value_usd = format_token_amount(position.lamports, NATIVE_DECIMALS) * sol_price;
+    }
+
+    wallet.update_total_value();
+    prices
+}
+
+// Moves spam and phishing tokens out of the wallet's holdings, so they count
+// towards neither its value nor its scores. Holdings are kept as they are if
+// screening fails. `prices` are the ones the wallet was valued with.
+async fn screen_wallet(
+    state: &AppState,
+    wallet: &mut Wallet,
+    prices: &HashMap<String, f64>,
+    commitment: Commitment,
+) -> Vec<SuspiciousAsset> {
+    let mints: Vec<String> = wallet.tokens.iter().map(|token| token.token_address.clone()).collect();
+    let tokens = load_tokens(state, &mints, prices).await;
+
+    let balances = std::mem::take(&mut wallet.tokens);
+    match state
+        .spam_filter
+        .screen(&wallet.address, balances.clone(), &tokens, commitment)
+        .await
+    {
+        Ok((kept, suspicious)) => {
+            wallet.tokens = kept;
+            wallet.update_total_value();
+            suspicious
+        }
+        Err(e) => {
+            warn!("Failed to screen {} for spam tokens: {}", wallet.address, e);
+            wallet.tokens = balances;
+            Vec::new()
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TokenAnalysisRequest {
     pub address: String,
@@ -59,15 +278,131 @@ pub struct TokenAnalysisResponse {
     pub price_prediction: PricePrediction,
 }
 
+// Supply and authorities change, so cached token info is re-read from chain
+// once it is this old
+const TOKEN_INFO_TTL_SECS: i64 = 3_600;
+
+fn needs_refresh(token: &Token, now: chrono::DateTime<chrono::Utc>) -> bool {
+    !token
+        .refreshed_at
+        .is_some_and(|refreshed_at| now - refreshed_at < chrono::Duration::seconds(TOKEN_INFO_TTL_SECS))
+}
+
+// Token info is served from the `tokens` collection and read from chain when
+// missing or stale; a stale entry is still served if chain is unreachable.
+// The price provider's price replaces the cached one, and new, refreshed or
+// repriced tokens are cached.
+async fn load_token(state: &AppState, address: &str) -> anyhow::Result<Token> {
+    let (mut token, mut changed) = match state.db.get_token(address).await? {
+        Some(cached) if !needs_refresh(&cached, chrono::Utc::now()) => (cached, false),
+        cached => match state.blockchain_client.get_token_info(address).await {
+            Ok(mut token) => {
+                if let Some(cached) = &cached {
+                    keep_market_data(&mut token, cached);
+                }
+                (token, true)
+            }
+            Err(e) => match cached {
+                Some(cached) => {
+                    warn!("Failed to refresh token {}, serving cached info: {}", address, e);
+                    (cached, false)
+                }
+                None => return Err(e),
+            },
+        },
+    };
+
+    match state.price_provider.get_prices(&[address.to_string()]).await {
+        Ok(prices) => {
+            if let Some(price) = prices.get(address) {
+                changed |= token.price_usd != price.price_usd;
+                token.price_usd = price.price_usd;
+            }
+        }
+        Err(e) => warn!("Failed to price token {}: {}", address, e),
+    }
+
+    if changed {
+        if let Err(e) = state.db.save_token(&token).await {
+            warn!("Failed to cache token {}: {}", address, e);
+        }
+    }
+    Ok(token)
+}
+
+// `load_token` for many mints at once: cached tokens are read in one query,
+// missing and stale ones from chain in one pass, and all are priced from
+// `prices`. Only new, refreshed or repriced tokens are cached. Mints with
+// no token info are left out.
+async fn load_tokens(state: &AppState, mints: &[String], prices: &HashMap<String, f64>) -> HashMap<String, Token> {
+    let mut tokens: HashMap<String, Token> = match state.db.get_tokens(mints).await {
+        Ok(cached) => cached.into_iter().map(|token| (token.address.clone(), token)).collect(),
+        Err(e) => {
+            warn!("Failed to read cached token info: {}", e);
+            HashMap::new()
+        }
+    };
+
+    let now = chrono::Utc::now();
+    let stale: Vec<String> = mints
+        .iter()
+        .filter(|mint| tokens.get(*mint).map_or(true, |token| needs_refresh(token, now)))
+        .cloned()
+        .collect();
+    let mut changed = HashSet::new();
+    if !stale.is_empty() {
+        match state.blockchain_client.get_token_infos(&stale).await {
+            Ok(fresh) => {
+                for (mint, mut token) in fresh {
+                    if let Some(cached) = tokens.get(&mint) {
+                        keep_market_data(&mut token, cached);
+                    }
+                    changed.insert(mint.clone());
+                    tokens.insert(mint, token);
+                }
+            }
+            Err(e) => warn!("Failed to refresh token info, serving cached info: {}", e),
+        }
+    }
+
+    for (mint, token) in &mut tokens {
+        if let Some(price) = prices.get(mint) {
+            if token.price_usd != *price {
+                token.price_usd = *price;
+                changed.insert(mint.clone());
+            }
+        }
+    }
+
+    for mint in &changed {
+        if let Err(e) = state.db.save_token(&tokens[mint]).await {
+            warn!("Failed to cache token {}: {}", mint, e);
+        }
+    }
+    tokens
+}
+
+// Chain carries no market data; keep what was last seen
+fn keep_market_data(token: &mut Token, cached: &Token) {
+    token.price_usd = cached.price_usd;
+    token.market_cap_usd = cached.market_cap_usd;
+    token.volume_24h = cached.volume_24h;
+    token.price_change_24h = cached.price_change_24h;
+    token.liquidity_usd = cached.liquidity_usd;
+}
+
 pub async fn analyze_token(
     data: web::Json<TokenAnalysisRequest>,
     state: web::Data<AppState>,
 ) -> impl Responder {
-    match state.blockchain_client.get_token_info(&data.address).await {
+    if let Err(e) = parse_address(&data.address) {
+        return e.error_response();
+    }
+    match load_token(&state, &data.address).await {
         Ok(token) => {
             match state.ai_service.analyze_token(&token).await {
                 Ok(analysis) => {
-                    let price_prediction = state.ai_service.predict_token_price(&token).await?;
+                    let price_prediction = analysis.price_prediction.clone();
                     let response = TokenAnalysisResponse {
                         token,
                         analysis,
@@ -84,11 +419,15 @@ pub async fn analyze_token(
 
 pub async fn get_portfolio_metrics(
     wallet_id: web::Path<Uuid>,
+    query: web::Query<CommitmentQuery>,
     state: web::Data<AppState>,
 ) -> impl Responder {
     match state.db.get_wallet(wallet_id.into_inner()).await {
         Ok(wallet) => {
-            let metrics = state.portfolio_service.calculate_metrics(&wallet).await?;
+            let metrics = state
+                .portfolio_service
+                .calculate_metrics(&wallet, query.resolve(&state), query.as_of)
+                .await?;
             HttpResponse::Ok().json(metrics)
         }
         Err(e) => HttpResponse::NotFound().body(e.to_string()),
@@ -97,12 +436,13 @@ pub async fn get_portfolio_metrics(
 
 pub async fn get_transaction_history(
     wallet_id: web::Path<Uuid>,
+    query: web::Query<CommitmentQuery>,
     state: web::Data<AppState>,
 ) -> impl Responder {
     match state.db.get_wallet(wallet_id.into_inner()).await {
         Ok(wallet) => {
             let transactions = state.blockchain_client
-                .get_transactions(&wallet.address)
+                .get_transactions(&wallet.address, query.resolve(&state))
                 .await?;
             HttpResponse::Ok().json(transactions)
         }
@@ -110,34 +450,628 @@ pub async fn get_transaction_history(
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct NftInventoryResponse {
+    pub address: String,
+    pub total: usize,
+    pub collections: Vec<NftCollection>,
+}
+
+pub async fn get_wallet_nfts(
+    wallet_id: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let mut wallet = match state.db.get_wallet(wallet_id.into_inner()).await {
+        Ok(wallet) => wallet,
+        Err(e) => return HttpResponse::NotFound().body(e.to_string()),
+    };
+
+    match state.blockchain_client.get_nfts(&wallet.address).await {
+        Ok(nfts) => {
+            wallet.nfts = nfts;
+            if let Err(e) = state.db.save_wallet(&wallet).await {
+                warn!("Failed to store NFT inventory for {}: {}", wallet.address, e);
+            }
+
+            HttpResponse::Ok().json(NftInventoryResponse {
+                total: wallet.nfts.len(),
+                collections: group_by_collection(&wallet.nfts),
+                address: wallet.address,
+            })
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+// `?days=N` looks back N days; defaults to 30
+#[derive(Debug, Deserialize)]
+pub struct PeriodQuery {
+    pub days: Option<u32>,
+}
+
+impl PeriodQuery {
+    fn since(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now() - chrono::Duration::days(self.days.unwrap_or(30) as i64)
+    }
+}
+
+// Fee spend over the period for transactions the wallet paid for, compared
+// with recent priority fees on the accounts those transactions write-lock
+pub async fn get_wallet_fees(
+    wallet_id: web::Path<Uuid>,
+    query: web::Query<PeriodQuery>,
+    commitment: web::Query<CommitmentQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let wallet = match state.db.get_wallet(wallet_id.into_inner()).await {
+        Ok(wallet) => wallet,
+        Err(e) => return HttpResponse::NotFound().body(e.to_string()),
+    };
+
+    let since = query.since();
+    let transactions = match state
+        .db
+        .get_paid_transactions(&wallet.address, since, FEE_HISTORY_LIMIT, commitment.resolve(&state))
+        .await
+    {
+        Ok(transactions) => transactions,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    let sol_price = load_sol_price(&state).await;
+    let accounts = priority_fee_accounts(&wallet.address, &transactions);
+    let network = match state.blockchain_client.get_recent_prioritization_fees(&accounts).await {
+        Ok(samples) => NetworkFeeLevels::from_samples(samples),
+        Err(e) => {
+            warn!("Failed to load recent prioritization fees: {}", e);
+            NetworkFeeLevels::default()
+        }
+    };
+
+    HttpResponse::Ok().json(summarize_fees(&wallet.address, since, &transactions, sol_price, network))
+}
+
+// Failure rate over the period and the most common reasons the wallet's
+// transactions failed
+pub async fn get_wallet_failures(
+    wallet_id: web::Path<Uuid>,
+    query: web::Query<PeriodQuery>,
+    commitment: web::Query<CommitmentQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let wallet = match state.db.get_wallet(wallet_id.into_inner()).await {
+        Ok(wallet) => wallet,
+        Err(e) => return HttpResponse::NotFound().body(e.to_string()),
+    };
+
+    let since = query.since();
+    match state
+        .db
+        .get_paid_transactions(&wallet.address, since, FAILURE_HISTORY_LIMIT, commitment.resolve(&state))
+        .await
+    {
+        Ok(transactions) => HttpResponse::Ok().json(summarize_failures(&wallet.address, since, &transactions)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    #[default]
+    Json,
+    Graphml,
+}
+
+fn graph_response(graph: &CounterpartyGraph, format: GraphFormat) -> HttpResponse {
+    match format {
+        GraphFormat::Json => HttpResponse::Ok().json(graph),
+        GraphFormat::Graphml => HttpResponse::Ok()
+            .content_type("application/graphml+xml")
+            .body(graph.to_graphml()),
+    }
+}
+
+// `?hops=N&format=json|graphml`; hops defaults to 1
+#[derive(Debug, Deserialize)]
+pub struct GraphQuery {
+    pub hops: Option<usize>,
+    #[serde(default)]
+    pub format: GraphFormat,
+}
+
+pub async fn get_counterparty_graph(
+    address: web::Path<String>,
+    query: web::Query<GraphQuery>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let center = match resolve_wallet(state.blockchain_client.as_ref(), &address).await {
+        Ok(resolved) => resolved,
+        Err(e) => return e.error_response(),
+    };
+    match state
+        .graph_service
+        .neighbourhood(&center.address, query.hops.unwrap_or(1))
+        .await
+    {
+        Ok(graph) => graph_response(&graph, query.format),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+// `?from=A&to=B&max_hops=N&format=json|graphml`; either end may be a `.sol` domain
+#[derive(Debug, Deserialize)]
+pub struct PathQuery {
+    pub from: String,
+    pub to: String,
+    pub max_hops: Option<usize>,
+    #[serde(default)]
+    pub format: GraphFormat,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FundsPathResponse {
+    pub from: ResolvedAddress,
+    pub to: ResolvedAddress,
+    pub path: Vec<GraphEdge>,
+}
+
+pub async fn get_funds_path(query: web::Query<PathQuery>, state: web::Data<AppState>) -> impl Responder {
+    let ends = futures::try_join!(
+        resolve_wallet(state.blockchain_client.as_ref(), &query.from),
+        resolve_wallet(state.blockchain_client.as_ref(), &query.to)
+    );
+    let (from, to) = match ends {
+        Ok(ends) => ends,
+        Err(e) => return e.error_response(),
+    };
+
+    let max_hops = query.max_hops.unwrap_or(crate::services::graph::MAX_HOPS);
+    match state.graph_service.find_path(&from.address, &to.address, max_hops).await {
+        Ok(Some(path)) => match query.format {
+            GraphFormat::Json => HttpResponse::Ok().json(FundsPathResponse { from, to, path }),
+            GraphFormat::Graphml => graph_response(&CounterpartyGraph::from_edges(path), query.format),
+        },
+        Ok(None) => HttpResponse::NotFound().body(format!(
+            "No flow of funds from {} to {} within {} hops",
+            from.address, to.address, max_hops
+        )),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use actix_web::test;
+    use crate::db::store::{MemoryStore, Store};
+    use crate::models::{BalanceChange, TokenBalance, Transaction};
+    use crate::services::ai_analysis::AIService;
+    use crate::services::blockchain::replay::ReplaySender;
+    use crate::services::blockchain::SolanaClient;
+    use crate::services::candles::{CandleStore, MemoryCandles};
+    use crate::services::chain::fixture::{ChainFixture, WalletFixture};
+    use crate::services::chain::FixtureChain;
+    use crate::services::graph::CounterpartyGraphService;
+    use crate::services::history::BalanceHistoryService;
+    use crate::services::portfolio::PortfolioService;
+    use crate::services::prices::{Price, StaticPriceProvider};
+    use crate::services::programs::{ProgramProfileService, ProtocolRegistry};
+    use crate::services::spam::{SpamClassifier, SpamFilter};
+    use crate::services::subscriptions::WalletUpdate;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    const WALLET: &str = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn price(mint: &str, price_usd: f64) -> Price {
+        Price {
+            mint: mint.to_string(),
+            price_usd,
+            confidence: 0.0,
+            publish_time: chrono::Utc::now(),
+        }
+    }
+
+    fn usdc() -> Token {
+        Token {
+            address: USDC.to_string(),
+            symbol: "USDC".to_string(),
+            name: "USD Coin".to_string(),
+            decimals: 6,
+            total_supply: 1_000_000_000,
+            price_usd: 0.0,
+            market_cap_usd: 0.0,
+            volume_24h: 0.0,
+            price_change_24h: 0.0,
+            mint_authority: None,
+            freeze_authority: None,
+            uri: None,
+            liquidity_usd: None,
+            refreshed_at: None,
+        }
+    }
+
+    // The wallet, also known as bonfida.sol, holds 2 SOL at $150 and 100
+    // USDC at $1, with everything stored in memory
+    async fn app_state(store: Arc<MemoryStore>) -> AppState {
+        let chain = FixtureChain::new(ChainFixture {
+            wallets: HashMap::from([(
+                WALLET.to_string(),
+                WalletFixture {
+                    tokens: vec![
+                        TokenBalance::new(NATIVE_MINT.to_string(), 2_000_000_000, 9),
+                        TokenBalance::new(USDC.to_string(), 100_000_000, 6),
+                    ],
+                    ..Default::default()
+                },
+            )]),
+            tokens: vec![usdc()],
+            domains: HashMap::from([("bonfida.sol".to_string(), WALLET.to_string())]),
+            ..Default::default()
+        });
+        let price_provider = Arc::new(StaticPriceProvider::new(vec![
+            price(&NATIVE_MINT.to_string(), 150.0),
+            price(USDC, 1.0),
+        ]));
+        let candle_store = Arc::new(CandleStore::new(Arc::new(MemoryCandles::default()), price_provider.clone()));
+
+        AppState {
+            db: store.clone(),
+            blockchain_client: Arc::new(chain),
+            price_provider,
+            ai_service: Arc::new(AIService::new(candle_store).await.unwrap()),
+            portfolio_service: Arc::new(PortfolioService::new(store.clone())),
+            profile_service: Arc::new(ProgramProfileService::new(
+                store.clone(),
+                Arc::new(ProtocolRegistry::default()),
+            )),
+            graph_service: Arc::new(CounterpartyGraphService::new(store.clone())),
+            history_service: Arc::new(BalanceHistoryService::new(store.clone())),
+            spam_filter: Arc::new(SpamFilter::new(store, Arc::new(SpamClassifier::default()))),
+            wallet_tracker: None,
+        }
+    }
 
     #[actix_rt::test]
     async fn test_analyze_wallet() {
-        let app_state = web::Data::new(AppState::new().await);
-        let req = test::TestRequest::post()
-            .set_json(&WalletAnalysisRequest {
-                address: "test_address".to_string(),
-            })
-            .to_http_request();
+        let store = Arc::new(MemoryStore::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state(store.clone()).await))
+                .configure(crate::api::routes::configure),
+        )
+        .await;
 
-        let resp = analyze_wallet(web::Json(req.into()), app_state).await;
+        let req = test::TestRequest::post()
+            .uri("/api/wallets/analyze")
+            .set_json(json!({ "address": WALLET }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["address"], WALLET);
+        assert_eq!(body["wallet"]["total_value_usd"], 400.0);
+        assert_eq!(body["analysis"]["suspicious_assets"], json!([]));
+        // Token info read from chain is cached
+        assert!(store.get_token(USDC).await.unwrap().is_some());
     }
 
+    // The same wallet as an RPC node answered for it, checked in under
+    // data/rpc_fixtures/wallet, read and valued without network access
     #[actix_rt::test]
-    async fn test_analyze_token() {
-        let app_state = web::Data::new(AppState::new().await);
+    async fn test_value_replayed_wallet() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/data/rpc_fixtures/wallet");
+        let chain = SolanaClient::with_sender(Arc::new(ReplaySender::load(fixtures).unwrap()));
+        let state = AppState {
+            blockchain_client: Arc::new(chain),
+            ..app_state(Arc::new(MemoryStore::default())).await
+        };
+
+        let mut wallet = Wallet::new(WALLET.to_string());
+        wallet.tokens = state
+            .blockchain_client
+            .get_wallet_tokens(WALLET, Commitment::Confirmed)
+            .await
+            .unwrap();
+        let prices = value_wallet(&state, &mut wallet).await;
+
+        assert_eq!(wallet.tokens.len(), 2);
+        assert_eq!(wallet.tokens[0].slot, 250_000_010);
+        assert_eq!(prices[USDC], 1.0);
+        assert_eq!(wallet.total_value_usd, 400.0);
+    }
+
+    #[actix_rt::test]
+    async fn test_analyzed_wallets_are_stored_and_tracked() {
+        let store = Arc::new(MemoryStore::default());
+        let subscription = FixtureChain::new(ChainFixture::default())
+            .subscribe(Vec::new())
+            .await
+            .unwrap();
+        let mut updates = subscription.updates;
+        assert_eq!(updates.recv().await.unwrap(), WalletUpdate::Subscribed { wallets: Vec::new() });
+
+        let state = AppState {
+            wallet_tracker: Some(subscription.tracker),
+            ..app_state(store.clone()).await
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .configure(crate::api::routes::configure),
+        )
+        .await;
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .uri("/api/wallets/analyze")
+                .set_json(json!({ "address": WALLET }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            ids.push(body["wallet"]["id"].clone());
+        }
+
+        // Analysed again, the wallet keeps its id and is tracked only once
+        assert_eq!(ids[0], ids[1]);
+        let stored = store.get_wallet_by_address(WALLET).await.unwrap().unwrap();
+        assert_eq!(stored.total_value_usd, 400.0);
+        assert_eq!(
+            updates.recv().await.unwrap(),
+            WalletUpdate::Subscribed {
+                wallets: vec![WALLET.to_string()]
+            }
+        );
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_analyze_wallet_resolves_domains() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state(Arc::new(MemoryStore::default())).await))
+                .configure(crate::api::routes::configure),
+        )
+        .await;
+
         let req = test::TestRequest::post()
-            .set_json(&TokenAnalysisRequest {
-                address: "test_token".to_string(),
+            .uri("/api/wallets/analyze")
+            .set_json(json!({ "address": "Bonfida.sol" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["address"], WALLET);
+        assert_eq!(body["domain"], "bonfida.sol");
+        assert_eq!(body["wallet"]["total_value_usd"], 400.0);
+
+        for (address, status) in [("test_address", 400), ("unregistered.sol", 404)] {
+            let req = test::TestRequest::post()
+                .uri("/api/wallets/analyze")
+                .set_json(json!({ "address": address }))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), status);
+        }
+    }
+
+    // A finalized transaction leaving the wallet with `lamports`
+    fn sol_balance_at(slot: u64, lamports: u64) -> Transaction {
+        Transaction {
+            balance_changes: vec![BalanceChange {
+                account: WALLET.to_string(),
+                owner: WALLET.to_string(),
+                mint: None,
+                decimals: 9,
+                pre: 0,
+                post: lamports,
+            }],
+            commitment: Commitment::Finalized,
+            ..Transaction::paid_by(slot, WALLET)
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_analyze_wallet_as_of() {
+        let store = Arc::new(MemoryStore::default());
+        store
+            .transactions
+            .lock()
+            .unwrap()
+            .extend([sol_balance_at(10, 1_000_000_000), sol_balance_at(20, 3_000_000_000)]);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state(store).await))
+                .configure(crate::api::routes::configure),
+        )
+        .await;
+
+        // Rebuilt from stored history and valued at current prices
+        for (as_of, value) in [(10, 150.0), (20, 450.0)] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/wallets/analyze?as_of={}&commitment=finalized", as_of))
+                .set_json(json!({ "address": WALLET }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["wallet"]["total_value_usd"], value);
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/api/wallets/analyze?as_of=yesterday")
+            .set_json(json!({ "address": WALLET }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_wallet_failures_at_commitment() {
+        let store = Arc::new(MemoryStore::default());
+        let wallet = Wallet::new(WALLET.to_string());
+        store.save_wallet(&wallet).await.unwrap();
+        store.transactions.lock().unwrap().extend([
+            Transaction {
+                block_time: chrono::Utc::now(),
+                commitment: Commitment::Finalized,
+                ..Transaction::paid_by(1, WALLET)
+            },
+            Transaction {
+                block_time: chrono::Utc::now(),
+                success: false,
+                ..Transaction::paid_by(2, WALLET)
+            },
+        ]);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state(store).await))
+                .configure(crate::api::routes::configure),
+        )
+        .await;
+
+        for (query, transactions) in [("", 2), ("?commitment=finalized", 1)] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/api/wallets/{}/failures{}", wallet.id, query))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["transactions"], transactions);
+        }
+    }
+
+    fn fixture_chain(domains: &[(&str, String)]) -> FixtureChain {
+        FixtureChain::new(ChainFixture {
+            domains: domains
+                .iter()
+                .map(|(domain, owner)| (domain.to_string(), owner.clone()))
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_needs_refresh() {
+        let now = chrono::Utc::now();
+        let mut token = Token {
+            address: "test_token".to_string(),
+            symbol: "TEST".to_string(),
+            name: "Test Token".to_string(),
+            decimals: 9,
+            total_supply: 1_000_000_000,
+            price_usd: 1.0,
+            market_cap_usd: 1_000_000_000.0,
+            volume_24h: 0.0,
+            price_change_24h: 0.0,
+            mint_authority: None,
+            freeze_authority: None,
+            uri: None,
+            liquidity_usd: None,
+            refreshed_at: None,
+        };
+        // Cached before refresh times were recorded
+        assert!(needs_refresh(&token, now));
+
+        token.refreshed_at = Some(now - chrono::Duration::minutes(5));
+        assert!(!needs_refresh(&token, now));
+
+        token.refreshed_at = Some(now - chrono::Duration::seconds(TOKEN_INFO_TTL_SECS));
+        assert!(needs_refresh(&token, now));
+    }
+
+    #[actix_rt::test]
+    async fn test_load_tokens() {
+        let store = Arc::new(MemoryStore::default());
+        let state = app_state(store.clone()).await;
+        let mints = vec![USDC.to_string(), NATIVE_MINT.to_string()];
+        let prices = HashMap::from([(USDC.to_string(), 1.0)]);
+
+        // Missing tokens are read from chain, priced and cached; the fixture
+        // chain has no info for SOL
+        let tokens = load_tokens(&state, &mints, &prices).await;
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[USDC].price_usd, 1.0);
+        assert_eq!(store.get_token(USDC).await.unwrap().unwrap().price_usd, 1.0);
+
+        // Fresh cached tokens are served without reading chain
+        store
+            .save_token(&Token {
+                name: "Cached USD Coin".to_string(),
+                price_usd: 1.0,
+                refreshed_at: Some(chrono::Utc::now()),
+                ..usdc()
             })
-            .to_http_request();
+            .await
+            .unwrap();
+        let tokens = load_tokens(&state, &mints, &prices).await;
+        assert_eq!(tokens[USDC].name, "Cached USD Coin");
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert!(parse_address(" 9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM ").is_ok());
+        assert!(matches!(parse_address("test_address"), Err(AppError::InvalidInput(_))));
+        assert!(matches!(parse_address(""), Err(AppError::InvalidInput(_))));
+    }
+
+    #[actix_rt::test]
+    async fn test_resolve_wallet() {
+        let owner = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM";
+        let chain = fixture_chain(&[("bonfida.sol", owner.to_string())]);
+
+        let resolved = resolve_wallet(&chain, owner).await.unwrap();
+        assert_eq!(resolved.address, owner);
+        assert_eq!(resolved.domain, None);
 
-        let resp = analyze_token(web::Json(req.into()), app_state).await;
+        let resolved = resolve_wallet(&chain, " Bonfida.SOL").await.unwrap();
+        assert_eq!(resolved.address, owner);
+        assert_eq!(resolved.domain.as_deref(), Some("bonfida.sol"));
+
+        assert!(matches!(
+            resolve_wallet(&chain, "test_address").await,
+            Err(AppError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            resolve_wallet(&chain, "unregistered.sol").await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(matches!(
+            resolve_wallet(&chain, "a.b.c.sol").await,
+            Err(AppError::InvalidInput(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_resolve_wallet_rejects_tokenizer_escrow() {
+        let chain = fixture_chain(&[("tokenized.sol", sns::tokenizer_escrow().to_string())]);
+        assert!(matches!(
+            resolve_wallet(&chain, "tokenized.sol").await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_analyze_token() {
+        let store = Arc::new(MemoryStore::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state(store.clone()).await))
+                .configure(crate::api::routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/tokens/analyze")
+            .set_json(json!({ "address": USDC }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["token"]["symbol"], "USDC");
+        assert_eq!(body["token"]["price_usd"], 1.0);
+        assert_eq!(store.get_token(USDC).await.unwrap().unwrap().price_usd, 1.0);
+
+        let req = test::TestRequest::post()
+            .uri("/api/tokens/analyze")
+            .set_json(json!({ "address": "test_token" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
     }
 }
\ No newline at end of file
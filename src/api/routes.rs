@@ -0,0 +1,17 @@
+use super::handlers;
+use actix_web::web;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api")
+            .route("/wallets/analyze", web::post().to(handlers::analyze_wallet))
+            .route("/wallets/{wallet_id}/metrics", web::get().to(handlers::get_portfolio_metrics))
+            .route("/wallets/{wallet_id}/transactions", web::get().to(handlers::get_transaction_history))
+            .route("/wallets/{wallet_id}/nfts", web::get().to(handlers::get_wallet_nfts))
+            .route("/wallets/{wallet_id}/fees", web::get().to(handlers::get_wallet_fees))
+            .route("/wallets/{wallet_id}/failures", web::get().to(handlers::get_wallet_failures))
+            .route("/graph/path", web::get().to(handlers::get_funds_path))
+            .route("/graph/{address}", web::get().to(handlers::get_counterparty_graph))
+            .route("/tokens/analyze", web::post().to(handlers::analyze_token)),
+    );
+}
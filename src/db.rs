@@ -0,0 +1,3 @@
+// src/db/mod.rs
+pub mod mongodb;
+pub mod store;
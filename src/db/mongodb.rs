@@ -1,16 +1,73 @@
 use mongodb::{
-    bson::{doc, Document},
-    options::ClientOptions,
+    bson::{self, doc, Document},
+    options::{
+        ClientOptions, CreateCollectionOptions, FindOptions, ReplaceOptions, TimeseriesGranularity, TimeseriesOptions,
+    },
     Client, Collection, Database,
 };
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::models::{Wallet, Token, Transaction};
+use crate::models::{
+    BackfillCheckpoint, BalanceCheckpoint, Candle, Commitment, IndexerCursor, Resolution, Wallet, Token, Transaction,
+};
 
 pub struct MongoDB {
     db: Database,
 }
 
+// A candle as stored in its resolution's time-series collection, which
+// needs a BSON datetime time field and groups series by the `mint` meta field
+#[derive(Debug, Serialize, Deserialize)]
+struct CandleDocument {
+    time: bson::DateTime,
+    mint: String,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+}
+
+impl CandleDocument {
+    fn from_candle(candle: &Candle) -> Self {
+        Self {
+            time: bson::DateTime::from_millis(candle.open_time.timestamp_millis()),
+            mint: candle.mint.clone(),
+            open: candle.open,
+            high: candle.high,
+            low: candle.low,
+            close: candle.close,
+            volume: candle.volume,
+        }
+    }
+
+    fn into_candle(self, resolution: Resolution) -> Candle {
+        Candle {
+            mint: self.mint,
+            resolution,
+            open_time: DateTime::from_timestamp_millis(self.time.timestamp_millis()).unwrap_or_default(),
+            open: self.open,
+            high: self.high,
+            low: self.low,
+            close: self.close,
+            volume: self.volume,
+        }
+    }
+}
+
+fn candle_collection(resolution: Resolution) -> String {
+    format!("candles_{}", resolution.as_str())
+}
+
+// Matches transaction records seen from `address`. Records stored before
+// transactions were kept per wallet have no `wallet` field.
+fn seen_from(address: &str) -> Document {
+    doc! { "$in": [address, bson::Bson::Null] }
+}
+
 impl MongoDB {
     pub async fn new() -> Result<Self> {
         let mongodb_uri = std::env::var("MONGODB_URI")?;
@@ -26,6 +83,7 @@ impl MongoDB {
         self.create_wallet_indexes().await?;
         self.create_token_indexes().await?;
         self.create_transaction_indexes().await?;
+        self.create_candle_collections().await?;
         Ok(())
     }
 
@@ -55,12 +113,63 @@ impl MongoDB {
         Ok(())
     }
 
+    // Time-series collections have to be created explicitly, once
+    async fn create_candle_collections(&self) -> Result<()> {
+        let existing = self.db.list_collection_names(None).await?;
+        for resolution in [Resolution::Minute, Resolution::Hour, Resolution::Day] {
+            let name = candle_collection(resolution);
+            if existing.contains(&name) {
+                continue;
+            }
+            let granularity = match resolution {
+                Resolution::Minute => TimeseriesGranularity::Minutes,
+                Resolution::Hour | Resolution::Day => TimeseriesGranularity::Hours,
+            };
+            let timeseries = TimeseriesOptions::builder()
+                .time_field("time".to_string())
+                .meta_field(Some("mint".to_string()))
+                .granularity(Some(granularity))
+                .build();
+            self.db
+                .create_collection(&name, CreateCollectionOptions::builder().timeseries(timeseries).build())
+                .await?;
+        }
+        Ok(())
+    }
+
     async fn create_transaction_indexes(&self) -> Result<()> {
         let collection = self.db.collection::<Document>("transactions");
         collection
             .create_index(
                 doc! {
-                    "signature": 1
+                    "signature": 1,
+                    "wallet": 1
+                },
+                None,
+            )
+            .await?;
+        collection
+            .create_index(
+                doc! {
+                    "account_keys": 1
+                },
+                None,
+            )
+            .await?;
+        collection
+            .create_index(
+                doc! {
+                    "commitment": 1,
+                    "slot": 1
+                },
+                None,
+            )
+            .await?;
+        collection
+            .create_index(
+                doc! {
+                    "balance_changes.owner": 1,
+                    "slot": 1
                 },
                 None,
             )
@@ -78,35 +187,53 @@ impl MongoDB {
         Ok(wallet)
     }
 
+    pub async fn get_wallet_by_address(&self, address: &str) -> Result<Option<Wallet>> {
+        let collection = self.db.collection::<Wallet>("wallets");
+        Ok(collection.find_one(doc! { "address": address }, None).await?)
+    }
+
+    // Addresses of every stored wallet; these are the wallets we keep in sync
+    pub async fn get_wallet_addresses(&self) -> Result<Vec<String>> {
+        let collection = self.db.collection::<Wallet>("wallets");
+        let addresses = collection.distinct("address", None, None).await?;
+        Ok(addresses
+            .into_iter()
+            .filter_map(|address| address.as_str().map(str::to_string))
+            .collect())
+    }
+
     pub async fn save_wallet(&self, wallet: &Wallet) -> Result<()> {
         let collection = self.db.collection::<Wallet>("wallets");
         collection
             .replace_one(
                 doc! { "_id": wallet.id.to_string() },
                 wallet,
-                None,
+                ReplaceOptions::builder().upsert(true).build(),
             )
             .await?;
         Ok(())
     }
 
     // Token Operations
-    pub async fn get_token(&self, address: &str) -> Result<Token> {
+    pub async fn get_token(&self, address: &str) -> Result<Option<Token>> {
         let collection = self.db.collection::<Token>("tokens");
-        let token = collection
-            .find_one(doc! { "address": address }, None)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("Token not found"))?;
-        Ok(token)
+        Ok(collection.find_one(doc! { "_id": address }, None).await?)
+    }
+
+    // The cached tokens among `addresses`; the rest are left out
+    pub async fn get_tokens(&self, addresses: &[String]) -> Result<Vec<Token>> {
+        let collection = self.db.collection::<Token>("tokens");
+        let cursor = collection.find(doc! { "_id": { "$in": addresses } }, None).await?;
+        Ok(cursor.try_collect().await?)
     }
 
     pub async fn save_token(&self, token: &Token) -> Result<()> {
         let collection = self.db.collection::<Token>("tokens");
         collection
             .replace_one(
-                doc! { "address": &token.address },
+                doc! { "_id": &token.address },
                 token,
-                None,
+                ReplaceOptions::builder().upsert(true).build(),
             )
             .await?;
         Ok(())
@@ -114,37 +241,341 @@ impl MongoDB {
 
     // Transaction Operations
     pub async fn save_transaction(&self, transaction: &Transaction) -> Result<()> {
+        // Upsert so that re-running a backfill over the same range is harmless.
+        // Each tracked wallet in a transaction keeps its own record.
         let collection = self.db.collection::<Transaction>("transactions");
         collection
-            .insert_one(transaction, None)
+            .replace_one(
+                doc! { "signature": &transaction.signature, "wallet": &transaction.wallet },
+                transaction,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
             .await?;
         Ok(())
     }
 
+    // Confirmed reads include finalized transactions; finalized reads only
+    // return transactions the reconciler has promoted
     pub async fn get_wallet_transactions(
         &self,
         wallet_address: &str,
         limit: i64,
         skip: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let collection = self.db.collection::<Transaction>("transactions");
+        let mut filter = doc! {
+            "$or": [
+                { "from_address": wallet_address },
+                { "to_address": wallet_address },
+                { "account_keys": wallet_address }
+            ],
+            "wallet": seen_from(wallet_address)
+        };
+        if commitment == Commitment::Finalized {
+            filter.insert("commitment", Commitment::Finalized.as_str());
+        }
+        // Oldest first, which is the order cost basis is built up in
+        let options = FindOptions::builder()
+            .sort(doc! { "block_time": 1, "slot": 1 })
+            .limit(limit)
+            .skip(u64::try_from(skip)?)
+            .build();
+        let mut cursor = collection.find(filter, options).await?;
+
+        let mut transactions = Vec::new();
+        while let Some(transaction) = cursor.try_next().await? {
+            transactions.push(transaction);
+        }
+        Ok(transactions)
+    }
+
+    // The most recent `limit` transactions of the wallet that moved any of
+    // `mints`, newest first
+    pub async fn get_mint_transactions(
+        &self,
+        wallet_address: &str,
+        mints: &[String],
+        limit: i64,
+        commitment: Commitment,
     ) -> Result<Vec<Transaction>> {
         let collection = self.db.collection::<Transaction>("transactions");
-        let mut cursor = collection
+        let mut filter = doc! {
+            "$and": [
+                { "$or": [
+                    { "from_address": wallet_address },
+                    { "to_address": wallet_address },
+                    { "account_keys": wallet_address }
+                ] },
+                { "$or": [
+                    { "balance_changes.mint": { "$in": mints } },
+                    { "token_address": { "$in": mints } }
+                ] }
+            ],
+            "wallet": seen_from(wallet_address)
+        };
+        if commitment == Commitment::Finalized {
+            filter.insert("commitment", Commitment::Finalized.as_str());
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "slot": -1 })
+            .limit(limit)
+            .build();
+        let cursor = collection.find(filter, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    // The most recent `limit` transactions `wallet_address` paid for, with a
+    // block time at or after `since`, newest first
+    pub async fn get_paid_transactions(
+        &self,
+        wallet_address: &str,
+        since: DateTime<Utc>,
+        limit: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let collection = self.db.collection::<Transaction>("transactions");
+        let mut filter = doc! {
+            "account_keys.0": wallet_address,
+            "wallet": seen_from(wallet_address),
+            "block_time": { "$gte": bson::to_bson(&since)? }
+        };
+        if commitment == Commitment::Finalized {
+            filter.insert("commitment", Commitment::Finalized.as_str());
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "slot": -1 })
+            .limit(limit)
+            .build();
+        let cursor = collection.find(filter, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    // Most recent transactions touching any of `addresses`
+    pub async fn get_transactions_involving(&self, addresses: &[String], limit: i64) -> Result<Vec<Transaction>> {
+        let collection = self.db.collection::<Transaction>("transactions");
+        let options = FindOptions::builder()
+            .sort(doc! { "slot": -1 })
+            .limit(limit)
+            .build();
+        let cursor = collection
+            .find(doc! { "account_keys": { "$in": addresses } }, options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    // Transactions still at confirmed whose slot is at or below the finalized
+    // slot, so their fate is known. Transactions stored before slots were
+    // recorded (slot 0) are left alone.
+    pub async fn get_unfinalized_transactions(&self, finalized_slot: u64, limit: i64) -> Result<Vec<Transaction>> {
+        let collection = self.db.collection::<Transaction>("transactions");
+        let options = FindOptions::builder()
+            .sort(doc! { "slot": 1 })
+            .limit(limit)
+            .build();
+        let cursor = collection
             .find(
                 doc! {
-                    "$or": [
-                        { "from_address": wallet_address },
-                        { "to_address": wallet_address }
-                    ]
+                    "commitment": { "$ne": Commitment::Finalized.as_str() },
+                    "slot": { "$gt": 0_i64, "$lte": finalized_slot as i64 }
                 },
+                options,
+            )
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn finalize_transaction(&self, signature: &str, slot: u64) -> Result<()> {
+        let collection = self.db.collection::<Transaction>("transactions");
+        collection
+            .update_many(
+                doc! { "signature": signature },
+                doc! { "$set": { "commitment": Commitment::Finalized.as_str(), "slot": slot as i64 } },
                 None,
             )
             .await?;
+        Ok(())
+    }
 
-        let mut transactions = Vec::new();
-        while let Some(transaction) = cursor.try_next().await? {
-            transactions.push(transaction);
+    pub async fn delete_transaction(&self, signature: &str) -> Result<()> {
+        let collection = self.db.collection::<Transaction>("transactions");
+        collection.delete_many(doc! { "signature": signature }, None).await?;
+        Ok(())
+    }
+
+    // Wallets holding a confirmed balance read at or below the finalized slot
+    pub async fn get_wallets_with_unfinalized_balances(&self, finalized_slot: u64) -> Result<Vec<Wallet>> {
+        let collection = self.db.collection::<Wallet>("wallets");
+        let cursor = collection
+            .find(
+                doc! {
+                    "tokens": {
+                        "$elemMatch": {
+                            "commitment": { "$ne": Commitment::Finalized.as_str() },
+                            "slot": { "$gt": 0_i64, "$lte": finalized_slot as i64 }
+                        }
+                    }
+                },
+                None,
+            )
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    // Transactions that changed a balance owned by `address`, oldest first,
+    // with slots in `(after_slot, until_slot]` and block times up to `until_time`
+    pub async fn get_balance_transactions(
+        &self,
+        address: &str,
+        after_slot: Option<u64>,
+        until_slot: Option<u64>,
+        until_time: Option<DateTime<Utc>>,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let collection = self.db.collection::<Transaction>("transactions");
+        let mut slot = doc! { "$gte": 0_i64 };
+        if let Some(after_slot) = after_slot {
+            slot.insert("$gt", after_slot as i64);
         }
-        Ok(transactions)
+        if let Some(until_slot) = until_slot {
+            slot.insert("$lte", until_slot as i64);
+        }
+        let mut filter = doc! { "balance_changes.owner": address, "wallet": seen_from(address), "slot": slot };
+        if let Some(until_time) = until_time {
+            filter.insert("block_time", doc! { "$lte": bson::to_bson(&until_time)? });
+        }
+        if commitment == Commitment::Finalized {
+            filter.insert("commitment", Commitment::Finalized.as_str());
+        }
+        let options = FindOptions::builder().sort(doc! { "slot": 1 }).build();
+        let cursor = collection.find(filter, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn count_balance_transactions(
+        &self,
+        address: &str,
+        until_slot: u64,
+        commitment: Commitment,
+    ) -> Result<u64> {
+        let collection = self.db.collection::<Transaction>("transactions");
+        let mut filter = doc! {
+            "balance_changes.owner": address,
+            "wallet": seen_from(address),
+            "slot": { "$lte": until_slot as i64 }
+        };
+        if commitment == Commitment::Finalized {
+            filter.insert("commitment", Commitment::Finalized.as_str());
+        }
+        Ok(collection.count_documents(filter, None).await?)
+    }
+
+    // Candle Operations
+    // Candles opening in `[start, end)`, oldest first, one per open time
+    pub async fn get_candles(
+        &self,
+        mint: &str,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let collection = self.db.collection::<CandleDocument>(&candle_collection(resolution));
+        let options = FindOptions::builder().sort(doc! { "time": 1 }).build();
+        let cursor = collection
+            .find(
+                doc! {
+                    "mint": mint,
+                    "time": {
+                        "$gte": bson::DateTime::from_millis(start.timestamp_millis()),
+                        "$lt": bson::DateTime::from_millis(end.timestamp_millis())
+                    }
+                },
+                options,
+            )
+            .await?;
+        let documents: Vec<CandleDocument> = cursor.try_collect().await?;
+        let mut candles: Vec<Candle> = documents
+            .into_iter()
+            .map(|document| document.into_candle(resolution))
+            .collect();
+        // Time-series collections have no unique key, so a period inserted
+        // twice comes back twice
+        candles.dedup_by_key(|candle| candle.open_time);
+        Ok(candles)
+    }
+
+    // Time-series collections are append-only, so callers only insert
+    // candles they do not have yet
+    pub async fn insert_candles(&self, resolution: Resolution, candles: &[Candle]) -> Result<()> {
+        if candles.is_empty() {
+            return Ok(());
+        }
+        let collection = self.db.collection::<CandleDocument>(&candle_collection(resolution));
+        collection
+            .insert_many(candles.iter().map(CandleDocument::from_candle), None)
+            .await?;
+        Ok(())
+    }
+
+    // Balance Checkpoint Operations
+    pub async fn get_balance_checkpoints(&self, address: &str) -> Result<Vec<BalanceCheckpoint>> {
+        let collection = self.db.collection::<BalanceCheckpoint>("balance_checkpoints");
+        let options = FindOptions::builder().sort(doc! { "slot": 1 }).build();
+        let cursor = collection.find(doc! { "address": address }, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn save_balance_checkpoint(&self, checkpoint: &BalanceCheckpoint) -> Result<()> {
+        let collection = self.db.collection::<BalanceCheckpoint>("balance_checkpoints");
+        collection
+            .replace_one(
+                doc! { "_id": &checkpoint.id },
+                checkpoint,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_balance_checkpoint(&self, id: &str) -> Result<()> {
+        let collection = self.db.collection::<BalanceCheckpoint>("balance_checkpoints");
+        collection.delete_one(doc! { "_id": id }, None).await?;
+        Ok(())
+    }
+
+    // Backfill Operations
+    pub async fn get_backfill_checkpoint(&self, address: &str) -> Result<Option<BackfillCheckpoint>> {
+        let collection = self.db.collection::<BackfillCheckpoint>("backfill_checkpoints");
+        Ok(collection.find_one(doc! { "_id": address }, None).await?)
+    }
+
+    pub async fn save_backfill_checkpoint(&self, checkpoint: &BackfillCheckpoint) -> Result<()> {
+        let collection = self.db.collection::<BackfillCheckpoint>("backfill_checkpoints");
+        collection
+            .replace_one(
+                doc! { "_id": &checkpoint.address },
+                checkpoint,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_indexer_cursor(&self, id: &str) -> Result<Option<IndexerCursor>> {
+        let collection = self.db.collection::<IndexerCursor>("indexer_cursors");
+        Ok(collection.find_one(doc! { "_id": id }, None).await?)
+    }
+
+    pub async fn save_indexer_cursor(&self, cursor: &IndexerCursor) -> Result<()> {
+        let collection = self.db.collection::<IndexerCursor>("indexer_cursors");
+        collection
+            .replace_one(
+                doc! { "_id": &cursor.id },
+                cursor,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
     }
 }
 
@@ -162,6 +593,8 @@ mod tests {
             address: "test_address".to_string(),
             total_value_usd: 1000.0,
             tokens: vec![],
+            stake_positions: vec![],
+            nfts: vec![],
             risk_score: 0.5,
             created_at: Utc::now(),
             updated_at: Utc::now(),
@@ -189,13 +622,19 @@ mod tests {
             market_cap_usd: 1_000_000_000.0,
             volume_24h: 1_000_000.0,
             price_change_24h: 5.0,
+            mint_authority: None,
+            freeze_authority: None,
+            uri: None,
+            liquidity_usd: None,
+            refreshed_at: None,
         };
 
         // Test save
         db.save_token(&token).await.unwrap();
 
         // Test get
-        let retrieved_token = db.get_token(&token.address).await.unwrap();
+        let retrieved_token = db.get_token(&token.address).await.unwrap().unwrap();
+        assert!(db.get_token("missing_token").await.unwrap().is_none());
         assert_eq!(token.symbol, retrieved_token.symbol);
     }
 }
\ No newline at end of file
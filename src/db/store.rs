@@ -0,0 +1,411 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::db::mongodb::MongoDB;
+use crate::models::{BalanceCheckpoint, Commitment, Token, Transaction, Wallet};
+
+// Transactions read per query when walking a wallet's whole history
+const HISTORY_PAGE_SIZE: i64 = 1_000;
+
+// What the API handlers and the services behind them read and write.
+// `MongoDB` is the real store; `MemoryStore` stands in for it in tests.
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn get_wallet(&self, id: Uuid) -> Result<Wallet>;
+
+    async fn get_wallet_by_address(&self, address: &str) -> Result<Option<Wallet>>;
+
+    async fn save_wallet(&self, wallet: &Wallet) -> Result<()>;
+
+    // None when the token has not been cached
+    async fn get_token(&self, address: &str) -> Result<Option<Token>>;
+
+    // The cached tokens among `addresses`
+    async fn get_tokens(&self, addresses: &[String]) -> Result<Vec<Token>>;
+
+    async fn save_token(&self, token: &Token) -> Result<()>;
+
+    // Oldest first
+    async fn get_wallet_transactions(
+        &self,
+        wallet_address: &str,
+        limit: i64,
+        skip: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>>;
+
+    // Every stored transaction of the wallet, oldest first, read a page at a
+    // time so long histories are not cut short
+    async fn get_all_wallet_transactions(
+        &self,
+        wallet_address: &str,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let mut transactions = Vec::new();
+        loop {
+            let page = self
+                .get_wallet_transactions(wallet_address, HISTORY_PAGE_SIZE, transactions.len() as i64, commitment)
+                .await?;
+            let last_page = (page.len() as i64) < HISTORY_PAGE_SIZE;
+            transactions.extend(page);
+            if last_page {
+                return Ok(transactions);
+            }
+        }
+    }
+
+    // The most recent `limit` transactions of the wallet that moved any of
+    // `mints`, newest first
+    async fn get_mint_transactions(
+        &self,
+        wallet_address: &str,
+        mints: &[String],
+        limit: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>>;
+
+    // The most recent `limit` transactions `wallet_address` paid for, with a
+    // block time at or after `since`, newest first
+    async fn get_paid_transactions(
+        &self,
+        wallet_address: &str,
+        since: DateTime<Utc>,
+        limit: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>>;
+
+    // Most recent transactions touching any of `addresses`
+    async fn get_transactions_involving(&self, addresses: &[String], limit: i64) -> Result<Vec<Transaction>>;
+
+    // Transactions that changed a balance owned by `address`, oldest first,
+    // with slots in `(after_slot, until_slot]` and block times up to `until_time`
+    async fn get_balance_transactions(
+        &self,
+        address: &str,
+        after_slot: Option<u64>,
+        until_slot: Option<u64>,
+        until_time: Option<DateTime<Utc>>,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>>;
+
+    async fn count_balance_transactions(&self, address: &str, until_slot: u64, commitment: Commitment) -> Result<u64>;
+
+    // Oldest first
+    async fn get_balance_checkpoints(&self, address: &str) -> Result<Vec<BalanceCheckpoint>>;
+
+    async fn save_balance_checkpoint(&self, checkpoint: &BalanceCheckpoint) -> Result<()>;
+
+    async fn delete_balance_checkpoint(&self, id: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl Store for MongoDB {
+    async fn get_wallet(&self, id: Uuid) -> Result<Wallet> {
+        MongoDB::get_wallet(self, id).await
+    }
+
+    async fn get_wallet_by_address(&self, address: &str) -> Result<Option<Wallet>> {
+        MongoDB::get_wallet_by_address(self, address).await
+    }
+
+    async fn save_wallet(&self, wallet: &Wallet) -> Result<()> {
+        MongoDB::save_wallet(self, wallet).await
+    }
+
+    async fn get_token(&self, address: &str) -> Result<Option<Token>> {
+        MongoDB::get_token(self, address).await
+    }
+
+    async fn get_tokens(&self, addresses: &[String]) -> Result<Vec<Token>> {
+        MongoDB::get_tokens(self, addresses).await
+    }
+
+    async fn save_token(&self, token: &Token) -> Result<()> {
+        MongoDB::save_token(self, token).await
+    }
+
+    async fn get_wallet_transactions(
+        &self,
+        wallet_address: &str,
+        limit: i64,
+        skip: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        MongoDB::get_wallet_transactions(self, wallet_address, limit, skip, commitment).await
+    }
+
+    async fn get_mint_transactions(
+        &self,
+        wallet_address: &str,
+        mints: &[String],
+        limit: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        MongoDB::get_mint_transactions(self, wallet_address, mints, limit, commitment).await
+    }
+
+    async fn get_paid_transactions(
+        &self,
+        wallet_address: &str,
+        since: DateTime<Utc>,
+        limit: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        MongoDB::get_paid_transactions(self, wallet_address, since, limit, commitment).await
+    }
+
+    async fn get_transactions_involving(&self, addresses: &[String], limit: i64) -> Result<Vec<Transaction>> {
+        MongoDB::get_transactions_involving(self, addresses, limit).await
+    }
+
+    async fn get_balance_transactions(
+        &self,
+        address: &str,
+        after_slot: Option<u64>,
+        until_slot: Option<u64>,
+        until_time: Option<DateTime<Utc>>,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        MongoDB::get_balance_transactions(self, address, after_slot, until_slot, until_time, commitment).await
+    }
+
+    async fn count_balance_transactions(&self, address: &str, until_slot: u64, commitment: Commitment) -> Result<u64> {
+        MongoDB::count_balance_transactions(self, address, until_slot, commitment).await
+    }
+
+    async fn get_balance_checkpoints(&self, address: &str) -> Result<Vec<BalanceCheckpoint>> {
+        MongoDB::get_balance_checkpoints(self, address).await
+    }
+
+    async fn save_balance_checkpoint(&self, checkpoint: &BalanceCheckpoint) -> Result<()> {
+        MongoDB::save_balance_checkpoint(self, checkpoint).await
+    }
+
+    async fn delete_balance_checkpoint(&self, id: &str) -> Result<()> {
+        MongoDB::delete_balance_checkpoint(self, id).await
+    }
+}
+
+// Everything kept in memory, standing in for Mongo in tests. Queries filter
+// and sort the way the Mongo ones do.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    pub wallets: std::sync::Mutex<Vec<Wallet>>,
+    pub tokens: std::sync::Mutex<std::collections::HashMap<String, Token>>,
+    pub transactions: std::sync::Mutex<Vec<Transaction>>,
+    pub checkpoints: std::sync::Mutex<Vec<BalanceCheckpoint>>,
+}
+
+#[cfg(test)]
+impl MemoryStore {
+    fn transactions(&self, filter: impl Fn(&Transaction) -> bool, commitment: Commitment) -> Vec<Transaction> {
+        self.transactions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|transaction| commitment == Commitment::Confirmed || transaction.commitment == commitment)
+            .filter(|transaction| filter(transaction))
+            .cloned()
+            .collect()
+    }
+}
+
+// Records stored before transactions were kept per wallet have no wallet
+#[cfg(test)]
+fn seen_from(transaction: &Transaction, address: &str) -> bool {
+    transaction.wallet == address || transaction.wallet.is_empty()
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Store for MemoryStore {
+    async fn get_wallet(&self, id: Uuid) -> Result<Wallet> {
+        self.wallets
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|wallet| wallet.id == id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Wallet not found"))
+    }
+
+    async fn get_wallet_by_address(&self, address: &str) -> Result<Option<Wallet>> {
+        Ok(self
+            .wallets
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|wallet| wallet.address == address)
+            .cloned())
+    }
+
+    async fn save_wallet(&self, wallet: &Wallet) -> Result<()> {
+        let mut wallets = self.wallets.lock().unwrap();
+        wallets.retain(|stored| stored.id != wallet.id);
+        wallets.push(wallet.clone());
+        Ok(())
+    }
+
+    async fn get_token(&self, address: &str) -> Result<Option<Token>> {
+        Ok(self.tokens.lock().unwrap().get(address).cloned())
+    }
+
+    async fn get_tokens(&self, addresses: &[String]) -> Result<Vec<Token>> {
+        let tokens = self.tokens.lock().unwrap();
+        Ok(addresses
+            .iter()
+            .filter_map(|address| tokens.get(address).cloned())
+            .collect())
+    }
+
+    async fn save_token(&self, token: &Token) -> Result<()> {
+        self.tokens.lock().unwrap().insert(token.address.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn get_wallet_transactions(
+        &self,
+        wallet_address: &str,
+        limit: i64,
+        skip: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let mut transactions = self.transactions(
+            |transaction| {
+                seen_from(transaction, wallet_address)
+                    && (transaction.from_address == wallet_address
+                        || transaction.to_address == wallet_address
+                        || transaction.account_keys.iter().any(|key| key == wallet_address))
+            },
+            commitment,
+        );
+        transactions.sort_by_key(|transaction| (transaction.block_time, transaction.slot));
+        Ok(transactions
+            .into_iter()
+            .skip(skip as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn get_mint_transactions(
+        &self,
+        wallet_address: &str,
+        mints: &[String],
+        limit: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let mut transactions = self.transactions(
+            |transaction| {
+                seen_from(transaction, wallet_address)
+                    && (transaction.from_address == wallet_address
+                        || transaction.to_address == wallet_address
+                        || transaction.account_keys.iter().any(|key| key == wallet_address))
+                    && (transaction
+                        .balance_changes
+                        .iter()
+                        .any(|change| change.mint.as_ref().is_some_and(|mint| mints.contains(mint)))
+                        || transaction
+                            .token_address
+                            .as_ref()
+                            .is_some_and(|mint| mints.contains(mint)))
+            },
+            commitment,
+        );
+        transactions.sort_by_key(|transaction| std::cmp::Reverse(transaction.slot));
+        transactions.truncate(limit as usize);
+        Ok(transactions)
+    }
+
+    async fn get_paid_transactions(
+        &self,
+        wallet_address: &str,
+        since: DateTime<Utc>,
+        limit: i64,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let mut transactions = self.transactions(
+            |transaction| {
+                seen_from(transaction, wallet_address)
+                    && transaction
+                        .account_keys
+                        .first()
+                        .is_some_and(|payer| payer == wallet_address)
+                    && transaction.block_time >= since
+            },
+            commitment,
+        );
+        transactions.sort_by_key(|transaction| std::cmp::Reverse(transaction.slot));
+        transactions.truncate(limit as usize);
+        Ok(transactions)
+    }
+
+    async fn get_transactions_involving(&self, addresses: &[String], limit: i64) -> Result<Vec<Transaction>> {
+        let mut transactions = self.transactions(
+            |transaction| transaction.account_keys.iter().any(|key| addresses.contains(key)),
+            Commitment::Confirmed,
+        );
+        transactions.sort_by_key(|transaction| std::cmp::Reverse(transaction.slot));
+        transactions.truncate(limit as usize);
+        Ok(transactions)
+    }
+
+    async fn get_balance_transactions(
+        &self,
+        address: &str,
+        after_slot: Option<u64>,
+        until_slot: Option<u64>,
+        until_time: Option<DateTime<Utc>>,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let mut transactions = self.transactions(
+            |transaction| {
+                seen_from(transaction, address)
+                    && transaction.balance_changes.iter().any(|change| change.owner == address)
+                    && after_slot.map_or(true, |after_slot| transaction.slot > after_slot)
+                    && until_slot.map_or(true, |until_slot| transaction.slot <= until_slot)
+                    && until_time.map_or(true, |until_time| transaction.block_time <= until_time)
+            },
+            commitment,
+        );
+        transactions.sort_by_key(|transaction| transaction.slot);
+        Ok(transactions)
+    }
+
+    async fn count_balance_transactions(&self, address: &str, until_slot: u64, commitment: Commitment) -> Result<u64> {
+        Ok(self
+            .get_balance_transactions(address, None, Some(until_slot), None, commitment)
+            .await?
+            .len() as u64)
+    }
+
+    async fn get_balance_checkpoints(&self, address: &str) -> Result<Vec<BalanceCheckpoint>> {
+        let mut checkpoints: Vec<BalanceCheckpoint> = self
+            .checkpoints
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|checkpoint| checkpoint.address == address)
+            .cloned()
+            .collect();
+        checkpoints.sort_by_key(|checkpoint| checkpoint.slot);
+        Ok(checkpoints)
+    }
+
+    async fn save_balance_checkpoint(&self, checkpoint: &BalanceCheckpoint) -> Result<()> {
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        checkpoints.retain(|stored| stored.id != checkpoint.id);
+        checkpoints.push(checkpoint.clone());
+        Ok(())
+    }
+
+    async fn delete_balance_checkpoint(&self, id: &str) -> Result<()> {
+        self.checkpoints
+            .lock()
+            .unwrap()
+            .retain(|checkpoint| checkpoint.id != id);
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
 use actix_cors::Cors;
-use actix_web::{middleware, App, HttpServer};
+use actix_web::{middleware, web, App, HttpServer};
 use dotenv::dotenv;
+use services::chain::ChainClient;
+use services::prices::PriceProvider;
+use services::subscriptions::WalletTracker;
+use std::sync::Arc;
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
@@ -28,23 +32,85 @@ async fn main() -> std::io::Result<()> {
     info!("Starting Insight Wallet Analysis Platform...");
 
     // Initialize database connection
-    let db = db::mongodb::init_database().await.expect("Failed to connect to database");
-
-    // Initialize blockchain client
-    let blockchain_client = services::blockchain::SolanaClient::new()
+    let db = Arc::new(
+        db::mongodb::MongoDB::new()
+            .await
+            .expect("Failed to connect to database"),
+    );
+    // Indexes and the candle time-series collections must exist before the
+    // first write, or Mongo creates plain collections in their place
+    db.init_collections()
         .await
-        .expect("Failed to initialize blockchain client");
+        .expect("Failed to initialize database collections");
+
+    // Serve a fixture chain when CHAIN_FIXTURE is set; otherwise connect to
+    // Solana and keep stored wallets in sync with it
+    let (blockchain_client, solana, wallet_tracker): (
+        Arc<dyn ChainClient>,
+        Option<Arc<services::blockchain::SolanaClient>>,
+        Option<Arc<dyn WalletTracker>>,
+    ) = match std::env::var("CHAIN_FIXTURE") {
+        Ok(path) => {
+            info!("Serving chain data from fixture {}", path);
+            let fixture = services::chain::FixtureChain::from_file(&path).expect("Failed to load chain fixture");
+            (Arc::new(fixture), None, None)
+        }
+        Err(_) => {
+            let solana = Arc::new(
+                services::blockchain::SolanaClient::new()
+                    .await
+                    .expect("Failed to initialize blockchain client"),
+            );
+            let wallet_tracker = spawn_solana_sync(solana.clone(), db.clone()).await;
+            (solana.clone(), Some(solana), wallet_tracker)
+        }
+    };
+
+    // Prices come from PRICE_FILE when set, and otherwise from Pyth on a
+    // live cluster
+    let price_provider: Arc<dyn PriceProvider> = match (std::env::var("PRICE_FILE"), solana) {
+        (Ok(path), _) => {
+            info!("Serving prices from {}", path);
+            Arc::new(services::prices::StaticPriceProvider::from_file(&path).expect("Failed to load price file"))
+        }
+        (Err(_), Some(solana)) => {
+            Arc::new(services::prices::PythPriceProvider::from_env(solana).expect("Failed to load Pyth feeds"))
+        }
+        (Err(_), None) => Arc::new(services::prices::StaticPriceProvider::default()),
+    };
+
+    let candle_store = Arc::new(services::candles::CandleStore::new(db.clone(), price_provider.clone()));
 
     // Initialize AI service
-    let ai_service = services::ai_analysis::AIService::new()
-        .await
-        .expect("Failed to initialize AI service");
+    let ai_service = Arc::new(
+        services::ai_analysis::AIService::new(candle_store)
+            .await
+            .expect("Failed to initialize AI service"),
+    );
+
+    let portfolio_service = Arc::new(services::portfolio::PortfolioService::new(db.clone()));
+
+    let protocol_registry =
+        Arc::new(services::programs::ProtocolRegistry::from_env().expect("Failed to load protocol registry"));
+    let profile_service = Arc::new(services::programs::ProgramProfileService::new(db.clone(), protocol_registry));
+    let graph_service = Arc::new(services::graph::CounterpartyGraphService::new(db.clone()));
+    let history_service = Arc::new(services::history::BalanceHistoryService::new(db.clone()));
+    let spam_classifier =
+        Arc::new(services::spam::SpamClassifier::from_env().expect("Failed to load spam denylist"));
+    let spam_filter = Arc::new(services::spam::SpamFilter::new(db.clone(), spam_classifier));
 
     // Create shared application state
     let app_state = web::Data::new(AppState {
         db: db.clone(),
         blockchain_client: blockchain_client.clone(),
+        price_provider: price_provider.clone(),
         ai_service: ai_service.clone(),
+        portfolio_service: portfolio_service.clone(),
+        profile_service: profile_service.clone(),
+        graph_service: graph_service.clone(),
+        history_service: history_service.clone(),
+        spam_filter: spam_filter.clone(),
+        wallet_tracker,
     });
 
     // Start HTTP server
@@ -68,8 +134,65 @@ async fn main() -> std::io::Result<()> {
     .await
 }
 
+// Background work that only makes sense against a live Solana cluster.
+// Returns the handle that adds wallets stored later to the live sync.
+async fn spawn_solana_sync(
+    client: Arc<services::blockchain::SolanaClient>,
+    db: Arc<db::mongodb::MongoDB>,
+) -> Option<Arc<dyn WalletTracker>> {
+    // Replayed RPC traffic has no cluster behind it to stay in sync with
+    let Some(pool) = client.rpc_pool() else {
+        info!("Replaying recorded RPC traffic, live sync disabled");
+        return None;
+    };
+
+    // Track RPC endpoint slot lag so lagging providers are routed around
+    pool.spawn_health_monitor(std::time::Duration::from_secs(10));
+
+    // Keep stored wallets in sync from the PubSub websocket
+    let tracked_wallets = db
+        .get_wallet_addresses()
+        .await
+        .expect("Failed to load tracked wallets");
+    let subscription = client
+        .subscribe(tracked_wallets)
+        .await
+        .expect("Failed to subscribe to wallet updates");
+    tokio::spawn(services::subscriptions::WalletSync::new(client.clone(), db.clone()).run(subscription.updates));
+
+    // Walk blocks for tracked wallets; opt-in since it fetches every block
+    if std::env::var("BLOCK_INDEXER_ENABLED").is_ok() {
+        tokio::spawn({
+            let indexer = services::indexer::BlockIndexer::new(client.clone(), db.clone());
+            async move { indexer.run().await }
+        });
+    }
+
+    // Store the full history of tracked wallets and keep picking up new activity
+    tokio::spawn({
+        let backfill = services::backfill::BackfillService::new(client.clone(), db.clone());
+        async move { backfill.run(std::time::Duration::from_secs(60)).await }
+    });
+
+    // Promote confirmed data once finalized and drop what was rolled back
+    tokio::spawn({
+        let reconciler = services::reconciler::Reconciler::new(client, db);
+        async move { reconciler.run(std::time::Duration::from_secs(30)).await }
+    });
+
+    Some(subscription.tracker)
+}
+
 pub struct AppState {
-    db: mongodb::Database,
-    blockchain_client: Arc<services::blockchain::SolanaClient>,
+    db: Arc<dyn db::store::Store>,
+    blockchain_client: Arc<dyn ChainClient>,
+    price_provider: Arc<dyn PriceProvider>,
     ai_service: Arc<services::ai_analysis::AIService>,
+    portfolio_service: Arc<services::portfolio::PortfolioService>,
+    profile_service: Arc<services::programs::ProgramProfileService>,
+    graph_service: Arc<services::graph::CounterpartyGraphService>,
+    history_service: Arc<services::history::BalanceHistoryService>,
+    spam_filter: Arc<services::spam::SpamFilter>,
+    // Absent when nothing is kept in sync with a live cluster
+    wallet_tracker: Option<Arc<dyn WalletTracker>>,
 }
\ No newline at end of file
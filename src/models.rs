@@ -3,34 +3,114 @@ mod token;
 mod transaction;
 mod wallet;
 
-pub use token::Token;
-pub use transaction::Transaction;
-pub use wallet::Wallet;
+pub use token::{Candle, Resolution, Token};
+pub use transaction::{
+    AccountBalance, BackfillCheckpoint, BalanceChange, BalanceCheckpoint, Commitment, EventKind, FeeBreakdown,
+    IndexerCursor, SwapLeg, Transaction, TransactionEvent, TransactionFailure,
+};
+pub use wallet::{Nft, NftCollection, StakePosition, StakeState, TokenBalance, Wallet};
 
 // src/models/wallet.rs
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use super::Commitment;
+use crate::utils::helpers::format_token_amount;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Wallet {
     #[serde(rename = "_id")]
     pub id: Uuid,
     pub address: String,
     pub total_value_usd: f64,
     pub tokens: Vec<TokenBalance>,
+    #[serde(default)]
+    pub stake_positions: Vec<StakePosition>,
+    #[serde(default)]
+    pub nfts: Vec<Nft>,
     pub risk_score: f32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenBalance {
     pub token_address: String,
     pub amount: f64,
+    pub raw_amount: u64,
+    pub decimals: u8,
+    pub value_usd: f64,
+    // Set for liquid staking tokens: SOL redeemable per token
+    #[serde(default)]
+    pub sol_exchange_rate: Option<f64>,
+    // Newest slot any account behind this balance was read at
+    #[serde(default)]
+    pub slot: u64,
+    #[serde(default)]
+    pub commitment: Commitment,
+}
+
+impl TokenBalance {
+    pub fn new(token_address: String, raw_amount: u64, decimals: u8) -> Self {
+        Self {
+            token_address,
+            amount: format_token_amount(raw_amount, decimals),
+            raw_amount,
+            decimals,
+            value_usd: 0.0,
+            sol_exchange_rate: None,
+            slot: 0,
+            commitment: Commitment::default(),
+        }
+    }
+}
+
+// A native stake account the wallet is staker or withdrawer of
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StakePosition {
+    pub address: String,
+    pub staker: String,
+    pub withdrawer: String,
+    pub state: StakeState,
+    // Vote account the stake is delegated to
+    pub validator: Option<String>,
+    // Account balance, including the rent-exempt reserve and any undelegated lamports
+    pub lamports: u64,
+    pub delegated_lamports: u64,
     pub value_usd: f64,
 }
 
+// A non-fungible token held directly (mint + token account) or, when
+// `compressed`, recorded in a Bubblegum merkle tree and read from an indexer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Nft {
+    // Mint address, or asset id for compressed NFTs
+    pub mint: String,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    // Verified collection only; unverified collection claims are ignored
+    pub collection: Option<String>,
+    pub compressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NftCollection {
+    pub collection: Option<String>,
+    pub nfts: Vec<Nft>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StakeState {
+    // Initialized but never delegated
+    Initialized,
+    Activating,
+    Active,
+    Deactivating,
+    Inactive,
+}
+
 impl Wallet {
     pub fn new(address: String) -> Self {
         Self {
@@ -38,17 +118,29 @@ impl Wallet {
             address,
             total_value_usd: 0.0,
             tokens: Vec::new(),
+            stake_positions: Vec::new(),
+            nfts: Vec::new(),
             risk_score: 0.0,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
     }
+
+    pub fn staked_value_usd(&self) -> f64 {
+        self.stake_positions.iter().map(|position| position.value_usd).sum()
+    }
+
+    pub fn update_total_value(&mut self) {
+        self.total_value_usd =
+            self.tokens.iter().map(|token| token.value_usd).sum::<f64>() + self.staked_value_usd();
+    }
 }
 
 // src/models/token.rs
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Token {
     #[serde(rename = "_id")]
     pub address: String,
@@ -60,20 +152,387 @@ pub struct Token {
     pub market_cap_usd: f64,
     pub volume_24h: f64,
     pub price_change_24h: f64,
+    #[serde(default)]
+    pub mint_authority: Option<String>,
+    #[serde(default)]
+    pub freeze_authority: Option<String>,
+    #[serde(default)]
+    pub uri: Option<String>,
+    // Pooled liquidity in USD as reported by a market data source; None
+    // until a source has reported on the token
+    #[serde(default)]
+    pub liquidity_usd: Option<f64>,
+    // When supply, authorities and metadata were last read from chain; None
+    // for tokens cached before this was recorded
+    #[serde(default)]
+    pub refreshed_at: Option<DateTime<Utc>>,
+}
+
+// Candle width. Each resolution is rolled up from the next finer one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Resolution {
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl Resolution {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::Minute => "1m",
+            Resolution::Hour => "1h",
+            Resolution::Day => "1d",
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Resolution::Minute => 60,
+            Resolution::Hour => 3_600,
+            Resolution::Day => 86_400,
+        }
+    }
+
+    // Open time of the candle `time` falls in
+    pub fn floor(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let timestamp = time.timestamp();
+        DateTime::from_timestamp(timestamp - timestamp.rem_euclid(self.seconds()), 0).unwrap_or_default()
+    }
+
+    pub fn finer(&self) -> Option<Resolution> {
+        match self {
+            Resolution::Minute => None,
+            Resolution::Hour => Some(Resolution::Minute),
+            Resolution::Day => Some(Resolution::Hour),
+        }
+    }
+}
+
+// Price action of a token over the period starting at `open_time`, in USD
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub mint: String,
+    pub resolution: Resolution,
+    pub open_time: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
 }
 
 // src/models/transaction.rs
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Transaction {
     pub signature: String,
+    #[serde(default)]
+    pub slot: u64,
     pub block_time: DateTime<Utc>,
     pub success: bool,
+    // Set when `success` is false
+    #[serde(default)]
+    pub failure: Option<TransactionFailure>,
     pub from_address: String,
     pub to_address: String,
     pub amount: f64,
     pub token_address: Option<String>,
+    // Total network fee in lamports; `fees` breaks it down
     pub fee: u64,
+    #[serde(default)]
+    pub fees: FeeBreakdown,
+    #[serde(default)]
+    pub account_keys: Vec<String>,
+    // Accounts the transaction write-locked; priority fees are set per
+    // writable account
+    #[serde(default)]
+    pub writable_accounts: Vec<String>,
+    #[serde(default)]
+    pub events: Vec<TransactionEvent>,
+    // Every SOL and token balance the transaction changed
+    #[serde(default)]
+    pub balance_changes: Vec<BalanceChange>,
+    #[serde(default)]
+    pub commitment: Commitment,
+    // Wallet the amounts and addresses are seen from. A transaction touching
+    // several tracked wallets is stored once per wallet.
+    #[serde(default)]
+    pub wallet: String,
+}
+
+#[cfg(test)]
+impl Transaction {
+    // A successful transaction `payer` signed and paid for at `slot`, for
+    // tests to fill in
+    pub fn paid_by(slot: u64, payer: &str) -> Self {
+        Transaction {
+            signature: format!("sig{}", slot),
+            slot,
+            block_time: crate::utils::helpers::from_unix_timestamp(1_700_000_000 + slot as i64),
+            success: true,
+            from_address: payer.to_string(),
+            fee: 5000,
+            account_keys: vec![payer.to_string()],
+            wallet: payer.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+// Why a transaction failed. Instruction errors carry the failing
+// instruction and its program; custom error codes are named from the
+// program's error table when it is known.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionFailure {
+    pub instruction_index: Option<usize>,
+    pub program_id: Option<String>,
+    // The runtime's error as reported, e.g. `InstructionError(2, Custom(1))`
+    pub error: String,
+    pub code: Option<u32>,
+    pub name: Option<String>,
+    pub message: Option<String>,
+    // Log lines emitted by the failing instruction
+    pub logs: Vec<String>,
+}
+
+// Where a transaction's fee went. Base and priority fee add up to `fee`;
+// a Jito tip is paid by transfer and comes on top of it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeBreakdown {
+    pub base_fee: u64,
+    pub priority_fee: u64,
+    // Micro-lamports per compute unit, as set with ComputeBudget
+    pub compute_unit_price: u64,
+    pub compute_units_requested: u64,
+    pub compute_units_consumed: Option<u64>,
+    pub jito_tip: u64,
+}
+
+// An account balance before and after a transaction, in raw units. SOL
+// balances have no mint and are owned by the account itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BalanceChange {
+    pub account: String,
+    pub owner: String,
+    pub mint: Option<String>,
+    pub decimals: u8,
+    pub pre: u64,
+    pub post: u64,
+}
+
+// Confirmation level data was read at. Confirmed data can still be rolled
+// back if its slot ends up skipped; the reconciler promotes it to finalized
+// once the slot is rooted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Commitment {
+    #[default]
+    Confirmed,
+    Finalized,
+}
+
+impl Commitment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Commitment::Confirmed => "confirmed",
+            Commitment::Finalized => "finalized",
+        }
+    }
+}
+
+impl std::str::FromStr for Commitment {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "confirmed" => Ok(Commitment::Confirmed),
+            "finalized" => Ok(Commitment::Finalized),
+            other => Err(anyhow::anyhow!("Unsupported commitment {:?}; expected confirmed or finalized", other)),
+        }
+    }
+}
+
+// A single decoded instruction. Inner (CPI) instructions carry the index of
+// the top-level instruction that invoked them plus their own position.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionEvent {
+    pub program_id: String,
+    pub instruction_index: usize,
+    pub inner_index: Option<usize>,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    SolTransfer {
+        from: String,
+        to: String,
+        lamports: u64,
+    },
+    TokenTransfer {
+        source: String,
+        destination: String,
+        authority: String,
+        mint: Option<String>,
+        amount: u64,
+        decimals: Option<u8>,
+        // Wallet owning the destination token account, when the status meta
+        // recorded it
+        #[serde(default)]
+        destination_owner: Option<String>,
+    },
+    TokenMint {
+        mint: String,
+        destination: String,
+        authority: String,
+        amount: u64,
+    },
+    TokenBurn {
+        account: String,
+        mint: String,
+        authority: String,
+        amount: u64,
+    },
+    TokenAccountClosed {
+        account: String,
+        destination: String,
+        authority: String,
+    },
+    TokenAccountCreated {
+        payer: String,
+        account: String,
+        owner: String,
+        mint: String,
+    },
+    // Leg amounts are what actually moved in the swap's inner token
+    // transfers, not the quoted amounts in the instruction data
+    Swap {
+        dex: String,
+        trader: String,
+        pool: Option<String>,
+        // Pools swapped through in order; aggregators list every hop
+        route: Vec<String>,
+        input: SwapLeg,
+        output: SwapLeg,
+        // Output tokens received per input token, in UI units
+        price: Option<f64>,
+    },
+    ComputeUnitLimit {
+        units: u32,
+    },
+    ComputeUnitPrice {
+        micro_lamports: u64,
+    },
+    Unknown {
+        data_len: usize,
+    },
+}
+
+// One side of a swap. Mint and decimals stay unset when the trader's token
+// account does not appear in the transaction's token balances.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SwapLeg {
+    pub account: String,
+    pub mint: Option<String>,
+    pub decimals: Option<u8>,
+    pub amount: u64,
+}
+
+// Progress of a wallet's signature history backfill
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillCheckpoint {
+    #[serde(rename = "_id")]
+    pub address: String,
+    // Newest signature stored; later runs only fetch history after it
+    pub newest_signature: Option<String>,
+    // Oldest signature stored; the backwards walk resumes before it
+    pub oldest_signature: Option<String>,
+    pub complete: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl BackfillCheckpoint {
+    pub fn new(address: String) -> Self {
+        Self {
+            address,
+            newest_signature: None,
+            oldest_signature: None,
+            complete: false,
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+// A wallet's account balances as of `slot`, so reconstructing later
+// balances only replays the transactions after it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceCheckpoint {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub address: String,
+    pub slot: u64,
+    pub block_time: DateTime<Utc>,
+    // Stored balance-changing transactions at or below `slot` when taken; a
+    // different count later means history was backfilled or rolled back
+    pub transactions: u64,
+    pub accounts: Vec<AccountBalance>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl BalanceCheckpoint {
+    pub fn new(
+        address: String,
+        slot: u64,
+        block_time: DateTime<Utc>,
+        transactions: u64,
+        accounts: Vec<AccountBalance>,
+    ) -> Self {
+        Self {
+            id: format!("{}:{}", address, slot),
+            address,
+            slot,
+            block_time,
+            transactions,
+            accounts,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+// Last known balance of one of a wallet's accounts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountBalance {
+    pub account: String,
+    // Unset for the wallet's SOL balance
+    pub mint: Option<String>,
+    pub decimals: u8,
+    pub amount: u64,
+    // Slot of the transaction that last changed it
+    pub slot: u64,
+}
+
+// How far the block indexer has walked the chain
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexerCursor {
+    #[serde(rename = "_id")]
+    pub id: String,
+    // Last slot whose block has been matched and stored
+    pub last_slot: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl IndexerCursor {
+    pub fn new(id: String, last_slot: u64) -> Self {
+        Self {
+            id,
+            last_slot,
+            updated_at: Utc::now(),
+        }
+    }
 }
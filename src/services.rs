@@ -1,7 +1,20 @@
 // src/services/mod.rs
 pub mod ai_analysis;
+pub mod backfill;
 pub mod blockchain;
+pub mod candles;
+pub mod chain;
+pub mod diagnostics;
+pub mod fees;
+pub mod graph;
+pub mod history;
+pub mod indexer;
 pub mod portfolio;
+pub mod prices;
+pub mod programs;
+pub mod reconciler;
+pub mod spam;
+pub mod subscriptions;
 
 // src/services/ai_analysis.rs
 use anyhow::Result;
@@ -40,66 +53,3 @@ impl AIService {
         })
     }
 }
-
-// src/services/blockchain.rs
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::commitment_config::CommitmentConfig;
-use anyhow::Result;
-
-pub struct SolanaClient {
-    client: RpcClient,
-}
-
-impl SolanaClient {
-    pub async fn new() -> Result<Self> {
-        let rpc_url = std::env::var("SOLANA_RPC_URL")?;
-        let client = RpcClient::new_with_commitment(
-            rpc_url,
-            CommitmentConfig::confirmed(),
-        );
-        
-        Ok(Self { client })
-    }
-
-    pub async fn get_wallet_tokens(&self, address: &str) -> Result<Vec<TokenBalance>> {
-        // Implement token balance fetching logic
-        Ok(Vec::new())
-    }
-
-    pub async fn get_transactions(&self, address: &str) -> Result<Vec<Transaction>> {
-        // Implement transaction history fetching logic
-        Ok(Vec::new())
-    }
-}
-
-// src/services/portfolio.rs
-use crate::models::Wallet;
-use anyhow::Result;
-
-pub struct PortfolioService {
-    db: mongodb::Database,
-}
-
-impl PortfolioService {
-    pub fn new(db: mongodb::Database) -> Self {
-        Self { db }
-    }
-
-    pub async fn optimize_portfolio(&self, wallet: &Wallet) -> Result<PortfolioRecommendation> {
-        // Implement portfolio optimization logic
-        Ok(PortfolioRecommendation {
-            suggested_allocations: Vec::new(),
-            expected_return: 0.0,
-            risk_reduction: 0.0,
-        })
-    }
-
-    pub async fn calculate_metrics(&self, wallet: &Wallet) -> Result<PortfolioMetrics> {
-        // Implement portfolio metrics calculation
-        Ok(PortfolioMetrics {
-            total_value: 0.0,
-            daily_change: 0.0,
-            risk_level: 0.0,
-        })
-    }
-}
\ No newline at end of file
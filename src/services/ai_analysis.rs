@@ -1,8 +1,13 @@
 use anyhow::Result;
 use rust_bert::pipelines::sequence_classification::SequenceClassificationModel;
-use crate::models::{Token, Wallet};
+use crate::models::{Resolution, StakePosition, Token, TokenBalance, Wallet};
+use crate::services::blockchain::NATIVE_MINT;
+use crate::services::candles::CandleStore;
+use crate::services::programs::ProfileFeatures;
+use crate::services::spam::SuspiciousAsset;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WalletAnalysis {
@@ -10,8 +15,19 @@ pub struct WalletAnalysis {
     pub diversity_score: f64,
     pub recommendations: Vec<String>,
     pub token_insights: HashMap<String, TokenInsight>,
+    pub staked_value_usd: f64,
+    pub liquid_staked_value_usd: f64,
+    pub stake_positions: Vec<StakePosition>,
+    pub protocol_features: ProfileFeatures,
+    // Holdings flagged as spam, left out of every figure above
+    pub suspicious_assets: Vec<SuspiciousAsset>,
 }
 
+// Share of the risk score taken by protocol activity when there is any
+const PROTOCOL_RISK_WEIGHT: f64 = 0.3;
+// Hourly candles read for forecasts and technical indicators
+const HISTORY_DAYS: i64 = 30;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenAnalysis {
     pub sentiment_score: f64,
@@ -25,6 +41,8 @@ pub struct TokenInsight {
     pub risk_level: RiskLevel,
     pub concentration: f64,
     pub suggested_action: Action,
+    // Liquid staking tokens track SOL and are scored as SOL exposure
+    pub staking_exposure: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,7 +62,7 @@ pub enum Action {
     IncreasePosition,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PricePrediction {
     pub price_24h: f64,
     pub price_7d: f64,
@@ -83,7 +101,36 @@ pub struct MovingAverages {
 
 pub struct AIService {
     model: SequenceClassificationModel,
-    historical_data: HashMap<String, Vec<HistoricalDataPoint>>,
+    candle_store: Arc<CandleStore>,
+}
+
+// USD value per underlying asset. Native stake and liquid staking tokens are
+// SOL exposure, so they are merged with the SOL balance rather than treated
+// as separate volatile assets.
+fn asset_exposures(wallet: &Wallet) -> HashMap<String, f64> {
+    let mut exposures: HashMap<String, f64> = HashMap::new();
+    for token in &wallet.tokens {
+        let asset = if token.sol_exchange_rate.is_some() {
+            NATIVE_MINT.to_string()
+        } else {
+            token.token_address.clone()
+        };
+        *exposures.entry(asset).or_default() += token.value_usd;
+    }
+
+    let staked = wallet.staked_value_usd();
+    if staked > 0.0 {
+        *exposures.entry(NATIVE_MINT.to_string()).or_default() += staked;
+    }
+    exposures
+}
+
+fn blend_protocol_risk(holdings_risk: f64, protocol_features: &ProfileFeatures) -> f64 {
+    if protocol_features == &ProfileFeatures::default() {
+        return holdings_risk;
+    }
+    let protocol_risk = protocol_features.protocol_risk.max(protocol_features.unknown_call_share);
+    (holdings_risk * (1.0 - PROTOCOL_RISK_WEIGHT) + protocol_risk * PROTOCOL_RISK_WEIGHT).min(1.0)
 }
 
 struct HistoricalDataPoint {
@@ -93,15 +140,21 @@ struct HistoricalDataPoint {
 }
 
 impl AIService {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(candle_store: Arc<CandleStore>) -> Result<Self> {
         let model = SequenceClassificationModel::new(Default::default())?;
         Ok(Self { 
             model,
-            historical_data: HashMap::new(),
+            candle_store,
         })
     }
 
-    pub async fn analyze_wallet(&self, wallet: &Wallet) -> Result<WalletAnalysis> {
+    // `wallet` should already have its suspicious assets removed
+    pub async fn analyze_wallet(
+        &self,
+        wallet: &Wallet,
+        protocol_features: &ProfileFeatures,
+        suspicious_assets: Vec<SuspiciousAsset>,
+    ) -> Result<WalletAnalysis> {
         let mut token_insights = HashMap::new();
         let mut total_value = 0.0;
         
@@ -112,16 +165,35 @@ impl AIService {
             token_insights.insert(token_balance.token_address.clone(), insight);
         }
 
+        // Natively staked SOL is part of the portfolio even though it is not a token balance
+        total_value += wallet.staked_value_usd();
+
         // Calculate portfolio metrics
-        let risk_score = self.calculate_risk_score(wallet).await?;
+        let risk_score = self.calculate_risk_score(wallet, protocol_features).await?;
         let diversity_score = self.calculate_diversity_score(wallet, total_value).await?;
-        let recommendations = self.generate_recommendations(wallet, &token_insights).await?;
+        let mut recommendations = self.generate_recommendations(wallet, &token_insights).await?;
+        if !suspicious_assets.is_empty() {
+            recommendations.push(format!(
+                "{} suspicious tokens were excluded. Do not follow links in their metadata or interact with them.",
+                suspicious_assets.len()
+            ));
+        }
 
         Ok(WalletAnalysis {
             risk_score,
             diversity_score,
             recommendations,
             token_insights,
+            staked_value_usd: wallet.staked_value_usd(),
+            liquid_staked_value_usd: wallet
+                .tokens
+                .iter()
+                .filter(|token| token.sol_exchange_rate.is_some())
+                .map(|token| token.value_usd)
+                .sum(),
+            stake_positions: wallet.stake_positions.clone(),
+            protocol_features: protocol_features.clone(),
+            suspicious_assets,
         })
     }
 
@@ -134,27 +206,31 @@ impl AIService {
             risk_level,
             concentration,
             suggested_action,
+            staking_exposure: token_balance.sol_exchange_rate.is_some(),
         })
     }
 
-    async fn calculate_risk_score(&self, wallet: &Wallet) -> Result<f64> {
+    // Holdings risk, blended with how risky the protocols the wallet uses are
+    // once it has any program activity
+    async fn calculate_risk_score(&self, wallet: &Wallet, protocol_features: &ProfileFeatures) -> Result<f64> {
         let mut risk_score = 0.0;
-        let total_value = wallet.tokens.iter().map(|t| t.value_usd).sum::<f64>();
+        let exposures = asset_exposures(wallet);
+        let total_value = exposures.values().sum::<f64>();
 
-        for token in &wallet.tokens {
-            let concentration = token.value_usd / total_value;
-            let token_volatility = self.calculate_token_volatility(&token.token_address).await?;
+        for (asset, value_usd) in &exposures {
+            let concentration = value_usd / total_value;
+            let token_volatility = self.calculate_token_volatility(asset).await?;
             risk_score += concentration * token_volatility;
         }
 
-        Ok(risk_score.min(1.0))
+        Ok(blend_protocol_risk(risk_score.min(1.0), protocol_features))
     }
 
     async fn calculate_diversity_score(&self, wallet: &Wallet, total_value: f64) -> Result<f64> {
         let mut herfindahl_index = 0.0;
 
-        for token in &wallet.tokens {
-            let weight = token.value_usd / total_value;
+        for value_usd in asset_exposures(wallet).values() {
+            let weight = value_usd / total_value;
             herfindahl_index += weight * weight;
         }
 
@@ -193,10 +269,11 @@ impl AIService {
     }
 
     pub async fn analyze_token(&self, token: &Token) -> Result<TokenAnalysis> {
+        let historical_data = self.get_historical_data(&token.address).await?;
         let sentiment_score = self.calculate_sentiment_score(token).await?;
-        let price_prediction = self.predict_token_price(token).await?;
+        let price_prediction = self.predict_token_price(&historical_data)?;
         let market_sentiment = self.analyze_market_sentiment(token).await?;
-        let technical_indicators = self.calculate_technical_indicators(token).await?;
+        let technical_indicators = self.calculate_technical_indicators(&historical_data)?;
 
         Ok(TokenAnalysis {
             sentiment_score,
@@ -218,13 +295,11 @@ impl AIService {
             .min(1.0))
     }
 
-    async fn predict_token_price(&self, token: &Token) -> Result<PricePrediction> {
-        let historical_data = self.get_historical_data(&token.address).await?;
-        
+    fn predict_token_price(&self, historical_data: &[HistoricalDataPoint]) -> Result<PricePrediction> {
         // Use time series analysis for predictions
-        let (price_24h, conf_24h) = self.forecast_price(&historical_data, 24)?;
-        let (price_7d, conf_7d) = self.forecast_price(&historical_data, 168)?;
-        let (price_30d, conf_30d) = self.forecast_price(&historical_data, 720)?;
+        let (price_24h, conf_24h) = self.forecast_price(historical_data, 24)?;
+        let (price_7d, conf_7d) = self.forecast_price(historical_data, 168)?;
+        let (price_30d, conf_30d) = self.forecast_price(historical_data, 720)?;
 
         Ok(PricePrediction {
             price_24h,
@@ -249,12 +324,10 @@ impl AIService {
         })
     }
 
-    async fn calculate_technical_indicators(&self, token: &Token) -> Result<TechnicalIndicators> {
-        let historical_data = self.get_historical_data(&token.address).await?;
-        
-        let rsi = self.calculate_rsi(&historical_data)?;
-        let macd = self.calculate_macd(&historical_data)?;
-        let moving_averages = self.calculate_moving_averages(&historical_data)?;
+    fn calculate_technical_indicators(&self, historical_data: &[HistoricalDataPoint]) -> Result<TechnicalIndicators> {
+        let rsi = self.calculate_rsi(historical_data)?;
+        let macd = self.calculate_macd(historical_data)?;
+        let moving_averages = self.calculate_moving_averages(historical_data)?;
 
         Ok(TechnicalIndicators {
             rsi,
@@ -263,6 +336,20 @@ impl AIService {
         })
     }
 
+    async fn get_historical_data(&self, mint: &str) -> Result<Vec<HistoricalDataPoint>> {
+        let end = chrono::Utc::now();
+        let start = end - chrono::Duration::days(HISTORY_DAYS);
+        let candles = self.candle_store.candles(mint, Resolution::Hour, start, end).await?;
+        Ok(candles
+            .into_iter()
+            .map(|candle| HistoricalDataPoint {
+                timestamp: candle.open_time,
+                price: candle.close,
+                volume: candle.volume,
+            })
+            .collect())
+    }
+
     // Helper methods for technical analysis
     fn calculate_rsi(&self, data: &[HistoricalDataPoint]) -> Result<f64> {
         if data.len() < 14 {
@@ -298,7 +385,7 @@ impl AIService {
         let ema_12 = self.calculate_ema(data, 12)?;
         let ema_26 = self.calculate_ema(data, 26)?;
         let macd_value = ema_12 - ema_26;
-        let signal = self.calculate_ema(&data[14..], 9)?;
+        let signal = self.calculate_ema(data.get(14..).unwrap_or_default(), 9)?;
         let histogram = macd_value - signal;
 
         Ok(MACD {
@@ -344,11 +431,21 @@ impl AIService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::candles::MemoryCandles;
+    use crate::services::prices::StaticPriceProvider;
     use chrono::Utc;
 
+    async fn service() -> AIService {
+        let candle_store = Arc::new(CandleStore::new(
+            Arc::new(MemoryCandles::default()),
+            Arc::new(StaticPriceProvider::default()),
+        ));
+        AIService::new(candle_store).await.unwrap()
+    }
+
     #[tokio::test]
     async fn test_wallet_analysis() {
-        let service = AIService::new().await.unwrap();
+        let service = service().await;
         let wallet = Wallet {
             id: uuid::Uuid::new_v4(),
             address: "test_wallet".to_string(),
@@ -357,28 +454,83 @@ mod tests {
                 TokenBalance {
                     token_address: "token1".to_string(),
                     amount: 100.0,
+                    raw_amount: 100_000_000_000,
+                    decimals: 9,
                     value_usd: 500.0,
+                    sol_exchange_rate: None,
+                    slot: 0,
+                    commitment: Default::default(),
                 },
                 TokenBalance {
                     token_address: "token2".to_string(),
                     amount: 200.0,
+                    raw_amount: 200_000_000,
+                    decimals: 6,
                     value_usd: 500.0,
+                    sol_exchange_rate: None,
+                    slot: 0,
+                    commitment: Default::default(),
                 },
             ],
+            stake_positions: vec![],
+            nfts: vec![],
             risk_score: 0.0,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
 
-        let analysis = service.analyze_wallet(&wallet).await.unwrap();
+        let analysis = service
+            .analyze_wallet(&wallet, &ProfileFeatures::default(), Vec::new())
+            .await
+            .unwrap();
         assert!(analysis.risk_score >= 0.0 && analysis.risk_score <= 1.0);
         assert!(analysis.diversity_score >= 0.0 && analysis.diversity_score <= 1.0);
         assert!(!analysis.recommendations.is_empty());
     }
 
+    #[test]
+    fn test_staking_counts_as_sol_exposure() {
+        let mut wallet = Wallet::new("test_wallet".to_string());
+        let mut sol = TokenBalance::new(NATIVE_MINT.to_string(), 1_000_000_000, 9);
+        sol.value_usd = 150.0;
+        wallet.tokens.push(sol);
+        wallet.stake_positions.push(StakePosition {
+            address: "stake_account".to_string(),
+            staker: "test_wallet".to_string(),
+            withdrawer: "test_wallet".to_string(),
+            state: crate::models::StakeState::Active,
+            validator: Some("vote_account".to_string()),
+            lamports: 10_000_000_000,
+            delegated_lamports: 9_997_717_120,
+            value_usd: 1500.0,
+        });
+
+        let mut jito_sol = TokenBalance::new("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn".to_string(), 1_000_000_000, 9);
+        jito_sol.value_usd = 172.5;
+        jito_sol.sol_exchange_rate = Some(1.15);
+        wallet.tokens.push(jito_sol);
+
+        let exposures = asset_exposures(&wallet);
+        assert_eq!(exposures.len(), 1);
+        assert_eq!(exposures[&NATIVE_MINT.to_string()], 1822.5);
+    }
+
+    #[test]
+    fn test_protocol_activity_blends_into_risk() {
+        assert_eq!(blend_protocol_risk(0.4, &ProfileFeatures::default()), 0.4);
+
+        let perps_trader = ProfileFeatures {
+            protocol_count: 2,
+            unknown_call_share: 0.1,
+            protocol_risk: 0.8,
+            active_days: 30,
+        };
+        assert!((blend_protocol_risk(0.4, &perps_trader) - (0.4 * 0.7 + 0.8 * 0.3)).abs() < 1e-9);
+    }
+
     #[tokio::test]
     async fn test_token_analysis() {
-        let service = AIService::new().await.unwrap();
+        let service = service().await;
         let token = Token {
             address: "test_token".to_string(),
             symbol: "TEST".to_string(),
@@ -389,6 +541,11 @@ mod tests {
             market_cap_usd: 1_000_000.0,
             volume_24h: 100_000.0,
             price_change_24h: 5.0,
+            mint_authority: None,
+            freeze_authority: None,
+            uri: None,
+            liquidity_usd: None,
+            refreshed_at: None,
         };
 
         let analysis = service.analyze_token(&token).await.unwrap();
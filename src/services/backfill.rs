@@ -0,0 +1,377 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_transaction_status::TransactionConfirmationStatus;
+use tracing::{info, warn};
+
+use crate::db::mongodb::MongoDB;
+use crate::models::{BackfillCheckpoint, Commitment, Transaction};
+use crate::services::blockchain::{SolanaClient, SIGNATURE_PAGE_LIMIT};
+
+// Where the backfill reads history from. `SolanaClient` is the production
+// implementation.
+#[async_trait]
+pub trait SignatureHistory: Send + Sync {
+    // One page of signatures, newest first. `before` and `until` are exclusive cursors.
+    async fn signatures_page(
+        &self,
+        address: &str,
+        before: Option<&str>,
+        until: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>>;
+
+    // Transactions in input order, seen from `address`
+    async fn transactions(&self, signatures: &[String], address: &str) -> Result<Vec<Transaction>>;
+}
+
+#[async_trait]
+impl SignatureHistory for SolanaClient {
+    async fn signatures_page(
+        &self,
+        address: &str,
+        before: Option<&str>,
+        until: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.get_signatures_page(address, before, until, limit, self.commitment())
+            .await
+    }
+
+    async fn transactions(&self, signatures: &[String], address: &str) -> Result<Vec<Transaction>> {
+        self.get_transactions_batch(signatures, address, self.commitment())
+            .await
+    }
+}
+
+// Where the backfill keeps transactions and its progress. `MongoDB` is the
+// production implementation.
+#[async_trait]
+pub trait BackfillStore: Send + Sync {
+    // The wallets to backfill
+    async fn get_wallet_addresses(&self) -> Result<Vec<String>>;
+
+    async fn get_backfill_checkpoint(&self, address: &str) -> Result<Option<BackfillCheckpoint>>;
+
+    async fn save_backfill_checkpoint(&self, checkpoint: &BackfillCheckpoint) -> Result<()>;
+
+    async fn save_transaction(&self, transaction: &Transaction) -> Result<()>;
+}
+
+#[async_trait]
+impl BackfillStore for MongoDB {
+    async fn get_wallet_addresses(&self) -> Result<Vec<String>> {
+        MongoDB::get_wallet_addresses(self).await
+    }
+
+    async fn get_backfill_checkpoint(&self, address: &str) -> Result<Option<BackfillCheckpoint>> {
+        MongoDB::get_backfill_checkpoint(self, address).await
+    }
+
+    async fn save_backfill_checkpoint(&self, checkpoint: &BackfillCheckpoint) -> Result<()> {
+        MongoDB::save_backfill_checkpoint(self, checkpoint).await
+    }
+
+    async fn save_transaction(&self, transaction: &Transaction) -> Result<()> {
+        MongoDB::save_transaction(self, transaction).await
+    }
+}
+
+// Walks a wallet's full signature history and stores every transaction.
+//
+// The first run pages backwards from the chain tip with `before` cursors until
+// history is exhausted. Later runs first pick up anything newer than the last
+// stored signature (using `until`), then resume the backwards walk if it was
+// interrupted. Progress is checkpointed after every page.
+pub struct BackfillService {
+    history: Arc<dyn SignatureHistory>,
+    store: Arc<dyn BackfillStore>,
+    page_limit: usize,
+}
+
+impl BackfillService {
+    pub fn new(history: Arc<dyn SignatureHistory>, store: Arc<dyn BackfillStore>) -> Self {
+        Self {
+            history,
+            store,
+            page_limit: SIGNATURE_PAGE_LIMIT,
+        }
+    }
+
+    // Backfills every stored wallet, then goes round again every `interval`
+    // to pick up their new activity
+    pub async fn run(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let addresses = match self.store.get_wallet_addresses().await {
+                Ok(addresses) => addresses,
+                Err(e) => {
+                    warn!("Failed to load wallets to backfill: {}", e);
+                    continue;
+                }
+            };
+            for address in addresses {
+                if let Err(e) = self.backfill_wallet(&address).await {
+                    warn!("Backfill for {} failed: {}", address, e);
+                }
+            }
+        }
+    }
+
+    // Returns the number of transactions written during this run
+    pub async fn backfill_wallet(&self, address: &str) -> Result<usize> {
+        let mut checkpoint = self
+            .store
+            .get_backfill_checkpoint(address)
+            .await?
+            .unwrap_or_else(|| BackfillCheckpoint::new(address.to_string()));
+
+        let mut stored = self.catch_up(address, &mut checkpoint).await?;
+
+        while !checkpoint.complete {
+            let page = self
+                .history
+                .signatures_page(address, checkpoint.oldest_signature.as_deref(), None, self.page_limit)
+                .await?;
+
+            if checkpoint.newest_signature.is_none() {
+                checkpoint.newest_signature = page.first().map(|s| s.signature.clone());
+            }
+
+            self.store_page(address, &page).await?;
+            stored += page.len();
+
+            if let Some(last) = page.last() {
+                checkpoint.oldest_signature = Some(last.signature.clone());
+            }
+            checkpoint.complete = page.len() < self.page_limit;
+            self.save_checkpoint(&mut checkpoint).await?;
+
+            info!(
+                "Backfill for {}: {} transactions stored, complete: {}",
+                address, stored, checkpoint.complete
+            );
+        }
+
+        Ok(stored)
+    }
+
+    // Fetches signatures newer than the checkpoint's newest one. A wallet
+    // whose history was empty has no newest signature, so everything from
+    // the tip down is new.
+    async fn catch_up(&self, address: &str, checkpoint: &mut BackfillCheckpoint) -> Result<usize> {
+        // Before the first backwards walk finishes, that walk covers the tip
+        if checkpoint.newest_signature.is_none() && !checkpoint.complete {
+            return Ok(0);
+        }
+        let until = checkpoint.newest_signature.clone();
+
+        let mut stored = 0;
+        let mut before: Option<String> = None;
+        let mut newest: Option<String> = None;
+
+        loop {
+            let page = self
+                .history
+                .signatures_page(address, before.as_deref(), until.as_deref(), self.page_limit)
+                .await?;
+
+            if newest.is_none() {
+                newest = page.first().map(|s| s.signature.clone());
+            }
+
+            self.store_page(address, &page).await?;
+            stored += page.len();
+
+            if page.len() < self.page_limit {
+                break;
+            }
+            before = page.last().map(|s| s.signature.clone());
+        }
+
+        // Only advance the cursor once the whole gap is stored, so an
+        // interrupted catch-up is simply repeated on the next run
+        if newest.is_some() {
+            checkpoint.newest_signature = newest;
+            self.save_checkpoint(checkpoint).await?;
+        }
+
+        Ok(stored)
+    }
+
+    // Transactions are stored at the commitment their signature was listed
+    // with, so finalized history does not wait on the reconciler
+    async fn store_page(&self, address: &str, page: &[RpcConfirmedTransactionStatusWithSignature]) -> Result<()> {
+        let signatures: Vec<String> = page.iter().map(|status| status.signature.clone()).collect();
+        let transactions = self.history.transactions(&signatures, address).await?;
+        for (mut transaction, status) in transactions.into_iter().zip(page) {
+            transaction.commitment = match status.confirmation_status {
+                Some(TransactionConfirmationStatus::Finalized) => Commitment::Finalized,
+                _ => Commitment::Confirmed,
+            };
+            self.store.save_transaction(&transaction).await?;
+        }
+        Ok(())
+    }
+
+    async fn save_checkpoint(&self, checkpoint: &mut BackfillCheckpoint) -> Result<()> {
+        checkpoint.updated_at = Utc::now();
+        self.store.save_backfill_checkpoint(checkpoint).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    const WALLET: &str = "wallet";
+
+    // A wallet history of `sig1..=sigN`, newest first like the RPC. All but
+    // the newest two are finalized.
+    #[derive(Default)]
+    struct MemoryHistory {
+        slots: Mutex<Vec<u64>>,
+    }
+
+    impl MemoryHistory {
+        fn push(&self, count: u64) {
+            let mut slots = self.slots.lock().unwrap();
+            let newest = slots.first().copied().unwrap_or_default();
+            for slot in newest + 1..=newest + count {
+                slots.insert(0, slot);
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SignatureHistory for MemoryHistory {
+        async fn signatures_page(
+            &self,
+            _address: &str,
+            before: Option<&str>,
+            until: Option<&str>,
+            limit: usize,
+        ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+            let signature = |slot: &u64| format!("sig{}", slot);
+            let slots = self.slots.lock().unwrap();
+            let finalized_slot = slots.first().copied().unwrap_or_default().saturating_sub(2);
+            Ok(slots
+                .iter()
+                .skip_while(|slot| before.is_some_and(|before| signature(slot) != before))
+                .skip(before.is_some() as usize)
+                .take_while(|slot| !until.is_some_and(|until| signature(slot) == until))
+                .take(limit)
+                .map(|slot| RpcConfirmedTransactionStatusWithSignature {
+                    signature: signature(slot),
+                    slot: *slot,
+                    err: None,
+                    memo: None,
+                    block_time: None,
+                    confirmation_status: Some(if *slot <= finalized_slot {
+                        TransactionConfirmationStatus::Finalized
+                    } else {
+                        TransactionConfirmationStatus::Confirmed
+                    }),
+                })
+                .collect())
+        }
+
+        async fn transactions(&self, signatures: &[String], address: &str) -> Result<Vec<Transaction>> {
+            Ok(signatures
+                .iter()
+                .map(|signature| {
+                    let slot = signature.trim_start_matches("sig").parse().unwrap();
+                    Transaction::paid_by(slot, address)
+                })
+                .collect())
+        }
+    }
+
+    #[derive(Default)]
+    struct MemoryStore {
+        checkpoints: Mutex<HashMap<String, BackfillCheckpoint>>,
+        transactions: Mutex<HashMap<String, Transaction>>,
+    }
+
+    #[async_trait]
+    impl BackfillStore for MemoryStore {
+        async fn get_wallet_addresses(&self) -> Result<Vec<String>> {
+            Ok(vec![WALLET.to_string()])
+        }
+
+        async fn get_backfill_checkpoint(&self, address: &str) -> Result<Option<BackfillCheckpoint>> {
+            Ok(self.checkpoints.lock().unwrap().get(address).cloned())
+        }
+
+        async fn save_backfill_checkpoint(&self, checkpoint: &BackfillCheckpoint) -> Result<()> {
+            self.checkpoints
+                .lock()
+                .unwrap()
+                .insert(checkpoint.address.clone(), checkpoint.clone());
+            Ok(())
+        }
+
+        async fn save_transaction(&self, transaction: &Transaction) -> Result<()> {
+            self.transactions
+                .lock()
+                .unwrap()
+                .insert(transaction.signature.clone(), transaction.clone());
+            Ok(())
+        }
+    }
+
+    fn service(history: &Arc<MemoryHistory>, store: &Arc<MemoryStore>) -> BackfillService {
+        let mut service = BackfillService::new(history.clone(), store.clone());
+        service.page_limit = 2;
+        service
+    }
+
+    #[tokio::test]
+    async fn test_backfill_pages_through_history_then_catches_up() {
+        let history = Arc::new(MemoryHistory::default());
+        let store = Arc::new(MemoryStore::default());
+        history.push(5);
+        let service = service(&history, &store);
+
+        assert_eq!(service.backfill_wallet(WALLET).await.unwrap(), 5);
+        let checkpoint = store.get_backfill_checkpoint(WALLET).await.unwrap().unwrap();
+        assert!(checkpoint.complete);
+        assert_eq!(checkpoint.newest_signature.as_deref(), Some("sig5"));
+        assert_eq!(checkpoint.oldest_signature.as_deref(), Some("sig1"));
+        let commitment = |signature: &str| store.transactions.lock().unwrap()[signature].commitment;
+        assert_eq!(commitment("sig3"), Commitment::Finalized);
+        assert_eq!(commitment("sig4"), Commitment::Confirmed);
+
+        history.push(3);
+        assert_eq!(service.backfill_wallet(WALLET).await.unwrap(), 3);
+        assert_eq!(store.transactions.lock().unwrap().len(), 8);
+        let checkpoint = store.get_backfill_checkpoint(WALLET).await.unwrap().unwrap();
+        assert_eq!(checkpoint.newest_signature.as_deref(), Some("sig8"));
+    }
+
+    #[tokio::test]
+    async fn test_wallet_without_history_picks_up_new_activity() {
+        let history = Arc::new(MemoryHistory::default());
+        let store = Arc::new(MemoryStore::default());
+        let service = service(&history, &store);
+
+        assert_eq!(service.backfill_wallet(WALLET).await.unwrap(), 0);
+        let checkpoint = store.get_backfill_checkpoint(WALLET).await.unwrap().unwrap();
+        assert!(checkpoint.complete);
+        assert_eq!(checkpoint.newest_signature, None);
+
+        history.push(3);
+        assert_eq!(service.backfill_wallet(WALLET).await.unwrap(), 3);
+        let checkpoint = store.get_backfill_checkpoint(WALLET).await.unwrap().unwrap();
+        assert_eq!(checkpoint.newest_signature.as_deref(), Some("sig3"));
+
+        assert_eq!(service.backfill_wallet(WALLET).await.unwrap(), 0);
+    }
+}
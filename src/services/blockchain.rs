@@ -0,0 +1,1364 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::{stream, StreamExt, TryStreamExt};
+use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClientConfig};
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcBlockConfig, RpcProgramAccountsConfig, RpcTransactionConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_client::rpc_request::{
+    TokenAccountsFilter, MAX_GET_CONFIRMED_BLOCKS_RANGE, MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS,
+};
+use solana_client::rpc_response::{RpcConfirmedTransactionStatusWithSignature, RpcPrioritizationFee};
+use solana_client::rpc_sender::RpcSender;
+use solana_sdk::account::Account;
+use solana_sdk::bs58;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::v0::LoadedAddresses;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, TransactionDetails, TransactionStatus, UiInnerInstructions,
+    UiInstruction, UiLoadedAddresses, UiTransactionEncoding,
+};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::models::{Commitment, EventKind, Nft, StakePosition, Token, TokenBalance, Transaction};
+use crate::services::chain::{ChainClient, Subscription, SUBSCRIPTION_BUFFER};
+use crate::services::subscriptions::WalletStreamer;
+use crate::utils::helpers::{format_token_amount, from_unix_timestamp};
+
+pub mod balances;
+pub mod das;
+pub mod decoder;
+pub mod errors;
+pub mod fees;
+pub mod layout;
+pub mod lookup_tables;
+pub mod lst;
+pub mod nft;
+pub mod replay;
+pub mod rpc_pool;
+pub mod sns;
+pub mod stake;
+pub mod swaps;
+pub mod token_metadata;
+
+use balances::balance_changes;
+use das::DasClient;
+use decoder::DecoderRegistry;
+use errors::diagnose_failure;
+use fees::fee_breakdown;
+use lookup_tables::{parse_lookup_table, parse_ui_loaded_addresses, LookupTableCache};
+use lst::{find_liquid_staking_token, parse_exchange_rate};
+use nft::{is_nft_shaped, nft_from_metadata};
+use replay::{RecordingSender, ReplaySender, RpcMode, SharedSender};
+use rpc_pool::{PooledSender, RpcPool};
+use stake::{parse_stake_account, STAKER_OFFSET, STAKE_ACCOUNT_LEN, WITHDRAWER_OFFSET};
+use swaps::{settle_swaps, token_account_index};
+use token_metadata::{
+    metadata_address, parse_metaplex_metadata, parse_mint, parse_token_2022_metadata, MintInfo, TokenMetadata,
+};
+
+pub const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+// Native SOL is reported under the wrapped SOL mint so it merges with any wSOL accounts
+pub const NATIVE_MINT: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+pub const NATIVE_DECIMALS: u8 = 9;
+
+// getSignaturesForAddress never returns more than this per call
+pub const SIGNATURE_PAGE_LIMIT: usize = 1000;
+const RECENT_TRANSACTIONS_LIMIT: usize = 50;
+// getMultipleAccounts accepts at most this many keys per call
+pub const MULTIPLE_ACCOUNTS_CHUNK: usize = 100;
+const DEFAULT_MAX_CONCURRENCY: usize = 16;
+// Widest getBlocks range read when checking whether slots were finalized
+const FINALIZED_BLOCKS_SPAN: u64 = 1_000;
+
+pub struct SolanaClient {
+    client: RpcClient,
+    sender: SharedSender,
+    // Absent when replaying recorded traffic
+    pool: Option<Arc<RpcPool>>,
+    decoders: DecoderRegistry,
+    // Upper bound on RPC calls a single fan-out (one wallet analysis, one
+    // backfill page) keeps in flight at once
+    max_concurrency: usize,
+    das: Option<DasClient>,
+    lookup_tables: LookupTableCache,
+    // Default read commitment; calls that store data take it explicitly
+    commitment: Commitment,
+}
+
+impl From<Commitment> for CommitmentConfig {
+    fn from(commitment: Commitment) -> Self {
+        match commitment {
+            Commitment::Confirmed => CommitmentConfig::confirmed(),
+            Commitment::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+impl SolanaClient {
+    pub async fn new() -> Result<Self> {
+        let client = match RpcMode::from_env()? {
+            RpcMode::Live => Self::with_pool(rpc_pool_from_env()?).with_das(DasClient::from_env()?),
+            RpcMode::Record(dir) => {
+                info!("Recording Solana RPC traffic to {}", dir.display());
+                let pool = rpc_pool_from_env()?;
+                let recorder = RecordingSender::new(PooledSender(pool.clone()), &dir)?;
+                let das = DasClient::from_env()?.map(|das| das.recording(&dir)).transpose()?;
+                let mut client = Self::with_sender(Arc::new(recorder)).with_das(das);
+                client.pool = Some(pool);
+                client
+            }
+            // Fully offline: no pool, and DAS answers come from the same
+            // fixtures if the recording had an indexer
+            RpcMode::Replay(dir) => {
+                info!("Replaying Solana RPC traffic from {}", dir.display());
+                let replay = Arc::new(ReplaySender::load(&dir)?);
+                let das = replay
+                    .has_method(das::GET_ASSETS_BY_OWNER)
+                    .then(|| DasClient::with_sender(replay.clone()));
+                Self::with_sender(replay).with_das(das)
+            }
+        };
+        Ok(client.with_commitment(commitment_from_env()?))
+    }
+
+    pub fn with_commitment(mut self, commitment: Commitment) -> Self {
+        self.client = RpcClient::new_sender(
+            self.sender.clone(),
+            RpcClientConfig::with_commitment(commitment.into()),
+        );
+        self.commitment = commitment;
+        self
+    }
+
+    pub fn with_das(mut self, das: Option<DasClient>) -> Self {
+        self.das = das;
+        self
+    }
+
+    pub fn with_pool(pool: Arc<RpcPool>) -> Self {
+        let mut client = Self::with_sender(Arc::new(PooledSender(pool.clone())));
+        client.pool = Some(pool);
+        client
+    }
+
+    pub fn with_sender(sender: Arc<dyn RpcSender + Send + Sync>) -> Self {
+        let sender = SharedSender(sender);
+        let client = RpcClient::new_sender(
+            sender.clone(),
+            RpcClientConfig::with_commitment(Commitment::default().into()),
+        );
+
+        let max_concurrency = std::env::var("SOLANA_RPC_MAX_CONCURRENCY")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_MAX_CONCURRENCY);
+
+        Self {
+            client,
+            sender,
+            pool: None,
+            decoders: DecoderRegistry::with_builtin_decoders(),
+            max_concurrency,
+            das: None,
+            lookup_tables: LookupTableCache::new(),
+            commitment: Commitment::default(),
+        }
+    }
+
+    pub fn rpc_pool(&self) -> Option<Arc<RpcPool>> {
+        self.pool.clone()
+    }
+
+    pub fn commitment(&self) -> Commitment {
+        self.commitment
+    }
+
+    pub async fn get_wallet_tokens(&self, address: &str, commitment: Commitment) -> Result<Vec<TokenBalance>> {
+        let (token_accounts, native) = futures::try_join!(
+            self.get_token_accounts(address, commitment),
+            self.get_native_balance(address, commitment),
+        )?;
+        let nfts: HashSet<String> = self
+            .token_account_nfts(&token_accounts)
+            .await?
+            .into_iter()
+            .map(|nft| nft.mint)
+            .collect();
+        Ok(aggregate_balances(token_accounts.iter(), native, commitment, &nfts))
+    }
+
+    // Every SPL Token and Token-2022 account owned by the address
+    pub async fn get_token_accounts(&self, address: &str, commitment: Commitment) -> Result<Vec<TokenAccountBalance>> {
+        let owner = Pubkey::from_str(address)?;
+        let (classic, token_2022) = futures::try_join!(
+            self.client.get_token_accounts_by_owner_with_commitment(
+                &owner,
+                TokenAccountsFilter::ProgramId(TOKEN_PROGRAM_ID),
+                commitment.into(),
+            ),
+            self.client.get_token_accounts_by_owner_with_commitment(
+                &owner,
+                TokenAccountsFilter::ProgramId(TOKEN_2022_PROGRAM_ID),
+                commitment.into(),
+            ),
+        )?;
+
+        Ok([classic, token_2022]
+            .into_iter()
+            .flat_map(|response| {
+                let slot = response.context.slot;
+                response.value.into_iter().map(move |keyed_account| (slot, keyed_account))
+            })
+            .filter_map(|(slot, keyed_account)| {
+                let (mint, raw_amount, decimals) = parse_token_account(&keyed_account.account.data)?;
+                Some(TokenAccountBalance {
+                    address: keyed_account.pubkey,
+                    mint,
+                    raw_amount,
+                    decimals,
+                    slot,
+                })
+            })
+            .collect())
+    }
+
+    pub async fn get_native_balance(&self, address: &str, commitment: Commitment) -> Result<NativeBalance> {
+        let response = self
+            .client
+            .get_balance_with_commitment(&Pubkey::from_str(address)?, commitment.into())
+            .await?;
+        Ok(NativeBalance {
+            lamports: response.value,
+            slot: response.context.slot,
+        })
+    }
+
+    pub async fn get_finalized_slot(&self) -> Result<u64> {
+        Ok(self.client.get_slot_with_commitment(CommitmentConfig::finalized()).await?)
+    }
+
+    // Those of `slots` that hold a finalized block; the others were skipped.
+    // Only the ranges around the given slots are read, so a long-pending
+    // record does not drag in every block since.
+    pub async fn get_finalized_blocks(&self, slots: &[u64]) -> Result<HashSet<u64>> {
+        let wanted: HashSet<u64> = slots.iter().copied().collect();
+        let ranges: Vec<Vec<u64>> = stream::iter(slot_ranges(slots, FINALIZED_BLOCKS_SPAN))
+            .map(|(start, end)| self.get_blocks(start, end, Commitment::Finalized))
+            .buffer_unordered(self.max_concurrency)
+            .try_collect()
+            .await?;
+        Ok(ranges
+            .into_iter()
+            .flatten()
+            .filter(|slot| wanted.contains(slot))
+            .collect())
+    }
+
+    pub async fn get_slot(&self, commitment: Commitment) -> Result<u64> {
+        Ok(self.client.get_slot_with_commitment(commitment.into()).await?)
+    }
+
+    // Slots in `start..=end` that produced a block, ascending
+    pub async fn get_blocks(&self, start: u64, end: u64, commitment: Commitment) -> Result<Vec<u64>> {
+        let mut blocks = Vec::new();
+        let mut from = start;
+        while from <= end {
+            let to = end.min(from.saturating_add(MAX_GET_CONFIRMED_BLOCKS_RANGE - 1));
+            blocks.extend(
+                self.client
+                    .get_blocks_with_commitment(from, Some(to), commitment.into())
+                    .await?,
+            );
+            from = to + 1;
+        }
+        Ok(blocks)
+    }
+
+    // Transactions in the block at `slot` that touch a tracked address, one
+    // record per tracked address in the account keys, seen from that address
+    pub async fn get_block_transactions(
+        &self,
+        slot: u64,
+        tracked: &HashSet<Pubkey>,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        let config = RpcBlockConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            transaction_details: Some(TransactionDetails::Full),
+            rewards: Some(false),
+            commitment: Some(commitment.into()),
+            max_supported_transaction_version: Some(0),
+        };
+        let block = self.client.get_block_with_config(slot, config).await?;
+
+        let mut transactions = Vec::new();
+        for encoded in block.transactions.unwrap_or_default() {
+            let Some(versioned) = encoded.transaction.decode() else {
+                warn!("Skipping undecodable transaction in slot {}", slot);
+                continue;
+            };
+            let recorded = match encoded.meta.as_ref().map(|meta| &meta.loaded_addresses) {
+                Some(OptionSerializer::Some(loaded)) => Some(loaded),
+                _ => None,
+            };
+            let loaded = self.loaded_addresses(&versioned.message, recorded).await?;
+
+            let mut wallets: Vec<Pubkey> = full_account_keys(&versioned.message, &loaded)
+                .into_iter()
+                .filter(|key| tracked.contains(key))
+                .collect();
+            wallets.dedup();
+            if wallets.is_empty() {
+                continue;
+            }
+            let signature = versioned
+                .signatures
+                .first()
+                .map(|signature| signature.to_string())
+                .unwrap_or_default();
+
+            for wallet in wallets {
+                let encoded = EncodedConfirmedTransactionWithStatusMeta {
+                    slot,
+                    transaction: encoded.clone(),
+                    block_time: block.block_time,
+                };
+                let mut transaction = to_transaction(
+                    &self.decoders,
+                    &signature,
+                    &wallet.to_string(),
+                    encoded,
+                    &versioned,
+                    &loaded,
+                )?;
+                transaction.commitment = commitment;
+                transactions.push(transaction);
+            }
+        }
+        Ok(transactions)
+    }
+
+    // Statuses in input order; `None` for signatures the cluster does not know
+    pub async fn get_signature_statuses(&self, signatures: &[String]) -> Result<Vec<Option<TransactionStatus>>> {
+        let signatures: Vec<Signature> = signatures
+            .iter()
+            .map(|signature| Signature::from_str(signature))
+            .collect::<Result<_, _>>()?;
+
+        let chunks: Vec<Vec<Option<TransactionStatus>>> =
+            stream::iter(signatures.chunks(MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS))
+                .map(|chunk| async move {
+                    Ok::<_, anyhow::Error>(self.client.get_signature_statuses_with_history(chunk).await?.value)
+                })
+                .buffered(self.max_concurrency)
+                .try_collect()
+                .await?;
+
+        Ok(chunks.into_iter().flatten().collect())
+    }
+
+    // Lowest priority fee (micro-lamports per compute unit) that landed a
+    // transaction in each recent slot, limited to transactions write-locking
+    // all of `accounts` when any are given
+    pub async fn get_recent_prioritization_fees(&self, accounts: &[String]) -> Result<Vec<RpcPrioritizationFee>> {
+        let accounts: Vec<Pubkey> = accounts
+            .iter()
+            .map(|account| Pubkey::from_str(account))
+            .collect::<Result<_, _>>()?;
+        Ok(self.client.get_recent_prioritization_fees(&accounts).await?)
+    }
+
+    // NFTs held in token accounts, identified by their Metaplex metadata,
+    // plus compressed NFTs when a DAS indexer is configured
+    pub async fn get_nfts(&self, address: &str) -> Result<Vec<Nft>> {
+        let compressed = async {
+            match &self.das {
+                Some(das) => das.get_compressed_nfts(address).await,
+                None => Ok(Vec::new()),
+            }
+        };
+        let (token_accounts, compressed) =
+            futures::try_join!(self.get_token_accounts(address, self.commitment), compressed)?;
+
+        let mut nfts = self.token_account_nfts(&token_accounts).await?;
+        nfts.extend(compressed);
+        Ok(nfts)
+    }
+
+    // NFTs among the token accounts: NFT-shaped holdings whose Metaplex
+    // metadata confirms them
+    async fn token_account_nfts(&self, token_accounts: &[TokenAccountBalance]) -> Result<Vec<Nft>> {
+        let mints: Vec<Pubkey> = token_accounts
+            .iter()
+            .filter(|account| is_nft_shaped(account))
+            .filter_map(|account| Pubkey::from_str(&account.mint).ok())
+            .collect();
+        if mints.is_empty() {
+            return Ok(Vec::new());
+        }
+        let metadata_addresses: Vec<Pubkey> = mints.iter().map(metadata_address).collect();
+        let metadata_accounts = self.get_multiple_accounts(&metadata_addresses).await?;
+
+        Ok(mints
+            .iter()
+            .zip(metadata_accounts)
+            .filter_map(|(mint, account)| {
+                let metadata = parse_metaplex_metadata(&account?.data).ok()?;
+                nft_from_metadata(mint, metadata)
+            })
+            .collect())
+    }
+
+    // Native stake accounts the address can manage, found by either authority
+    pub async fn get_stake_positions(&self, address: &str, commitment: Commitment) -> Result<Vec<StakePosition>> {
+        let authority = Pubkey::from_str(address)?;
+        let (epoch_info, as_staker, as_withdrawer) = futures::try_join!(
+            self.client.get_epoch_info_with_commitment(commitment.into()),
+            self.get_stake_accounts_by_authority(&authority, STAKER_OFFSET, commitment),
+            self.get_stake_accounts_by_authority(&authority, WITHDRAWER_OFFSET, commitment),
+        )?;
+
+        // The same account usually matches both queries
+        let accounts: BTreeMap<Pubkey, Account> = as_staker.into_iter().chain(as_withdrawer).collect();
+        Ok(accounts
+            .iter()
+            .filter_map(|(pubkey, account)| {
+                parse_stake_account(pubkey, account.lamports, &account.data, epoch_info.epoch).ok()
+            })
+            .collect())
+    }
+
+    async fn get_stake_accounts_by_authority(
+        &self,
+        authority: &Pubkey,
+        offset: usize,
+        commitment: Commitment,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(STAKE_ACCOUNT_LEN as u64),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(offset, authority.as_ref())),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(commitment.into()),
+                ..Default::default()
+            },
+            with_context: None,
+        };
+
+        Ok(self
+            .client
+            .get_program_accounts_with_config(&solana_sdk::stake::program::id(), config)
+            .await?)
+    }
+
+    // SOL per token for each liquid staking token among `mints`, read from
+    // the stake pools' on-chain state
+    pub async fn get_liquid_staking_rates(&self, mints: &[String]) -> Result<HashMap<String, f64>> {
+        let tokens: Vec<_> = mints
+            .iter()
+            .filter_map(|mint| find_liquid_staking_token(mint))
+            .collect();
+        if tokens.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let pools: Vec<Pubkey> = tokens.iter().map(|token| token.pool).collect();
+        let accounts = self.get_multiple_accounts(&pools).await?;
+
+        let mut rates = HashMap::new();
+        for (token, account) in tokens.iter().zip(accounts) {
+            let Some(account) = account else {
+                warn!("Stake pool {} for {} not found", token.pool, token.symbol);
+                continue;
+            };
+            match parse_exchange_rate(token.program, &account.data) {
+                Ok(rate) => {
+                    rates.insert(token.mint.to_string(), rate);
+                }
+                Err(e) => warn!("Failed to read {} exchange rate: {}", token.symbol, e),
+            }
+        }
+        Ok(rates)
+    }
+
+    // Fetches any number of accounts in getMultipleAccounts-sized chunks,
+    // keeping at most `max_concurrency` chunks in flight. Order is preserved.
+    pub async fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let chunks: Vec<Vec<Option<Account>>> = stream::iter(pubkeys.chunks(MULTIPLE_ACCOUNTS_CHUNK))
+            .map(|chunk| self.client.get_multiple_accounts(chunk))
+            .buffered(self.max_concurrency)
+            .try_collect()
+            .await?;
+
+        Ok(chunks.into_iter().flatten().collect())
+    }
+
+    // Current owner of a `.sol` domain
+    pub async fn resolve_domain(&self, domain: &str) -> Result<String> {
+        let name_account = sns::domain_key(domain)?;
+        let account = self
+            .client
+            .get_account_with_commitment(&name_account, self.client.commitment())
+            .await?
+            .value
+            .ok_or_else(|| anyhow!("{} is not registered", domain))?;
+        if account.owner != sns::NAME_PROGRAM_ID {
+            return Err(anyhow!("{} is not a name registry account", domain));
+        }
+        let owner = sns::parse_owner(&account.data)?;
+        if owner != sns::tokenizer_escrow() {
+            return Ok(owner.to_string());
+        }
+
+        // Tokenized: the domain belongs to whoever holds its NFT
+        let holding = self
+            .client
+            .get_token_largest_accounts(&sns::tokenized_mint(&name_account))
+            .await?
+            .into_iter()
+            .find(|balance| balance.amount.amount == "1")
+            .ok_or_else(|| anyhow!("{} is tokenized but nobody holds its NFT", domain))?;
+        let holding = self.client.get_account(&Pubkey::from_str(&holding.address)?).await?;
+        Ok(sns::parse_token_account_owner(&holding.data)?.to_string())
+    }
+
+    pub async fn get_token_info(&self, address: &str) -> Result<Token> {
+        let mint = Pubkey::from_str(address)?;
+        let account = self.client.get_account(&mint).await?;
+        if account.owner != TOKEN_PROGRAM_ID && account.owner != TOKEN_2022_PROGRAM_ID {
+            return Err(anyhow!("{} is not a token mint", address));
+        }
+
+        let mint_info = parse_mint(&account.data)?;
+
+        // Token-2022 mints may carry their metadata inline; everything else
+        // falls back to the Metaplex metadata PDA
+        let mut metadata = if account.owner == TOKEN_2022_PROGRAM_ID {
+            parse_token_2022_metadata(&account.data)?
+        } else {
+            None
+        };
+        if metadata.is_none() {
+            metadata = self
+                .client
+                .get_account_with_commitment(&metadata_address(&mint), self.client.commitment())
+                .await?
+                .value
+                .map(|account| parse_metaplex_metadata(&account.data))
+                .transpose()?;
+        }
+        Ok(to_token(address.to_string(), mint_info, metadata.unwrap_or_default()))
+    }
+
+    // Token info for each of `mints`, read with one getMultipleAccounts pass
+    // over the mints and one over the metadata PDAs of those without inline
+    // metadata. Addresses that are not token mints are left out.
+    pub async fn get_token_infos(&self, mints: &[String]) -> Result<HashMap<String, Token>> {
+        let keys: Vec<Pubkey> = mints.iter().filter_map(|mint| Pubkey::from_str(mint).ok()).collect();
+        let accounts = self.get_multiple_accounts(&keys).await?;
+
+        let mut parsed = Vec::new();
+        for (key, account) in keys.iter().zip(accounts) {
+            let Some(account) = account else {
+                continue;
+            };
+            if account.owner != TOKEN_PROGRAM_ID && account.owner != TOKEN_2022_PROGRAM_ID {
+                continue;
+            }
+            let mint_info = match parse_mint(&account.data) {
+                Ok(mint_info) => mint_info,
+                Err(e) => {
+                    warn!("Failed to read mint {}: {}", key, e);
+                    continue;
+                }
+            };
+            let metadata = if account.owner == TOKEN_2022_PROGRAM_ID {
+                parse_token_2022_metadata(&account.data).unwrap_or_else(|e| {
+                    warn!("Failed to read inline metadata of {}: {}", key, e);
+                    None
+                })
+            } else {
+                None
+            };
+            parsed.push((*key, mint_info, metadata));
+        }
+
+        let metadata_addresses: Vec<Pubkey> = parsed
+            .iter()
+            .filter(|(_, _, metadata)| metadata.is_none())
+            .map(|(key, _, _)| metadata_address(key))
+            .collect();
+        // One entry per mint still missing metadata, in the same order
+        let mut metadata_accounts = self.get_multiple_accounts(&metadata_addresses).await?.into_iter();
+
+        Ok(parsed
+            .into_iter()
+            .map(|(key, mint_info, metadata)| {
+                let metadata = metadata.or_else(|| {
+                    metadata_accounts
+                        .next()
+                        .flatten()
+                        .and_then(|account| parse_metaplex_metadata(&account.data).ok())
+                });
+                (key.to_string(), to_token(key.to_string(), mint_info, metadata.unwrap_or_default()))
+            })
+            .collect())
+    }
+
+    pub async fn get_transactions(&self, address: &str, commitment: Commitment) -> Result<Vec<Transaction>> {
+        let signatures: Vec<String> = self
+            .get_signatures_page(address, None, None, RECENT_TRANSACTIONS_LIMIT, commitment)
+            .await?
+            .into_iter()
+            .map(|status| status.signature)
+            .collect();
+
+        self.get_transactions_batch(&signatures, address, commitment).await
+    }
+
+    // Fetches transactions concurrently (bounded), preserving input order
+    pub async fn get_transactions_batch(
+        &self,
+        signatures: &[String],
+        wallet_address: &str,
+        commitment: Commitment,
+    ) -> Result<Vec<Transaction>> {
+        stream::iter(signatures)
+            .map(|signature| self.get_transaction(signature, wallet_address, commitment))
+            .buffered(self.max_concurrency)
+            .try_collect()
+            .await
+    }
+
+    // One page of signatures, newest first. `before` and `until` are exclusive cursors.
+    pub async fn get_signatures_page(
+        &self,
+        address: &str,
+        before: Option<&str>,
+        until: Option<&str>,
+        limit: usize,
+        commitment: Commitment,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let address = Pubkey::from_str(address)?;
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before: before.map(Signature::from_str).transpose()?,
+            until: until.map(Signature::from_str).transpose()?,
+            limit: Some(limit.min(SIGNATURE_PAGE_LIMIT)),
+            commitment: Some(commitment.into()),
+        };
+
+        Ok(self
+            .client
+            .get_signatures_for_address_with_config(&address, config)
+            .await?)
+    }
+
+    pub async fn get_transaction(
+        &self,
+        signature: &str,
+        wallet_address: &str,
+        commitment: Commitment,
+    ) -> Result<Transaction> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(commitment.into()),
+            max_supported_transaction_version: Some(0),
+        };
+        let encoded = self
+            .client
+            .get_transaction_with_config(&Signature::from_str(signature)?, config)
+            .await?;
+
+        let versioned = encoded
+            .transaction
+            .transaction
+            .decode()
+            .ok_or_else(|| anyhow!("Unsupported encoding for transaction {}", signature))?;
+        let recorded = match encoded.transaction.meta.as_ref().map(|meta| &meta.loaded_addresses) {
+            Some(OptionSerializer::Some(loaded)) => Some(loaded),
+            _ => None,
+        };
+        let loaded = self.loaded_addresses(&versioned.message, recorded).await?;
+
+        let mut transaction = to_transaction(&self.decoders, signature, wallet_address, encoded, &versioned, &loaded)?;
+        transaction.commitment = commitment;
+        Ok(transaction)
+    }
+
+    // Accounts a v0 transaction pulled in from address lookup tables. The
+    // addresses recorded in the status meta are used when present; otherwise
+    // the tables are read, through the cache.
+    async fn loaded_addresses(
+        &self,
+        message: &VersionedMessage,
+        recorded: Option<&UiLoadedAddresses>,
+    ) -> Result<LoadedAddresses> {
+        let Some(lookups) = message.address_table_lookups().filter(|lookups| !lookups.is_empty()) else {
+            return Ok(LoadedAddresses::default());
+        };
+        if let Some(recorded) = recorded {
+            return parse_ui_loaded_addresses(recorded);
+        }
+
+        let missing = self.lookup_tables.missing(lookups);
+        if !missing.is_empty() {
+            for (key, account) in missing.iter().zip(self.get_multiple_accounts(&missing).await?) {
+                let account = account.ok_or_else(|| anyhow!("Address lookup table {} not found", key))?;
+                self.lookup_tables.insert(*key, parse_lookup_table(&account.data)?);
+            }
+        }
+
+        self.lookup_tables.resolve(lookups)
+    }
+}
+
+#[async_trait]
+impl ChainClient for SolanaClient {
+    fn commitment(&self) -> Commitment {
+        self.commitment
+    }
+
+    async fn get_wallet_tokens(&self, address: &str, commitment: Commitment) -> Result<Vec<TokenBalance>> {
+        SolanaClient::get_wallet_tokens(self, address, commitment).await
+    }
+
+    async fn get_stake_positions(&self, address: &str, commitment: Commitment) -> Result<Vec<StakePosition>> {
+        SolanaClient::get_stake_positions(self, address, commitment).await
+    }
+
+    async fn get_liquid_staking_rates(&self, mints: &[String]) -> Result<HashMap<String, f64>> {
+        SolanaClient::get_liquid_staking_rates(self, mints).await
+    }
+
+    async fn get_nfts(&self, address: &str) -> Result<Vec<Nft>> {
+        SolanaClient::get_nfts(self, address).await
+    }
+
+    async fn get_token_info(&self, address: &str) -> Result<Token> {
+        SolanaClient::get_token_info(self, address).await
+    }
+
+    async fn get_token_infos(&self, mints: &[String]) -> Result<HashMap<String, Token>> {
+        SolanaClient::get_token_infos(self, mints).await
+    }
+
+    async fn resolve_domain(&self, domain: &str) -> Result<String> {
+        SolanaClient::resolve_domain(self, domain).await
+    }
+
+    async fn get_transactions(&self, address: &str, commitment: Commitment) -> Result<Vec<Transaction>> {
+        SolanaClient::get_transactions(self, address, commitment).await
+    }
+
+    async fn get_transaction(
+        &self,
+        signature: &str,
+        wallet_address: &str,
+        commitment: Commitment,
+    ) -> Result<Transaction> {
+        SolanaClient::get_transaction(self, signature, wallet_address, commitment).await
+    }
+
+    async fn get_recent_prioritization_fees(&self, accounts: &[String]) -> Result<Vec<RpcPrioritizationFee>> {
+        SolanaClient::get_recent_prioritization_fees(self, accounts).await
+    }
+
+    // Streams over the PubSub websocket at SOLANA_WS_URL (or the RPC URL
+    // with a websocket scheme), reconnecting until the receiver is dropped
+    async fn subscribe(&self, wallets: Vec<String>) -> Result<Subscription> {
+        let streamer = Arc::new(
+            WalletStreamer::new(WalletStreamer::ws_url_from_env()?, wallets, Default::default())
+                .with_commitment(self.commitment),
+        );
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        tokio::spawn({
+            let streamer = streamer.clone();
+            async move { streamer.run(sender).await }
+        });
+        Ok(Subscription {
+            updates: receiver,
+            tracker: streamer,
+        })
+    }
+}
+
+// Token info as read from chain, which carries no market data
+fn to_token(address: String, mint_info: MintInfo, metadata: TokenMetadata) -> Token {
+    Token {
+        address,
+        symbol: metadata.symbol,
+        name: metadata.name,
+        decimals: mint_info.decimals,
+        total_supply: mint_info.supply,
+        price_usd: 0.0,
+        market_cap_usd: 0.0,
+        volume_24h: 0.0,
+        price_change_24h: 0.0,
+        mint_authority: mint_info.mint_authority.map(|key| key.to_string()),
+        freeze_authority: mint_info.freeze_authority.map(|key| key.to_string()),
+        uri: (!metadata.uri.is_empty()).then_some(metadata.uri),
+        liquidity_usd: None,
+        refreshed_at: Some(Utc::now()),
+    }
+}
+
+fn to_transaction(
+    decoders: &DecoderRegistry,
+    signature: &str,
+    wallet_address: &str,
+    encoded: EncodedConfirmedTransactionWithStatusMeta,
+    versioned: &VersionedTransaction,
+    loaded: &LoadedAddresses,
+) -> Result<Transaction> {
+    let meta = encoded
+        .transaction
+        .meta
+        .ok_or_else(|| anyhow!("Transaction {} has no status meta", signature))?;
+
+    let keys = full_account_keys(&versioned.message, loaded);
+    let account_keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+    let writable_accounts: Vec<String> = account_keys
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| versioned.message.is_maybe_writable(*index))
+        .map(|(_, key)| key.clone())
+        .collect();
+
+    let inner_instructions = match &meta.inner_instructions {
+        OptionSerializer::Some(inner) => compile_inner_instructions(inner),
+        _ => HashMap::new(),
+    };
+    let mut events = decoders.decode_transaction(&keys, versioned.message.instructions(), &inner_instructions);
+
+    // Swap amounts come from the transfers they caused, matched up using the
+    // mints recorded in the token balances
+    let pre_token_balances = match &meta.pre_token_balances {
+        OptionSerializer::Some(balances) => balances.as_slice(),
+        _ => &[],
+    };
+    let post_token_balances = match &meta.post_token_balances {
+        OptionSerializer::Some(balances) => balances.as_slice(),
+        _ => &[],
+    };
+    let token_balances = pre_token_balances.iter().chain(post_token_balances);
+    settle_swaps(&mut events, &token_account_index(&account_keys, token_balances));
+    let balance_changes = balance_changes(
+        &account_keys,
+        &meta.pre_balances,
+        &meta.post_balances,
+        pre_token_balances,
+        post_token_balances,
+    );
+
+    let compute_units_consumed = match meta.compute_units_consumed {
+        OptionSerializer::Some(units) => Some(units),
+        _ => None,
+    };
+    let fees = fee_breakdown(&versioned.message, &events, meta.fee, compute_units_consumed);
+
+    let logs = match &meta.log_messages {
+        OptionSerializer::Some(logs) => logs.as_slice(),
+        _ => &[],
+    };
+    let failure = meta
+        .err
+        .as_ref()
+        .map(|err| diagnose_failure(err, &keys, versioned.message.instructions(), logs));
+
+    // Net SOL movement for the wallet, excluding the fee it may have paid
+    let fee_payer = account_keys.first().cloned().unwrap_or_default();
+    let lamport_delta = account_keys
+        .iter()
+        .position(|key| key == wallet_address)
+        .map(|index| {
+            let pre = meta.pre_balances.get(index).copied().unwrap_or_default() as i128;
+            let post = meta.post_balances.get(index).copied().unwrap_or_default() as i128;
+            let fee = if fee_payer == wallet_address { meta.fee as i128 } else { 0 };
+            post - pre + fee
+        })
+        .unwrap_or_default();
+
+    // Prefer the counterparty from a decoded SOL transfer touching the wallet
+    let (from_address, to_address) = events
+        .iter()
+        .find_map(|event| match &event.kind {
+            EventKind::SolTransfer { from, to, .. } if from == wallet_address || to == wallet_address => {
+                Some((from.clone(), to.clone()))
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| {
+            if lamport_delta < 0 {
+                (wallet_address.to_string(), String::new())
+            } else {
+                (String::new(), wallet_address.to_string())
+            }
+        });
+
+    Ok(Transaction {
+        signature: signature.to_string(),
+        slot: encoded.slot,
+        block_time: from_unix_timestamp(encoded.block_time.unwrap_or_default()),
+        success: meta.err.is_none(),
+        failure,
+        from_address,
+        to_address,
+        amount: format_token_amount(lamport_delta.unsigned_abs() as u64, NATIVE_DECIMALS),
+        token_address: None,
+        fee: meta.fee,
+        fees,
+        account_keys,
+        writable_accounts,
+        events,
+        balance_changes,
+        commitment: Commitment::default(),
+        wallet: wallet_address.to_string(),
+    })
+}
+
+// Inclusive ranges covering `slots`, each spanning at most `max_span` slots.
+// Nearby slots share a range; isolated ones get a range of their own.
+fn slot_ranges(slots: &[u64], max_span: u64) -> Vec<(u64, u64)> {
+    let mut sorted = slots.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for slot in sorted {
+        match ranges.last_mut() {
+            Some((start, end)) if slot - *start < max_span => *end = slot,
+            _ => ranges.push((slot, slot)),
+        }
+    }
+    ranges
+}
+
+// Instruction account indexes address static keys, then loaded writable,
+// then loaded readonly keys
+fn full_account_keys(message: &VersionedMessage, loaded: &LoadedAddresses) -> Vec<Pubkey> {
+    message
+        .static_account_keys()
+        .iter()
+        .chain(&loaded.writable)
+        .chain(&loaded.readonly)
+        .copied()
+        .collect()
+}
+
+// Inner instructions come back with base58 data; decode them into the same
+// shape as top-level instructions, keyed by the invoking instruction's index
+fn compile_inner_instructions(
+    inner_instructions: &[UiInnerInstructions],
+) -> HashMap<usize, Vec<CompiledInstruction>> {
+    inner_instructions
+        .iter()
+        .map(|inner| {
+            let instructions = inner
+                .instructions
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    UiInstruction::Compiled(compiled) => Some(CompiledInstruction {
+                        program_id_index: compiled.program_id_index,
+                        accounts: compiled.accounts.clone(),
+                        data: bs58::decode(&compiled.data).into_vec().ok()?,
+                    }),
+                    UiInstruction::Parsed(_) => None,
+                })
+                .collect();
+            (inner.index as usize, instructions)
+        })
+        .collect()
+}
+
+// A single token account; several of these may hold the same mint
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenAccountBalance {
+    pub address: String,
+    pub mint: String,
+    pub raw_amount: u64,
+    pub decimals: u8,
+    // Slot the account was read at
+    pub slot: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NativeBalance {
+    pub lamports: u64,
+    pub slot: u64,
+}
+
+// Merges token accounts by mint and folds native lamports into the wrapped
+// SOL entry. Zero balances are dropped and the result is ordered by mint.
+// Mints in `nfts` are left out; they belong to the NFT inventory. Each
+// balance is tagged with the newest slot among the accounts behind it.
+pub fn aggregate_balances<'a>(
+    token_accounts: impl IntoIterator<Item = &'a TokenAccountBalance>,
+    native: NativeBalance,
+    commitment: Commitment,
+    nfts: &HashSet<String>,
+) -> Vec<TokenBalance> {
+    let mut holdings: BTreeMap<String, (u64, u8, u64)> = BTreeMap::new();
+
+    for account in token_accounts.into_iter().filter(|account| !nfts.contains(&account.mint)) {
+        let entry = holdings
+            .entry(account.mint.clone())
+            .or_insert((0, account.decimals, 0));
+        entry.0 = entry.0.saturating_add(account.raw_amount);
+        entry.2 = entry.2.max(account.slot);
+    }
+
+    let entry = holdings
+        .entry(NATIVE_MINT.to_string())
+        .or_insert((0, NATIVE_DECIMALS, 0));
+    entry.0 = entry.0.saturating_add(native.lamports);
+    entry.2 = entry.2.max(native.slot);
+
+    holdings
+        .into_iter()
+        .filter(|(_, (amount, _, _))| *amount > 0)
+        .map(|(mint, (amount, decimals, slot))| TokenBalance {
+            slot,
+            commitment,
+            ..TokenBalance::new(mint, amount, decimals)
+        })
+        .collect()
+}
+
+fn rpc_pool_from_env() -> Result<Arc<RpcPool>> {
+    let pool = Arc::new(RpcPool::from_env()?);
+    info!("Solana RPC pool: {}", pool.endpoint_names().join(", "));
+    Ok(pool)
+}
+
+fn commitment_from_env() -> Result<Commitment> {
+    match std::env::var("SOLANA_COMMITMENT") {
+        Ok(value) => value.parse(),
+        Err(_) => Ok(Commitment::default()),
+    }
+}
+
+// Extracts (mint, raw amount, decimals) from a jsonParsed SPL Token / Token-2022 account
+pub(crate) fn parse_token_account(data: &UiAccountData) -> Option<(String, u64, u8)> {
+    let UiAccountData::Json(parsed) = data else {
+        return None;
+    };
+
+    let info = parsed.parsed.get("info")?;
+    let mint = info.get("mint")?.as_str()?.to_string();
+    let token_amount = info.get("tokenAmount")?;
+    let amount = token_amount.get("amount")?.as_str()?.parse::<u64>().ok()?;
+    let decimals = u8::try_from(token_amount.get("decimals")?.as_u64()?).ok()?;
+
+    Some((mint, amount, decimals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rpc_pool::{EndpointConfig, RetryPolicy};
+    use serde_json::json;
+    use solana_account_decoder::parse_account_data::ParsedAccount;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    fn parsed_account(program: &str, parsed: serde_json::Value) -> UiAccountData {
+        UiAccountData::Json(ParsedAccount {
+            program: program.to_string(),
+            parsed,
+            space: 165,
+        })
+    }
+
+    #[test]
+    fn test_parse_token_account() {
+        let data = parsed_account(
+            "spl-token",
+            json!({
+                "type": "account",
+                "info": {
+                    "mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                    "owner": "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM",
+                    "state": "initialized",
+                    "tokenAmount": {
+                        "amount": "1250000",
+                        "decimals": 6,
+                        "uiAmount": 1.25,
+                        "uiAmountString": "1.25"
+                    }
+                }
+            }),
+        );
+
+        let (mint, amount, decimals) = parse_token_account(&data).unwrap();
+        assert_eq!(mint, "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+        assert_eq!(amount, 1_250_000);
+        assert_eq!(decimals, 6);
+    }
+
+    #[test]
+    fn test_aggregate_balances_merges_by_mint() {
+        let usdc = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string();
+        let account = |address: &str, mint: &str, raw_amount: u64, decimals: u8, slot: u64| TokenAccountBalance {
+            address: address.to_string(),
+            mint: mint.to_string(),
+            raw_amount,
+            decimals,
+            slot,
+        };
+        let accounts = vec![
+            account("a1", &usdc, 1_000_000, 6, 101),
+            account("a2", &usdc, 500_000, 6, 104),
+            account("a3", &NATIVE_MINT.to_string(), 2_000_000_000, 9, 98),
+            account("a4", "EmptyMint1111111111111111111111111111111111", 0, 2, 101),
+            account("a5", "NftMint111111111111111111111111111111111111", 1, 0, 101),
+            // NFT-shaped, but no metadata says it is an NFT
+            account("a6", "OneToken11111111111111111111111111111111111", 1, 0, 101),
+        ];
+
+        let native = NativeBalance { lamports: 1_000_000_000, slot: 99 };
+        let nfts = HashSet::from(["NftMint111111111111111111111111111111111111".to_string()]);
+        let balances = aggregate_balances(&accounts, native, Commitment::Finalized, &nfts);
+        assert_eq!(balances.len(), 3);
+        assert!(balances.iter().any(|b| b.token_address.starts_with("OneToken")));
+        assert!(balances.iter().all(|b| b.commitment == Commitment::Finalized));
+
+        let usdc_balance = balances.iter().find(|b| b.token_address == usdc).unwrap();
+        assert_eq!(usdc_balance.raw_amount, 1_500_000);
+        assert_eq!(usdc_balance.amount, 1.5);
+        assert_eq!(usdc_balance.slot, 104);
+
+        let sol = balances
+            .iter()
+            .find(|b| b.token_address == NATIVE_MINT.to_string())
+            .unwrap();
+        assert_eq!(sol.raw_amount, 3_000_000_000);
+        assert_eq!(sol.decimals, NATIVE_DECIMALS);
+        assert_eq!(sol.slot, 99);
+    }
+
+    // Answers getMultipleAccounts with one empty slot per requested key
+    struct MultipleAccountsResponder;
+
+    impl Respond for MultipleAccountsResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let result = match body["method"].as_str() {
+                Some("getMultipleAccounts") => {
+                    let keys = body["params"][0].as_array().unwrap().len();
+                    json!({ "context": { "slot": 1 }, "value": vec![serde_json::Value::Null; keys] })
+                }
+                _ => json!({ "solana-core": "1.17.26", "feature-set": 0 }),
+            };
+            ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0", "result": result, "id": body["id"]
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_multiple_accounts_is_chunked() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(MultipleAccountsResponder)
+            .mount(&server)
+            .await;
+
+        let pool = RpcPool::new(
+            vec![EndpointConfig { url: server.uri(), weight: 1.0 }],
+            RetryPolicy::default(),
+            50,
+        )
+        .unwrap();
+        let client = SolanaClient::with_pool(Arc::new(pool));
+
+        let pubkeys: Vec<Pubkey> = (0..250).map(|_| Pubkey::new_unique()).collect();
+        let accounts = client.get_multiple_accounts(&pubkeys).await.unwrap();
+        assert_eq!(accounts.len(), 250);
+
+        let chunk_sizes: Vec<usize> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).ok()?;
+                (body["method"] == "getMultipleAccounts")
+                    .then(|| body["params"][0].as_array().unwrap().len())
+            })
+            .collect();
+        let mut sorted = chunk_sizes.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, vec![50, 100, 100]);
+    }
+
+    // Answers a wallet balance lookup: no token accounts and 2 SOL
+    struct WalletResponder;
+
+    impl Respond for WalletResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let result = match body["method"].as_str() {
+                Some("getTokenAccountsByOwner") => json!({ "context": { "slot": 7 }, "value": [] }),
+                Some("getBalance") => json!({ "context": { "slot": 8 }, "value": 2_000_000_000u64 }),
+                _ => json!({ "solana-core": "1.17.26", "feature-set": 0 }),
+            };
+            ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0", "result": result, "id": body["id"]
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recorded_wallet_replays_offline() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(WalletResponder)
+            .mount(&server)
+            .await;
+        let pool = RpcPool::new(
+            vec![EndpointConfig { url: server.uri(), weight: 1.0 }],
+            RetryPolicy::default(),
+            50,
+        )
+        .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("wallet-fixtures-{}", Pubkey::new_unique()));
+        let recorder = replay::RecordingSender::new(PooledSender(Arc::new(pool)), &dir).unwrap();
+        let wallet = Pubkey::new_unique().to_string();
+
+        let recorded = SolanaClient::with_sender(Arc::new(recorder))
+            .get_wallet_tokens(&wallet, Commitment::Confirmed)
+            .await
+            .unwrap();
+        drop(server);
+
+        let replayed = SolanaClient::with_sender(Arc::new(ReplaySender::load(&dir).unwrap()))
+            .get_wallet_tokens(&wallet, Commitment::Confirmed)
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].raw_amount, recorded[0].raw_amount);
+        assert_eq!(replayed[0].raw_amount, 2_000_000_000);
+        assert_eq!(replayed[0].slot, 8);
+    }
+
+    // Answers getBlock with a single SOL transfer of 1 SOL from `from` to `to`
+    struct TransferBlockResponder {
+        from: solana_sdk::signature::Keypair,
+        to: Pubkey,
+    }
+
+    impl Respond for TransferBlockResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            use solana_transaction_status::Encodable;
+
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let transfer = solana_sdk::system_transaction::transfer(
+                &self.from,
+                &self.to,
+                1_000_000_000,
+                solana_sdk::hash::Hash::default(),
+            );
+            let result = json!({
+                "previousBlockhash": "11111111111111111111111111111111",
+                "blockhash": "11111111111111111111111111111111",
+                "parentSlot": 99,
+                "blockTime": 1_700_000_000,
+                "blockHeight": 90,
+                "transactions": [{
+                    "transaction": transfer.encode(UiTransactionEncoding::Base64),
+                    "meta": {
+                        "err": null,
+                        "status": { "Ok": null },
+                        "fee": 5000,
+                        "preBalances": [3_000_000_000u64, 0, 1],
+                        "postBalances": [1_999_995_000u64, 1_000_000_000u64, 1]
+                    },
+                    "version": "legacy"
+                }]
+            });
+            ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0", "result": result, "id": body["id"]
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block_transaction_is_stored_per_tracked_wallet() {
+        use solana_sdk::signer::Signer;
+
+        let from = solana_sdk::signature::Keypair::new();
+        let sender = from.pubkey();
+        let receiver = Pubkey::new_unique();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(TransferBlockResponder { from, to: receiver })
+            .mount(&server)
+            .await;
+        let pool = RpcPool::new(
+            vec![EndpointConfig { url: server.uri(), weight: 1.0 }],
+            RetryPolicy::default(),
+            50,
+        )
+        .unwrap();
+        let client = SolanaClient::with_pool(Arc::new(pool));
+
+        let tracked = HashSet::from([sender, receiver]);
+        let transactions = client
+            .get_block_transactions(100, &tracked, Commitment::Confirmed)
+            .await
+            .unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].signature, transactions[1].signature);
+
+        let seen_by = |wallet: Pubkey| {
+            transactions
+                .iter()
+                .find(|transaction| transaction.wallet == wallet.to_string())
+                .unwrap()
+        };
+        for wallet in [sender, receiver] {
+            let transaction = seen_by(wallet);
+            assert_eq!(transaction.from_address, sender.to_string());
+            assert_eq!(transaction.to_address, receiver.to_string());
+            assert_eq!(transaction.amount, 1.0);
+            assert_eq!(transaction.commitment, Commitment::Confirmed);
+        }
+    }
+
+    #[test]
+    fn test_slot_ranges_cover_only_requested_slots() {
+        assert_eq!(
+            slot_ranges(&[5_000_000, 120, 100, 100, 1_099, 1_100], 1_000),
+            vec![(100, 1_099), (1_100, 1_100), (5_000_000, 5_000_000)]
+        );
+        assert!(slot_ranges(&[], 1_000).is_empty());
+    }
+
+    #[test]
+    fn test_parse_token_account_rejects_binary_data() {
+        let data = UiAccountData::LegacyBinary(String::new());
+        assert!(parse_token_account(&data).is_none());
+    }
+}
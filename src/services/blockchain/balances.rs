@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::UiTransactionTokenBalance;
+
+use super::NATIVE_DECIMALS;
+use crate::models::BalanceChange;
+
+// Every balance a transaction changed, from its status meta. Token accounts
+// created or closed by the transaction appear on one side only and count
+// as zero on the other.
+pub fn balance_changes(
+    account_keys: &[String],
+    pre_balances: &[u64],
+    post_balances: &[u64],
+    pre_token_balances: &[UiTransactionTokenBalance],
+    post_token_balances: &[UiTransactionTokenBalance],
+) -> Vec<BalanceChange> {
+    let mut changes: Vec<BalanceChange> = account_keys
+        .iter()
+        .zip(pre_balances.iter().zip(post_balances))
+        .filter(|(_, (pre, post))| pre != post)
+        .map(|(account, (pre, post))| BalanceChange {
+            account: account.clone(),
+            owner: account.clone(),
+            mint: None,
+            decimals: NATIVE_DECIMALS,
+            pre: *pre,
+            post: *post,
+        })
+        .collect();
+
+    let mut token_accounts: BTreeMap<u8, (Option<&UiTransactionTokenBalance>, Option<&UiTransactionTokenBalance>)> =
+        BTreeMap::new();
+    for balance in pre_token_balances {
+        token_accounts.entry(balance.account_index).or_default().0 = Some(balance);
+    }
+    for balance in post_token_balances {
+        token_accounts.entry(balance.account_index).or_default().1 = Some(balance);
+    }
+
+    for (index, (pre, post)) in token_accounts {
+        let Some(account) = account_keys.get(index as usize) else {
+            continue;
+        };
+        let Some(balance) = post.or(pre) else {
+            continue;
+        };
+        let owner = [post, pre]
+            .into_iter()
+            .flatten()
+            .find_map(|balance| match &balance.owner {
+                OptionSerializer::Some(owner) => Some(owner.clone()),
+                _ => None,
+            });
+        // Nodes that predate owner recording leave it unset; those balances
+        // cannot be attributed to a wallet
+        let Some(owner) = owner else {
+            continue;
+        };
+        let pre = pre.map(raw_amount).unwrap_or_default();
+        let post = post.map(raw_amount).unwrap_or_default();
+        if pre != post {
+            changes.push(BalanceChange {
+                account: account.clone(),
+                owner,
+                mint: Some(balance.mint.clone()),
+                decimals: balance.ui_token_amount.decimals,
+                pre,
+                post,
+            });
+        }
+    }
+    changes
+}
+
+fn raw_amount(balance: &UiTransactionTokenBalance) -> u64 {
+    balance.ui_token_amount.amount.parse().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account_decoder::parse_token::UiTokenAmount;
+
+    fn token_balance(account_index: u8, owner: &str, amount: u64) -> UiTransactionTokenBalance {
+        UiTransactionTokenBalance {
+            account_index,
+            mint: "mint".to_string(),
+            ui_token_amount: UiTokenAmount {
+                ui_amount: None,
+                decimals: 6,
+                amount: amount.to_string(),
+                ui_amount_string: String::new(),
+            },
+            owner: OptionSerializer::Some(owner.to_string()),
+            program_id: OptionSerializer::None,
+        }
+    }
+
+    #[test]
+    fn test_balance_changes() {
+        let keys: Vec<String> = ["payer", "source", "destination", "untouched"]
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+        let changes = balance_changes(
+            &keys,
+            &[1_000_000, 2_039_280, 0, 5],
+            &[995_000, 2_039_280, 2_039_280, 5],
+            &[token_balance(1, "payer", 700)],
+            &[token_balance(1, "payer", 200), token_balance(2, "recipient", 500)],
+        );
+
+        assert_eq!(changes.len(), 4);
+        assert_eq!(
+            changes[0],
+            BalanceChange {
+                account: "payer".to_string(),
+                owner: "payer".to_string(),
+                mint: None,
+                decimals: 9,
+                pre: 1_000_000,
+                post: 995_000,
+            }
+        );
+        assert_eq!(
+            (changes[2].owner.as_str(), changes[2].pre, changes[2].post),
+            ("payer", 700, 200)
+        );
+        // Created by the transaction, so it has no pre balance
+        assert_eq!(
+            (changes[3].owner.as_str(), changes[3].pre, changes[3].post),
+            ("recipient", 0, 500)
+        );
+    }
+}
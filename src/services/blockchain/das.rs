@@ -0,0 +1,262 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::json;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_sender::RpcSender;
+
+use super::replay::{RecordingSender, SharedSender};
+use super::rpc_pool::{EndpointConfig, PooledSender, RetryPolicy, RpcPool};
+use crate::models::Nft;
+
+// Largest page DAS providers accept for getAssetsByOwner
+const DAS_PAGE_LIMIT: usize = 1000;
+
+pub const GET_ASSETS_BY_OWNER: &str = "getAssetsByOwner";
+
+// Client for the Metaplex Digital Asset Standard read API. Compressed NFTs
+// have no token account, so they are only visible through an indexer that
+// implements it (any DAS-compatible provider, or a local fixture server).
+// DAS is JSON-RPC, so it goes through an RpcSender like the Solana RPC and
+// is recorded and replayed with it.
+pub struct DasClient {
+    sender: Arc<dyn RpcSender + Send + Sync>,
+    page_limit: usize,
+}
+
+#[derive(Deserialize)]
+struct AssetPage {
+    items: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    id: String,
+    #[serde(default)]
+    content: AssetContent,
+    #[serde(default)]
+    grouping: Vec<AssetGroup>,
+    #[serde(default)]
+    compression: Option<AssetCompression>,
+}
+
+#[derive(Default, Deserialize)]
+struct AssetContent {
+    #[serde(default)]
+    json_uri: String,
+    #[serde(default)]
+    metadata: AssetMetadata,
+}
+
+#[derive(Default, Deserialize)]
+struct AssetMetadata {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    symbol: String,
+}
+
+#[derive(Deserialize)]
+struct AssetGroup {
+    group_key: String,
+    group_value: String,
+    // Anyone can claim a collection; only the collection authority can
+    // verify the claim. Providers that leave this out are not trusted.
+    #[serde(default)]
+    verified: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct AssetCompression {
+    compressed: bool,
+}
+
+impl DasClient {
+    pub fn new(url: String) -> Result<Self> {
+        let pool = RpcPool::new(
+            vec![EndpointConfig { url, weight: 1.0 }],
+            RetryPolicy::default(),
+            u64::MAX,
+        )?;
+        Ok(Self::with_sender(Arc::new(PooledSender(Arc::new(pool)))))
+    }
+
+    pub fn with_sender(sender: Arc<dyn RpcSender + Send + Sync>) -> Self {
+        Self {
+            sender,
+            page_limit: DAS_PAGE_LIMIT,
+        }
+    }
+
+    // Writes every answer to the RPC fixture directory as well
+    pub fn recording(self, dir: impl Into<PathBuf>) -> Result<Self> {
+        let recorder = RecordingSender::new(SharedSender(self.sender), dir)?;
+        Ok(Self {
+            sender: Arc::new(recorder),
+            page_limit: self.page_limit,
+        })
+    }
+
+    // Compressed NFT support is optional; without DAS_RPC_URL only
+    // uncompressed NFTs are reported
+    pub fn from_env() -> Result<Option<Self>> {
+        std::env::var("DAS_RPC_URL").ok().map(Self::new).transpose()
+    }
+
+    // Uncompressed assets are skipped; those are read from chain directly
+    pub async fn get_compressed_nfts(&self, owner: &str) -> Result<Vec<Nft>> {
+        let mut nfts = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let items = self.get_assets_by_owner(owner, page).await?;
+            let last_page = items.len() < self.page_limit;
+
+            nfts.extend(
+                items
+                    .into_iter()
+                    .filter(|asset| asset.compression.as_ref().is_some_and(|c| c.compressed))
+                    .map(|asset| Nft {
+                        collection: asset
+                            .grouping
+                            .into_iter()
+                            .find(|group| group.group_key == "collection" && group.verified == Some(true))
+                            .map(|group| group.group_value),
+                        mint: asset.id,
+                        name: asset.content.metadata.name,
+                        symbol: asset.content.metadata.symbol,
+                        uri: asset.content.json_uri,
+                        compressed: true,
+                    }),
+            );
+
+            if last_page {
+                return Ok(nfts);
+            }
+            page += 1;
+        }
+    }
+
+    async fn get_assets_by_owner(&self, owner: &str, page: usize) -> Result<Vec<Asset>> {
+        let result = self
+            .sender
+            .send(
+                RpcRequest::Custom {
+                    method: GET_ASSETS_BY_OWNER,
+                },
+                json!({
+                    "ownerAddress": owner,
+                    "page": page,
+                    "limit": self.page_limit,
+                }),
+            )
+            .await
+            .map_err(|e| anyhow!("{} failed: {}", GET_ASSETS_BY_OWNER, e))?;
+
+        let page: Option<AssetPage> = serde_json::from_value(result)?;
+        Ok(page.map(|page| page.items).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::blockchain::replay::ReplaySender;
+    use serde_json::Value;
+    use solana_sdk::pubkey::Pubkey;
+    use wiremock::matchers::{body_partial_json, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn asset(id: &str, compressed: bool, collection: Option<(&str, bool)>) -> Value {
+        json!({
+            "interface": "V1_NFT",
+            "id": id,
+            "content": {
+                "json_uri": format!("https://example.com/{}.json", id),
+                "metadata": { "name": format!("Asset {}", id), "symbol": "CNFT" }
+            },
+            "grouping": collection
+                .map(|(c, verified)| vec![json!({ "group_key": "collection", "group_value": c, "verified": verified })])
+                .unwrap_or_default(),
+            "compression": { "compressed": compressed, "tree": "tree" },
+            "ownership": { "owner": "owner" }
+        })
+    }
+
+    async fn mount_page(server: &MockServer, page: usize, items: Vec<Value>) {
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "method": "getAssetsByOwner",
+                "params": { "ownerAddress": "owner", "page": page }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": "insight-wallet",
+                "result": { "total": items.len(), "limit": 2, "page": page, "items": items }
+            })))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_compressed_nfts_pages_through_results() {
+        let server = MockServer::start().await;
+        mount_page(&server, 1, vec![asset("a", true, Some(("collection", true))), asset("b", false, None)]).await;
+        mount_page(&server, 2, vec![asset("c", true, Some(("collection", false)))]).await;
+
+        let mut client = DasClient::new(server.uri()).unwrap();
+        client.page_limit = 2;
+
+        let nfts = client.get_compressed_nfts("owner").await.unwrap();
+        assert_eq!(nfts.len(), 2);
+        assert_eq!(nfts[0].mint, "a");
+        assert_eq!(nfts[0].name, "Asset a");
+        assert_eq!(nfts[0].collection.as_deref(), Some("collection"));
+        assert!(nfts[0].compressed);
+        // Claims an unverified collection
+        assert_eq!(nfts[1].mint, "c");
+        assert_eq!(nfts[1].collection, None);
+    }
+
+    #[tokio::test]
+    async fn test_das_error_is_surfaced() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": "insight-wallet",
+                "error": { "code": -32601, "message": "Method not found" }
+            })))
+            .mount(&server)
+            .await;
+
+        let client = DasClient::new(server.uri()).unwrap();
+        assert!(client.get_compressed_nfts("owner").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recorded_das_traffic_replays_offline() {
+        let server = MockServer::start().await;
+        mount_page(&server, 1, vec![asset("a", true, Some(("collection", true)))]).await;
+        let dir = std::env::temp_dir().join(format!("das-fixtures-{}", Pubkey::new_unique()));
+
+        let recorder = DasClient::new(server.uri()).unwrap().recording(&dir).unwrap();
+        let recorded = recorder.get_compressed_nfts("owner").await.unwrap();
+        drop(server);
+
+        let replay = ReplaySender::load(&dir).unwrap();
+        assert!(replay.has_method(GET_ASSETS_BY_OWNER));
+        let replayed = DasClient::with_sender(Arc::new(replay))
+            .get_compressed_nfts("owner")
+            .await
+            .unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].mint, recorded[0].mint);
+        assert_eq!(replayed[0].collection, recorded[0].collection);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_program;
+
+use super::fees::{ComputeBudgetDecoder, COMPUTE_BUDGET_PROGRAM_ID};
+use super::swaps::{
+    JupiterDecoder, OrcaWhirlpoolDecoder, RaydiumAmmDecoder, RaydiumClmmDecoder,
+    JUPITER_V6_PROGRAM_ID, ORCA_WHIRLPOOL_PROGRAM_ID, RAYDIUM_AMM_V4_PROGRAM_ID,
+    RAYDIUM_CLMM_PROGRAM_ID,
+};
+use super::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use crate::models::{EventKind, TransactionEvent};
+
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+// An instruction with its account indexes already resolved against the
+// transaction's account keys
+pub struct InstructionContext<'a> {
+    pub program_id: &'a Pubkey,
+    pub accounts: Vec<&'a Pubkey>,
+    pub data: &'a [u8],
+}
+
+impl InstructionContext<'_> {
+    pub fn account(&self, index: usize) -> Option<String> {
+        self.accounts.get(index).map(|key| key.to_string())
+    }
+}
+
+pub trait InstructionDecoder: Send + Sync {
+    // Returns None for instructions this decoder does not understand; the
+    // registry records those as unknown
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind>;
+}
+
+#[derive(Clone, Default)]
+pub struct DecoderRegistry {
+    decoders: HashMap<Pubkey, Arc<dyn InstructionDecoder>>,
+}
+
+impl DecoderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_builtin_decoders() -> Self {
+        let mut registry = Self::new();
+        registry.register(system_program::id(), Arc::new(SystemDecoder));
+        registry.register(TOKEN_PROGRAM_ID, Arc::new(TokenDecoder));
+        registry.register(TOKEN_2022_PROGRAM_ID, Arc::new(TokenDecoder));
+        registry.register(ASSOCIATED_TOKEN_PROGRAM_ID, Arc::new(AssociatedTokenDecoder));
+        registry.register(COMPUTE_BUDGET_PROGRAM_ID, Arc::new(ComputeBudgetDecoder));
+        registry.register(JUPITER_V6_PROGRAM_ID, Arc::new(JupiterDecoder));
+        registry.register(RAYDIUM_AMM_V4_PROGRAM_ID, Arc::new(RaydiumAmmDecoder));
+        registry.register(RAYDIUM_CLMM_PROGRAM_ID, Arc::new(RaydiumClmmDecoder));
+        registry.register(ORCA_WHIRLPOOL_PROGRAM_ID, Arc::new(OrcaWhirlpoolDecoder));
+        registry
+    }
+
+    pub fn register(&mut self, program_id: Pubkey, decoder: Arc<dyn InstructionDecoder>) {
+        self.decoders.insert(program_id, decoder);
+    }
+
+    pub fn decode_instruction(
+        &self,
+        account_keys: &[Pubkey],
+        instruction: &CompiledInstruction,
+        instruction_index: usize,
+        inner_index: Option<usize>,
+    ) -> TransactionEvent {
+        let program_id = account_keys
+            .get(instruction.program_id_index as usize)
+            .copied()
+            .unwrap_or_default();
+
+        let kind = self
+            .decoders
+            .get(&program_id)
+            .and_then(|decoder| {
+                let context = InstructionContext {
+                    program_id: &program_id,
+                    accounts: instruction
+                        .accounts
+                        .iter()
+                        .filter_map(|index| account_keys.get(*index as usize))
+                        .collect(),
+                    data: &instruction.data,
+                };
+                decoder.decode(&context)
+            })
+            .unwrap_or(EventKind::Unknown {
+                data_len: instruction.data.len(),
+            });
+
+        TransactionEvent {
+            program_id: program_id.to_string(),
+            instruction_index,
+            inner_index,
+            kind,
+        }
+    }
+
+    // Decodes every top-level instruction followed by the inner instructions
+    // it invoked, keyed by the top-level index as reported in the status meta
+    pub fn decode_transaction(
+        &self,
+        account_keys: &[Pubkey],
+        instructions: &[CompiledInstruction],
+        inner_instructions: &HashMap<usize, Vec<CompiledInstruction>>,
+    ) -> Vec<TransactionEvent> {
+        let mut events = Vec::new();
+
+        for (index, instruction) in instructions.iter().enumerate() {
+            events.push(self.decode_instruction(account_keys, instruction, index, None));
+
+            if let Some(inner) = inner_instructions.get(&index) {
+                for (inner_index, inner_instruction) in inner.iter().enumerate() {
+                    events.push(self.decode_instruction(
+                        account_keys,
+                        inner_instruction,
+                        index,
+                        Some(inner_index),
+                    ));
+                }
+            }
+        }
+
+        events
+    }
+}
+
+pub struct SystemDecoder;
+
+impl InstructionDecoder for SystemDecoder {
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind> {
+        let tag = u32::from_le_bytes(instruction.data.get(..4)?.try_into().ok()?);
+        match tag {
+            // Transfer { lamports }
+            2 => Some(EventKind::SolTransfer {
+                from: instruction.account(0)?,
+                to: instruction.account(1)?,
+                lamports: read_u64(instruction.data, 4)?,
+            }),
+            // TransferWithSeed { lamports, from_seed, from_owner }
+            11 => Some(EventKind::SolTransfer {
+                from: instruction.account(0)?,
+                to: instruction.account(2)?,
+                lamports: read_u64(instruction.data, 4)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Handles both SPL Token and Token-2022, which share these instruction layouts
+pub struct TokenDecoder;
+
+impl InstructionDecoder for TokenDecoder {
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind> {
+        let (tag, rest) = instruction.data.split_first()?;
+        match tag {
+            // Transfer { amount }
+            3 => Some(EventKind::TokenTransfer {
+                source: instruction.account(0)?,
+                destination: instruction.account(1)?,
+                authority: instruction.account(2)?,
+                mint: None,
+                amount: read_u64(rest, 0)?,
+                decimals: None,
+                destination_owner: None,
+            }),
+            // MintTo { amount } / MintToChecked { amount, decimals }
+            7 | 14 => Some(EventKind::TokenMint {
+                mint: instruction.account(0)?,
+                destination: instruction.account(1)?,
+                authority: instruction.account(2)?,
+                amount: read_u64(rest, 0)?,
+            }),
+            // Burn { amount } / BurnChecked { amount, decimals }
+            8 | 15 => Some(EventKind::TokenBurn {
+                account: instruction.account(0)?,
+                mint: instruction.account(1)?,
+                authority: instruction.account(2)?,
+                amount: read_u64(rest, 0)?,
+            }),
+            // CloseAccount
+            9 => Some(EventKind::TokenAccountClosed {
+                account: instruction.account(0)?,
+                destination: instruction.account(1)?,
+                authority: instruction.account(2)?,
+            }),
+            // TransferChecked { amount, decimals }
+            12 => Some(EventKind::TokenTransfer {
+                source: instruction.account(0)?,
+                destination: instruction.account(2)?,
+                authority: instruction.account(3)?,
+                mint: Some(instruction.account(1)?),
+                amount: read_u64(rest, 0)?,
+                decimals: rest.get(8).copied(),
+                destination_owner: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub struct AssociatedTokenDecoder;
+
+impl InstructionDecoder for AssociatedTokenDecoder {
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind> {
+        // Create (empty data or 0) and CreateIdempotent (1) share an account layout
+        match instruction.data.first() {
+            None | Some(0) | Some(1) => Some(EventKind::TokenAccountCreated {
+                payer: instruction.account(0)?,
+                account: instruction.account(1)?,
+                owner: instruction.account(2)?,
+                mint: instruction.account(3)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiled(program_id_index: u8, accounts: Vec<u8>, data: Vec<u8>) -> CompiledInstruction {
+        CompiledInstruction {
+            program_id_index,
+            accounts,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_decode_system_transfer() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let keys = vec![from, to, system_program::id()];
+
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&1_500_000_000u64.to_le_bytes());
+
+        let registry = DecoderRegistry::with_builtin_decoders();
+        let event = registry.decode_instruction(&keys, &compiled(2, vec![0, 1], data), 0, None);
+
+        assert_eq!(event.program_id, system_program::id().to_string());
+        match event.kind {
+            EventKind::SolTransfer { from: f, to: t, lamports } => {
+                assert_eq!(f, from.to_string());
+                assert_eq!(t, to.to_string());
+                assert_eq!(lamports, 1_500_000_000);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_transfer_checked_for_both_token_programs() {
+        let registry = DecoderRegistry::with_builtin_decoders();
+
+        for program_id in [TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID] {
+            let (source, mint, destination, owner) = (
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+            );
+            let keys = vec![source, mint, destination, owner, program_id];
+
+            let mut data = vec![12];
+            data.extend_from_slice(&42_000u64.to_le_bytes());
+            data.push(6);
+
+            let event = registry.decode_instruction(&keys, &compiled(4, vec![0, 1, 2, 3], data), 1, Some(0));
+            assert_eq!(event.inner_index, Some(0));
+            match event.kind {
+                EventKind::TokenTransfer { mint: m, amount, decimals, destination: d, .. } => {
+                    assert_eq!(m, Some(mint.to_string()));
+                    assert_eq!(d, destination.to_string());
+                    assert_eq!(amount, 42_000);
+                    assert_eq!(decimals, Some(6));
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_associated_token_account_creation() {
+        let mut keys: Vec<Pubkey> = (0..6).map(|_| Pubkey::new_unique()).collect();
+        keys.push(ASSOCIATED_TOKEN_PROGRAM_ID);
+
+        let registry = DecoderRegistry::with_builtin_decoders();
+        let event = registry.decode_instruction(&keys, &compiled(6, vec![0, 1, 2, 3, 4, 5], vec![1]), 0, None);
+
+        assert!(matches!(
+            event.kind,
+            EventKind::TokenAccountCreated { ref owner, .. } if *owner == keys[2].to_string()
+        ));
+    }
+
+    #[test]
+    fn test_unknown_program_is_recorded() {
+        let program_id = Pubkey::new_unique();
+        let keys = vec![Pubkey::new_unique(), program_id];
+
+        let registry = DecoderRegistry::with_builtin_decoders();
+        let event = registry.decode_instruction(&keys, &compiled(1, vec![0], vec![9, 9, 9]), 3, None);
+
+        assert_eq!(event.program_id, program_id.to_string());
+        assert_eq!(event.instruction_index, 3);
+        assert!(matches!(event.kind, EventKind::Unknown { data_len: 3 }));
+    }
+
+    #[test]
+    fn test_inner_instructions_follow_their_parent() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let outer_program = Pubkey::new_unique();
+        let keys = vec![from, to, system_program::id(), outer_program];
+
+        let mut transfer = 2u32.to_le_bytes().to_vec();
+        transfer.extend_from_slice(&10u64.to_le_bytes());
+
+        let instructions = vec![compiled(3, vec![0, 1], vec![]), compiled(2, vec![0, 1], transfer.clone())];
+        let inner = HashMap::from([(0, vec![compiled(2, vec![0, 1], transfer)])]);
+
+        let registry = DecoderRegistry::with_builtin_decoders();
+        let events = registry.decode_transaction(&keys, &instructions, &inner);
+
+        let positions: Vec<(usize, Option<usize>)> =
+            events.iter().map(|e| (e.instruction_index, e.inner_index)).collect();
+        assert_eq!(positions, vec![(0, None), (0, Some(0)), (1, None)]);
+    }
+}
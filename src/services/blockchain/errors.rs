@@ -0,0 +1,305 @@
+use std::str::FromStr;
+
+use solana_sdk::instruction::{CompiledInstruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_program;
+use solana_sdk::transaction::TransactionError;
+
+use super::swaps::{JUPITER_V6_PROGRAM_ID, ORCA_WHIRLPOOL_PROGRAM_ID, RAYDIUM_CLMM_PROGRAM_ID};
+use super::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use crate::models::TransactionFailure;
+
+// Log lines kept per failure, counted back from the error itself
+const MAX_FAILURE_LOG_LINES: usize = 20;
+
+// SPL Token's `TokenError`, indexed by code; Token-2022 shares it
+const TOKEN_ERRORS: [(&str, &str); 20] = [
+    ("NotRentExempt", "Lamport balance below rent-exempt threshold"),
+    ("InsufficientFunds", "Insufficient funds"),
+    ("InvalidMint", "Invalid mint"),
+    ("MintMismatch", "Account not associated with this mint"),
+    ("OwnerMismatch", "Owner does not match"),
+    ("FixedSupply", "Fixed supply"),
+    ("AlreadyInUse", "Already in use"),
+    ("InvalidNumberOfProvidedSigners", "Invalid number of provided signers"),
+    ("InvalidNumberOfRequiredSigners", "Invalid number of required signers"),
+    ("UninitializedState", "State is uninitialized"),
+    ("NativeNotSupported", "Instruction does not support native tokens"),
+    ("NonNativeHasBalance", "Non-native account can only be closed if its balance is zero"),
+    ("InvalidInstruction", "Invalid instruction"),
+    ("InvalidState", "State is invalid for requested operation"),
+    ("Overflow", "Operation overflowed"),
+    ("AuthorityTypeNotSupported", "Account does not support specified authority type"),
+    ("MintCannotFreeze", "This token mint cannot freeze accounts"),
+    ("AccountFrozen", "Account is frozen"),
+    ("MintDecimalsMismatch", "The provided decimals value different from the mint decimals"),
+    ("NonNativeNotSupported", "Instruction does not support non-native tokens"),
+];
+
+// The System program's `SystemError`, indexed by code
+const SYSTEM_ERRORS: [(&str, &str); 9] = [
+    ("AccountAlreadyInUse", "An account with the same address already exists"),
+    ("ResultWithNegativeLamports", "Account does not have enough SOL to perform the operation"),
+    ("InvalidProgramId", "Cannot assign account to this program id"),
+    ("InvalidAccountDataLength", "Cannot allocate account data of this length"),
+    ("MaxSeedLengthExceeded", "Length of requested seed is too long"),
+    ("AddressWithSeedMismatch", "Provided address does not match addressed derived from seed"),
+    ("NonceNoRecentBlockhashes", "Advancing stored nonce requires a populated RecentBlockhashes sysvar"),
+    ("NonceBlockhashNotExpired", "Stored nonce is still in recent_blockhashes"),
+    ("NonceUnexpectedBlockhashValue", "Specified nonce does not match stored nonce"),
+];
+
+// Jupiter v6's program errors, which start at Anchor's 6000 offset
+const JUPITER_ERROR_OFFSET: u32 = 6000;
+const JUPITER_ERRORS: [(&str, &str); 19] = [
+    ("EmptyRoute", "Empty route"),
+    ("SlippageToleranceExceeded", "Slippage tolerance exceeded"),
+    ("InvalidCalculation", "Invalid calculation"),
+    ("MissingPlatformFeeAccount", "Missing platform fee account"),
+    ("InvalidSlippage", "Invalid slippage"),
+    ("NotEnoughPercent", "Not enough percent to 100"),
+    ("InvalidInputIndex", "Token input index is invalid"),
+    ("InvalidOutputIndex", "Token output index is invalid"),
+    ("NotEnoughAccountKeys", "Not enough account keys"),
+    ("NonZeroMinimumOutAmountNotSupported", "Non zero minimum out amount not supported"),
+    ("InvalidRoutePlan", "Invalid route plan"),
+    ("InvalidReferralAuthority", "Invalid referral authority"),
+    ("LedgerTokenAccountDoesNotMatch", "Token account doesn't match the ledger"),
+    ("InvalidTokenLedger", "Invalid token ledger"),
+    ("IncorrectTokenProgramID", "Token program ID is invalid"),
+    ("TokenProgramNotProvided", "Token program not provided"),
+    ("SwapNotSupported", "Swap not supported"),
+    ("ExactOutAmountNotMatched", "Exact out amount doesn't match"),
+    ("SourceAndDestinationMintCannotBeTheSame", "Source mint and destination mint cannot the same"),
+];
+
+// Anchor framework errors any Anchor program can return, mostly account
+// constraint violations
+const ANCHOR_ERRORS: [(u32, &str, &str); 12] = [
+    (2000, "ConstraintMut", "A mut constraint was violated"),
+    (2001, "ConstraintHasOne", "A has one constraint was violated"),
+    (2002, "ConstraintSigner", "A signer constraint was violated"),
+    (2003, "ConstraintRaw", "A raw constraint was violated"),
+    (2006, "ConstraintSeeds", "A seeds constraint was violated"),
+    (2012, "ConstraintAddress", "An address constraint was violated"),
+    (2014, "ConstraintTokenMint", "A token mint constraint was violated"),
+    (2015, "ConstraintTokenOwner", "A token owner constraint was violated"),
+    (3001, "AccountDiscriminatorNotFound", "No 8 byte discriminator was found on the account"),
+    (3002, "AccountDiscriminatorMismatch", "8 byte discriminator did not match what was expected"),
+    (3007, "AccountOwnedByWrongProgram", "The given account is owned by a different program than expected"),
+    (3012, "AccountNotInitialized", "The program expected this account to be already initialized"),
+];
+
+fn is_anchor_program(program_id: &Pubkey) -> bool {
+    [JUPITER_V6_PROGRAM_ID, ORCA_WHIRLPOOL_PROGRAM_ID, RAYDIUM_CLMM_PROGRAM_ID].contains(program_id)
+}
+
+// Name and description of `program_id`'s custom error `code`, when the
+// program's error table is known
+pub fn lookup_program_error(program_id: &Pubkey, code: u32) -> Option<(&'static str, &'static str)> {
+    let indexed = |table: &[(&'static str, &'static str)], index: u32| table.get(index as usize).copied();
+
+    if *program_id == TOKEN_PROGRAM_ID || *program_id == TOKEN_2022_PROGRAM_ID {
+        return indexed(&TOKEN_ERRORS, code);
+    }
+    if *program_id == system_program::id() {
+        return indexed(&SYSTEM_ERRORS, code);
+    }
+    if *program_id == JUPITER_V6_PROGRAM_ID && code >= JUPITER_ERROR_OFFSET {
+        return indexed(&JUPITER_ERRORS, code - JUPITER_ERROR_OFFSET);
+    }
+    if is_anchor_program(program_id) {
+        return ANCHOR_ERRORS
+            .iter()
+            .find(|(anchor_code, _, _)| *anchor_code == code)
+            .map(|(_, name, message)| (*name, *message));
+    }
+    None
+}
+
+// Explains a transaction error. Errors raised by an instruction point at
+// the program that raised them, which may be one the instruction invoked,
+// and keep the log lines the instruction emitted; custom codes are looked
+// up in that program's error table.
+pub fn diagnose_failure(
+    error: &TransactionError,
+    account_keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+    logs: &[String],
+) -> TransactionFailure {
+    let mut failure = TransactionFailure {
+        instruction_index: None,
+        program_id: None,
+        error: format!("{:?}", error),
+        code: None,
+        name: None,
+        message: Some(error.to_string()),
+        logs: Vec::new(),
+    };
+
+    let TransactionError::InstructionError(index, instruction_error) = error else {
+        failure.name = Some(variant_name(error));
+        return failure;
+    };
+    let index = *index as usize;
+    let segment = instruction_logs(logs, index);
+    let program_id = failing_program(segment).or_else(|| {
+        instructions
+            .get(index)
+            .and_then(|instruction| account_keys.get(instruction.program_id_index as usize))
+            .copied()
+    });
+    let program_id = program_id.as_ref();
+
+    failure.instruction_index = Some(index);
+    failure.program_id = program_id.map(|program_id| program_id.to_string());
+    failure.logs = segment[segment.len().saturating_sub(MAX_FAILURE_LOG_LINES)..].to_vec();
+
+    match instruction_error {
+        InstructionError::Custom(code) => {
+            failure.code = Some(*code);
+            let known = program_id.and_then(|program_id| lookup_program_error(program_id, *code));
+            failure.name = known.map(|(name, _)| name.to_string());
+            failure.message = known.map(|(_, message)| message.to_string());
+        }
+        other => {
+            failure.name = Some(variant_name(other));
+            failure.message = Some(other.to_string());
+        }
+    }
+    failure
+}
+
+// The variant name out of an error's Debug form, without its fields
+fn variant_name(error: &impl std::fmt::Debug) -> String {
+    let debug = format!("{:?}", error);
+    debug.split(['(', ' ', '{']).next().unwrap_or_default().to_string()
+}
+
+// The program that raised the error, from the first `Program <id> failed:`
+// line. A failing CPI logs its failure first, then every caller up the
+// stack logs the same error again as it unwinds.
+fn failing_program(logs: &[String]) -> Option<Pubkey> {
+    logs.iter().find_map(|line| {
+        let (program, _) = line.strip_prefix("Program ")?.split_once(" failed: ")?;
+        Pubkey::from_str(program).ok()
+    })
+}
+
+// Lines logged between the failing top-level instruction's `invoke [1]`
+// and the next one
+fn instruction_logs(logs: &[String], instruction_index: usize) -> &[String] {
+    let starts: Vec<usize> = logs
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.starts_with("Program ") && line.ends_with(" invoke [1]"))
+        .map(|(position, _)| position)
+        .collect();
+    let Some(&start) = starts.get(instruction_index) else {
+        return &[];
+    };
+    let end = starts.get(instruction_index + 1).copied().unwrap_or(logs.len());
+    &logs[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logs() -> Vec<String> {
+        [
+            "Program ComputeBudget111111111111111111111111111111 invoke [1]",
+            "Program ComputeBudget111111111111111111111111111111 success",
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 invoke [1]",
+            "Program log: Instruction: Route",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA success",
+            "Program log: AnchorError occurred. Error Code: SlippageToleranceExceeded. Error Number: 6001.",
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 failed: custom program error: 0x1771",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+    }
+
+    fn instruction(program_id_index: u8) -> CompiledInstruction {
+        CompiledInstruction {
+            program_id_index,
+            accounts: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_diagnose_custom_program_error() {
+        let keys = vec![Pubkey::new_unique(), Pubkey::new_unique(), JUPITER_V6_PROGRAM_ID];
+        let instructions = vec![instruction(1), instruction(2)];
+        let error = TransactionError::InstructionError(1, InstructionError::Custom(6001));
+
+        let failure = diagnose_failure(&error, &keys, &instructions, &logs());
+        assert_eq!(failure.instruction_index, Some(1));
+        assert_eq!(failure.program_id, Some(JUPITER_V6_PROGRAM_ID.to_string()));
+        assert_eq!(failure.error, "InstructionError(1, Custom(6001))");
+        assert_eq!(failure.code, Some(6001));
+        assert_eq!(failure.name.as_deref(), Some("SlippageToleranceExceeded"));
+        assert_eq!(failure.logs.len(), 6);
+        assert!(failure.logs[5].contains("failed: custom program error"));
+    }
+
+    #[test]
+    fn test_diagnose_failed_cpi() {
+        // Jupiter's transfer through SPL Token ran out of funds
+        let logs: Vec<String> = [
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 invoke [1]",
+            "Program log: Instruction: Route",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]",
+            "Program log: Instruction: Transfer",
+            "Program log: Error: insufficient funds",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA failed: custom program error: 0x1",
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 failed: custom program error: 0x1",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+        let keys = vec![Pubkey::new_unique(), JUPITER_V6_PROGRAM_ID, TOKEN_PROGRAM_ID];
+        let error = TransactionError::InstructionError(0, InstructionError::Custom(1));
+
+        let failure = diagnose_failure(&error, &keys, &[instruction(1)], &logs);
+        assert_eq!(failure.instruction_index, Some(0));
+        assert_eq!(failure.program_id, Some(TOKEN_PROGRAM_ID.to_string()));
+        assert_eq!(failure.name.as_deref(), Some("InsufficientFunds"));
+        assert_eq!(failure.logs.len(), 7);
+    }
+
+    #[test]
+    fn test_diagnose_builtin_errors() {
+        let keys = vec![Pubkey::new_unique(), TOKEN_PROGRAM_ID];
+        let error = TransactionError::InstructionError(0, InstructionError::Custom(1));
+        let failure = diagnose_failure(&error, &keys, &[instruction(1)], &[]);
+        assert_eq!(failure.name.as_deref(), Some("InsufficientFunds"));
+
+        let error = TransactionError::InstructionError(0, InstructionError::InsufficientFunds);
+        let failure = diagnose_failure(&error, &keys, &[instruction(1)], &[]);
+        assert_eq!(failure.code, None);
+        assert_eq!(failure.name.as_deref(), Some("InsufficientFunds"));
+
+        let failure = diagnose_failure(&TransactionError::BlockhashNotFound, &keys, &[], &[]);
+        assert_eq!(failure.instruction_index, None);
+        assert_eq!(failure.name.as_deref(), Some("BlockhashNotFound"));
+        assert!(failure.message.is_some());
+    }
+
+    #[test]
+    fn test_lookup_program_error() {
+        assert_eq!(
+            lookup_program_error(&system_program::id(), 1).map(|(name, _)| name),
+            Some("ResultWithNegativeLamports")
+        );
+        assert_eq!(
+            lookup_program_error(&ORCA_WHIRLPOOL_PROGRAM_ID, 2003).map(|(name, _)| name),
+            Some("ConstraintRaw")
+        );
+        assert_eq!(lookup_program_error(&TOKEN_PROGRAM_ID, 99), None);
+        assert_eq!(lookup_program_error(&Pubkey::new_unique(), 0), None);
+    }
+}
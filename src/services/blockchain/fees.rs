@@ -0,0 +1,208 @@
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+
+use super::decoder::{read_u64, InstructionContext, InstructionDecoder};
+use crate::models::{EventKind, FeeBreakdown, TransactionEvent};
+
+pub const COMPUTE_BUDGET_PROGRAM_ID: Pubkey = pubkey!("ComputeBudget111111111111111111111111111111");
+
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5000;
+// Limits the runtime applies when a transaction does not set its own
+const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u64 = 200_000;
+const MAX_COMPUTE_UNIT_LIMIT: u64 = 1_400_000;
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+
+// Transfers to any of these are tips to Jito block engine validators
+pub const JITO_TIP_ACCOUNTS: [Pubkey; 8] = [
+    pubkey!("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5"),
+    pubkey!("HFqU5x63VJqqUsTDnfVxsSN8ZSakBvcvf8LVoapgHMN"),
+    pubkey!("Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY"),
+    pubkey!("ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49"),
+    pubkey!("DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh"),
+    pubkey!("ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt"),
+    pubkey!("DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL"),
+    pubkey!("3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT"),
+];
+
+pub struct ComputeBudgetDecoder;
+
+impl InstructionDecoder for ComputeBudgetDecoder {
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind> {
+        let (tag, rest) = instruction.data.split_first()?;
+        match tag {
+            // SetComputeUnitLimit { units: u32 }
+            2 => Some(EventKind::ComputeUnitLimit {
+                units: u32::from_le_bytes(rest.get(..4)?.try_into().ok()?),
+            }),
+            // SetComputeUnitPrice { micro_lamports: u64 }
+            3 => Some(EventKind::ComputeUnitPrice {
+                micro_lamports: read_u64(rest, 0)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Splits `fee` into its signature and priority parts. Compute budget
+// settings come from the decoded top-level instructions; without an explicit
+// limit the runtime's per-instruction default applies.
+pub fn fee_breakdown(
+    message: &VersionedMessage,
+    events: &[TransactionEvent],
+    fee: u64,
+    compute_units_consumed: Option<u64>,
+) -> FeeBreakdown {
+    let base_fee = fee.min(message.header().num_required_signatures as u64 * LAMPORTS_PER_SIGNATURE);
+
+    let top_level = || events.iter().filter(|event| event.inner_index.is_none());
+    let compute_unit_price = top_level()
+        .find_map(|event| match event.kind {
+            EventKind::ComputeUnitPrice { micro_lamports } => Some(micro_lamports),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let compute_units_requested = top_level()
+        .find_map(|event| match event.kind {
+            EventKind::ComputeUnitLimit { units } => Some(units as u64),
+            _ => None,
+        })
+        .unwrap_or_else(|| {
+            let compute_budget = COMPUTE_BUDGET_PROGRAM_ID.to_string();
+            let instructions = top_level().filter(|event| event.program_id != compute_budget).count() as u64;
+            instructions * DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT
+        })
+        .min(MAX_COMPUTE_UNIT_LIMIT);
+
+    let jito_tip = events
+        .iter()
+        .filter_map(|event| match &event.kind {
+            EventKind::SolTransfer { to, lamports, .. } if is_jito_tip_account(to) => Some(*lamports),
+            _ => None,
+        })
+        .sum();
+
+    FeeBreakdown {
+        base_fee,
+        priority_fee: fee - base_fee,
+        compute_unit_price,
+        compute_units_requested,
+        compute_units_consumed,
+        jito_tip,
+    }
+}
+
+// Priority fee charged for `units` at `micro_lamports` per unit, rounded up
+// as the runtime does
+pub fn priority_fee(micro_lamports: u64, units: u64) -> u64 {
+    let micro = micro_lamports as u128 * units as u128;
+    micro.div_ceil(MICRO_LAMPORTS_PER_LAMPORT) as u64
+}
+
+fn is_jito_tip_account(address: &str) -> bool {
+    JITO_TIP_ACCOUNTS.iter().any(|account| account.to_string() == address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::blockchain::decoder::DecoderRegistry;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::{Message, MessageHeader};
+    use solana_sdk::system_program;
+
+    fn event(program_id: &Pubkey, kind: EventKind) -> TransactionEvent {
+        TransactionEvent {
+            program_id: program_id.to_string(),
+            instruction_index: 0,
+            inner_index: None,
+            kind,
+        }
+    }
+
+    fn message(signatures: u8) -> VersionedMessage {
+        VersionedMessage::Legacy(Message {
+            header: MessageHeader {
+                num_required_signatures: signatures,
+                ..MessageHeader::default()
+            },
+            ..Message::default()
+        })
+    }
+
+    #[test]
+    fn test_decode_compute_budget_instructions() {
+        let keys = vec![COMPUTE_BUDGET_PROGRAM_ID];
+        let registry = DecoderRegistry::with_builtin_decoders();
+
+        let mut limit = vec![2];
+        limit.extend_from_slice(&300_000u32.to_le_bytes());
+        let mut price = vec![3];
+        price.extend_from_slice(&25_000u64.to_le_bytes());
+
+        let instruction = |data| CompiledInstruction {
+            program_id_index: 0,
+            accounts: Vec::new(),
+            data,
+        };
+        assert!(matches!(
+            registry.decode_instruction(&keys, &instruction(limit), 0, None).kind,
+            EventKind::ComputeUnitLimit { units: 300_000 }
+        ));
+        assert!(matches!(
+            registry.decode_instruction(&keys, &instruction(price), 1, None).kind,
+            EventKind::ComputeUnitPrice { micro_lamports: 25_000 }
+        ));
+    }
+
+    #[test]
+    fn test_fee_breakdown() {
+        let events = vec![
+            event(
+                &COMPUTE_BUDGET_PROGRAM_ID,
+                EventKind::ComputeUnitLimit { units: 300_000 },
+            ),
+            event(
+                &COMPUTE_BUDGET_PROGRAM_ID,
+                EventKind::ComputeUnitPrice { micro_lamports: 25_000 },
+            ),
+            event(
+                &system_program::id(),
+                EventKind::SolTransfer {
+                    from: "payer".to_string(),
+                    to: JITO_TIP_ACCOUNTS[3].to_string(),
+                    lamports: 10_000,
+                },
+            ),
+        ];
+        let fee = LAMPORTS_PER_SIGNATURE + priority_fee(25_000, 300_000);
+
+        let fees = fee_breakdown(&message(1), &events, fee, Some(120_000));
+        assert_eq!(
+            fees,
+            FeeBreakdown {
+                base_fee: 5000,
+                priority_fee: 7500,
+                compute_unit_price: 25_000,
+                compute_units_requested: 300_000,
+                compute_units_consumed: Some(120_000),
+                jito_tip: 10_000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_fee_breakdown_defaults_compute_limit_per_instruction() {
+        let program = Pubkey::new_unique();
+        let events = vec![
+            event(&program, EventKind::Unknown { data_len: 0 }),
+            event(&program, EventKind::Unknown { data_len: 0 }),
+        ];
+
+        let fees = fee_breakdown(&message(2), &events, 10_000, None);
+        assert_eq!(fees.base_fee, 10_000);
+        assert_eq!(fees.priority_fee, 0);
+        assert_eq!(fees.compute_units_requested, 400_000);
+        assert_eq!(fees.jito_tip, 0);
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+
+// Sequential little-endian reader for Borsh / packed on-chain account layouts
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub fn at(data: &'a [u8], offset: usize) -> Self {
+        Self { data, offset }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.offset)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.read_bytes(len).map(|_| ())
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.offset + len;
+        let bytes = self.data.get(self.offset..end).ok_or_else(|| {
+            anyhow!(
+                "Account data too short: need {} bytes at offset {}, have {}",
+                len,
+                self.offset,
+                self.data.len()
+            )
+        })?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    pub fn read_pubkey(&mut self) -> Result<Pubkey> {
+        Ok(Pubkey::try_from(self.read_bytes(32)?)?)
+    }
+
+    // SPL packed `COption<Pubkey>`: a u32 tag followed by a pubkey that is
+    // always present in the layout
+    pub fn read_coption_pubkey(&mut self) -> Result<Option<Pubkey>> {
+        let tag = self.read_u32()?;
+        let key = self.read_pubkey()?;
+        Ok((tag == 1).then_some(key))
+    }
+
+    // Borsh string: u32 length prefix. Metaplex pads fixed-size fields with NULs.
+    pub fn read_string(&mut self) -> Result<String> {
+        let len = self.read_u32()? as usize;
+        let bytes = self.read_bytes(len)?;
+        Ok(String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string())
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, bail, Result};
+use solana_sdk::message::v0::{LoadedAddresses, MessageAddressTableLookup};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::UiLoadedAddresses;
+
+use super::layout::ByteReader;
+
+// Address lookup table accounts: a fixed metadata header followed by the
+// table's addresses
+const LOOKUP_TABLE_META_SIZE: usize = 56;
+
+// Lookup tables are append-only while active, so a cached copy only needs
+// refreshing when a transaction indexes past its end
+#[derive(Default)]
+pub struct LookupTableCache {
+    tables: RwLock<HashMap<Pubkey, Arc<Vec<Pubkey>>>>,
+}
+
+impl LookupTableCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, key: Pubkey, addresses: Vec<Pubkey>) {
+        self.tables.write().unwrap().insert(key, Arc::new(addresses));
+    }
+
+    // Tables the lookups need that are not cached, or cached too short
+    pub fn missing(&self, lookups: &[MessageAddressTableLookup]) -> Vec<Pubkey> {
+        let tables = self.tables.read().unwrap();
+        let mut missing: Vec<Pubkey> = lookups
+            .iter()
+            .filter(|lookup| {
+                let highest = lookup
+                    .writable_indexes
+                    .iter()
+                    .chain(&lookup.readonly_indexes)
+                    .max()
+                    .copied()
+                    .unwrap_or_default() as usize;
+                tables
+                    .get(&lookup.account_key)
+                    .is_none_or(|table| highest >= table.len())
+            })
+            .map(|lookup| lookup.account_key)
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    pub fn resolve(&self, lookups: &[MessageAddressTableLookup]) -> Result<LoadedAddresses> {
+        resolve_lookups(lookups, &self.tables.read().unwrap())
+    }
+}
+
+pub fn parse_lookup_table(data: &[u8]) -> Result<Vec<Pubkey>> {
+    if data.len() < LOOKUP_TABLE_META_SIZE || (data.len() - LOOKUP_TABLE_META_SIZE) % 32 != 0 {
+        bail!("Not an address lookup table: {} bytes", data.len());
+    }
+
+    let mut reader = ByteReader::at(data, LOOKUP_TABLE_META_SIZE);
+    let mut addresses = Vec::with_capacity(reader.remaining() / 32);
+    while reader.remaining() > 0 {
+        addresses.push(reader.read_pubkey()?);
+    }
+    Ok(addresses)
+}
+
+// Loaded addresses in runtime order: every table's writable entries first,
+// then every table's readonly entries. They follow the static keys.
+fn resolve_lookups(
+    lookups: &[MessageAddressTableLookup],
+    tables: &HashMap<Pubkey, Arc<Vec<Pubkey>>>,
+) -> Result<LoadedAddresses> {
+    let mut loaded = LoadedAddresses::default();
+
+    for lookup in lookups {
+        let table = tables
+            .get(&lookup.account_key)
+            .ok_or_else(|| anyhow!("Address lookup table {} not loaded", lookup.account_key))?;
+        let entry = |index: &u8| {
+            table.get(*index as usize).copied().ok_or_else(|| {
+                anyhow!(
+                    "Index {} out of range for lookup table {} ({} entries)",
+                    index,
+                    lookup.account_key,
+                    table.len()
+                )
+            })
+        };
+
+        for index in &lookup.writable_indexes {
+            loaded.writable.push(entry(index)?);
+        }
+        for index in &lookup.readonly_indexes {
+            loaded.readonly.push(entry(index)?);
+        }
+    }
+
+    Ok(loaded)
+}
+
+// The status meta records the addresses a transaction actually loaded, which
+// stays correct even if the table is later extended or closed
+pub fn parse_ui_loaded_addresses(loaded: &UiLoadedAddresses) -> Result<LoadedAddresses> {
+    let parse =
+        |keys: &[String]| -> Result<Vec<Pubkey>> { keys.iter().map(|key| Ok(Pubkey::from_str(key)?)).collect() };
+
+    Ok(LoadedAddresses {
+        writable: parse(&loaded.writable)?,
+        readonly: parse(&loaded.readonly)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_data(addresses: &[Pubkey]) -> Vec<u8> {
+        let mut data = vec![0u8; LOOKUP_TABLE_META_SIZE];
+        data[..4].copy_from_slice(&1u32.to_le_bytes());
+        for address in addresses {
+            data.extend_from_slice(address.as_ref());
+        }
+        data
+    }
+
+    fn lookup(account_key: Pubkey, writable: Vec<u8>, readonly: Vec<u8>) -> MessageAddressTableLookup {
+        MessageAddressTableLookup {
+            account_key,
+            writable_indexes: writable,
+            readonly_indexes: readonly,
+        }
+    }
+
+    #[test]
+    fn test_parse_lookup_table() {
+        let addresses: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        assert_eq!(parse_lookup_table(&table_data(&addresses)).unwrap(), addresses);
+        assert!(parse_lookup_table(&[0u8; 70]).is_err());
+    }
+
+    #[test]
+    fn test_writable_entries_of_all_tables_come_first() {
+        let (first_key, second_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let first: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let second: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+
+        let cache = LookupTableCache::new();
+        cache.insert(first_key, first.clone());
+        cache.insert(second_key, second.clone());
+
+        let lookups = vec![
+            lookup(first_key, vec![2], vec![0, 3]),
+            lookup(second_key, vec![1], vec![0]),
+        ];
+        let loaded = cache.resolve(&lookups).unwrap();
+
+        assert_eq!(loaded.writable, vec![first[2], second[1]]);
+        assert_eq!(loaded.readonly, vec![first[0], first[3], second[0]]);
+    }
+
+    #[test]
+    fn test_extended_table_is_refetched() {
+        let key = Pubkey::new_unique();
+        let cache = LookupTableCache::new();
+        assert_eq!(cache.missing(&[lookup(key, vec![0], vec![])]), vec![key]);
+
+        cache.insert(key, vec![Pubkey::new_unique(), Pubkey::new_unique()]);
+        assert!(cache.missing(&[lookup(key, vec![1], vec![0])]).is_empty());
+
+        // Index 5 was appended after the table was cached
+        let extended = [lookup(key, vec![1], vec![5])];
+        assert_eq!(cache.missing(&extended), vec![key]);
+        assert!(cache.resolve(&extended).is_err());
+    }
+}
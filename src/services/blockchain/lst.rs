@@ -0,0 +1,111 @@
+use anyhow::{bail, Result};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+
+use super::layout::ByteReader;
+
+// SPL stake pool: total_lamports and pool_token_supply follow the header pubkeys
+const STAKE_POOL_TOTAL_LAMPORTS_OFFSET: usize = 258;
+// Marinade State: msol_price, a fixed-point SOL per mSOL scaled by 2^32
+const MARINADE_MSOL_PRICE_OFFSET: usize = 512;
+const MARINADE_PRICE_DENOMINATOR: f64 = 4_294_967_296.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolProgram {
+    SplStakePool,
+    Marinade,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LiquidStakingToken {
+    pub symbol: &'static str,
+    pub mint: Pubkey,
+    // Account holding the pool state the exchange rate is read from
+    pub pool: Pubkey,
+    pub program: PoolProgram,
+}
+
+pub const LIQUID_STAKING_TOKENS: [LiquidStakingToken; 3] = [
+    LiquidStakingToken {
+        symbol: "mSOL",
+        mint: pubkey!("mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So"),
+        pool: pubkey!("8szGkuLTAux9XMgZ2vtY39jVSowEcpBfFfD8hXSEqdGC"),
+        program: PoolProgram::Marinade,
+    },
+    LiquidStakingToken {
+        symbol: "jitoSOL",
+        mint: pubkey!("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn"),
+        pool: pubkey!("Jito4APyf642JPZPx3hGc6WWJ8zPKtRbRs4P815Awbb"),
+        program: PoolProgram::SplStakePool,
+    },
+    LiquidStakingToken {
+        symbol: "bSOL",
+        mint: pubkey!("bSo13r4TkiE4KumL71LsHTPpL2euBYLFx6h9HP3piy1"),
+        pool: pubkey!("stk9ApL5HeVAwPLr3TLhDXdZS8ptVu7zp6ov8HFDuMi"),
+        program: PoolProgram::SplStakePool,
+    },
+];
+
+pub fn find_liquid_staking_token(mint: &str) -> Option<&'static LiquidStakingToken> {
+    LIQUID_STAKING_TOKENS
+        .iter()
+        .find(|token| token.mint.to_string() == mint)
+}
+
+// SOL redeemable per pool token, read from the pool's state account
+pub fn parse_exchange_rate(program: PoolProgram, data: &[u8]) -> Result<f64> {
+    match program {
+        PoolProgram::SplStakePool => {
+            let mut reader = ByteReader::at(data, STAKE_POOL_TOTAL_LAMPORTS_OFFSET);
+            let total_lamports = reader.read_u64()?;
+            let pool_token_supply = reader.read_u64()?;
+            if pool_token_supply == 0 {
+                bail!("Stake pool has no tokens outstanding");
+            }
+            // Pool tokens and SOL both use 9 decimals
+            Ok(total_lamports as f64 / pool_token_supply as f64)
+        }
+        PoolProgram::Marinade => {
+            let msol_price = ByteReader::at(data, MARINADE_MSOL_PRICE_OFFSET).read_u64()?;
+            Ok(msol_price as f64 / MARINADE_PRICE_DENOMINATOR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spl_stake_pool_rate() {
+        let mut data = vec![0u8; 611];
+        data[STAKE_POOL_TOTAL_LAMPORTS_OFFSET..STAKE_POOL_TOTAL_LAMPORTS_OFFSET + 8]
+            .copy_from_slice(&1_150_000_000_000u64.to_le_bytes());
+        data[STAKE_POOL_TOTAL_LAMPORTS_OFFSET + 8..STAKE_POOL_TOTAL_LAMPORTS_OFFSET + 16]
+            .copy_from_slice(&1_000_000_000_000u64.to_le_bytes());
+
+        assert_eq!(parse_exchange_rate(PoolProgram::SplStakePool, &data).unwrap(), 1.15);
+    }
+
+    #[test]
+    fn test_empty_stake_pool_is_rejected() {
+        assert!(parse_exchange_rate(PoolProgram::SplStakePool, &[0u8; 611]).is_err());
+    }
+
+    #[test]
+    fn test_parse_marinade_rate() {
+        let mut data = vec![0u8; 1024];
+        // 1.25 SOL per mSOL
+        data[MARINADE_MSOL_PRICE_OFFSET..MARINADE_MSOL_PRICE_OFFSET + 8]
+            .copy_from_slice(&(5u64 << 30).to_le_bytes());
+
+        assert_eq!(parse_exchange_rate(PoolProgram::Marinade, &data).unwrap(), 1.25);
+    }
+
+    #[test]
+    fn test_find_liquid_staking_token() {
+        let jito_sol = find_liquid_staking_token("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn").unwrap();
+        assert_eq!(jito_sol.symbol, "jitoSOL");
+        assert!(find_liquid_staking_token("So11111111111111111111111111111111111111112").is_none());
+    }
+}
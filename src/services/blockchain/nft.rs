@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+use solana_sdk::pubkey::Pubkey;
+
+use super::token_metadata::TokenMetadata;
+use super::TokenAccountBalance;
+use crate::models::{Nft, NftCollection};
+
+// Metaplex TokenStandard: NonFungible, NonFungibleEdition,
+// ProgrammableNonFungible, ProgrammableNonFungibleEdition
+const NON_FUNGIBLE_STANDARDS: [u8; 4] = [0, 3, 4, 5];
+
+// A zero-decimal mint with exactly one token in the holder's account. Plenty
+// of fungible tokens fit too, so only metadata can confirm an NFT.
+pub fn is_nft_shaped(account: &TokenAccountBalance) -> bool {
+    account.decimals == 0 && account.raw_amount == 1
+}
+
+// Legacy metadata without a token standard is taken at its NFT shape;
+// anything explicitly fungible is not an NFT
+pub fn nft_from_metadata(mint: &Pubkey, metadata: TokenMetadata) -> Option<Nft> {
+    if metadata
+        .token_standard
+        .is_some_and(|standard| !NON_FUNGIBLE_STANDARDS.contains(&standard))
+    {
+        return None;
+    }
+
+    Some(Nft {
+        mint: mint.to_string(),
+        name: metadata.name,
+        symbol: metadata.symbol,
+        uri: metadata.uri,
+        collection: metadata
+            .collection
+            .filter(|collection| collection.verified)
+            .map(|collection| collection.key.to_string()),
+        compressed: false,
+    })
+}
+
+// Groups by verified collection; NFTs without one come first under `None`
+pub fn group_by_collection(nfts: &[Nft]) -> Vec<NftCollection> {
+    let mut groups: BTreeMap<Option<String>, Vec<Nft>> = BTreeMap::new();
+    for nft in nfts {
+        groups.entry(nft.collection.clone()).or_default().push(nft.clone());
+    }
+
+    groups
+        .into_iter()
+        .map(|(collection, nfts)| NftCollection { collection, nfts })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::blockchain::token_metadata::CollectionRef;
+
+    fn metadata(token_standard: Option<u8>, collection: Option<CollectionRef>) -> TokenMetadata {
+        TokenMetadata {
+            name: "Mad Lad #1".to_string(),
+            symbol: "MAD".to_string(),
+            uri: "https://example.com/1.json".to_string(),
+            token_standard,
+            collection,
+        }
+    }
+
+    #[test]
+    fn test_only_verified_collections_are_kept() {
+        let key = Pubkey::new_unique();
+        let verified = nft_from_metadata(
+            &Pubkey::new_unique(),
+            metadata(Some(4), Some(CollectionRef { key, verified: true })),
+        )
+        .unwrap();
+        let spoofed = nft_from_metadata(
+            &Pubkey::new_unique(),
+            metadata(None, Some(CollectionRef { key, verified: false })),
+        )
+        .unwrap();
+
+        assert_eq!(verified.collection, Some(key.to_string()));
+        assert_eq!(spoofed.collection, None);
+    }
+
+    #[test]
+    fn test_fungible_metadata_is_not_an_nft() {
+        // TokenStandard::FungibleAsset
+        assert!(nft_from_metadata(&Pubkey::new_unique(), metadata(Some(1), None)).is_none());
+    }
+
+    #[test]
+    fn test_group_by_collection() {
+        let nft = |mint: &str, collection: Option<&str>| Nft {
+            mint: mint.to_string(),
+            name: String::new(),
+            symbol: String::new(),
+            uri: String::new(),
+            collection: collection.map(str::to_string),
+            compressed: false,
+        };
+        let nfts = vec![nft("a", Some("lads")), nft("b", None), nft("c", Some("lads"))];
+
+        let groups = group_by_collection(&nfts);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].collection, None);
+        assert_eq!(groups[1].collection.as_deref(), Some("lads"));
+        assert_eq!(groups[1].nfts.iter().map(|n| n.mint.as_str()).collect::<Vec<_>>(), vec!["a", "c"]);
+    }
+}
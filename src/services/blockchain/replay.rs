@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_client::client_error::{ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_sdk::hash::hashv;
+use tracing::warn;
+
+use super::rpc_pool::response_error_data;
+
+// Where SolanaClient's RPC traffic goes. Recording passes requests through
+// to the live pool and writes every answer to a fixture directory; replay
+// serves a fixture directory back without touching the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcMode {
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+impl RpcMode {
+    // SOLANA_RPC_RECORD=<dir> or SOLANA_RPC_REPLAY=<dir>; neither means live
+    pub fn from_env() -> Result<Self> {
+        match (std::env::var("SOLANA_RPC_RECORD"), std::env::var("SOLANA_RPC_REPLAY")) {
+            (Ok(_), Ok(_)) => Err(anyhow!(
+                "SOLANA_RPC_RECORD and SOLANA_RPC_REPLAY are mutually exclusive"
+            )),
+            (Ok(dir), Err(_)) => Ok(RpcMode::Record(dir.into())),
+            (Err(_), Ok(dir)) => Ok(RpcMode::Replay(dir.into())),
+            (Err(_), Err(_)) => Ok(RpcMode::Live),
+        }
+    }
+}
+
+// One fixture file: every answer the node gave to one request, in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    method: String,
+    params: Value,
+    responses: Vec<RecordedResponse>,
+}
+
+// JSON-RPC errors are part of the node's answer and are recorded too,
+// `data` included (e.g. a preflight failure's simulation logs); transport
+// failures are not
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordedResponse {
+    Result(Value),
+    Error {
+        code: i64,
+        message: String,
+        #[serde(default)]
+        data: Value,
+    },
+}
+
+// `data` as the node sent it, so replay can parse it back the same way
+fn error_data_json(data: &RpcResponseErrorData) -> Value {
+    match data {
+        RpcResponseErrorData::Empty => Value::Null,
+        RpcResponseErrorData::SendTransactionPreflightFailure(result) => {
+            serde_json::to_value(result).unwrap_or_default()
+        }
+        RpcResponseErrorData::NodeUnhealthy { num_slots_behind } => {
+            json!({ "numSlotsBehind": num_slots_behind })
+        }
+    }
+}
+
+// Fixture file name: the method plus a digest of the exact params, so any
+// difference in the request (commitment, cursor, encoding) is a different file
+fn interaction_key(request: RpcRequest, params: &Value) -> String {
+    let method = request.to_string();
+    let digest = hashv(&[method.as_bytes(), params.to_string().as_bytes()]);
+    format!("{}-{}", method, digest)
+}
+
+pub struct RecordingSender<S> {
+    inner: S,
+    dir: PathBuf,
+    interactions: Mutex<HashMap<String, Interaction>>,
+}
+
+impl<S: RpcSender> RecordingSender<S> {
+    pub fn new(inner: S, dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create RPC fixture directory {}", dir.display()))?;
+        Ok(Self {
+            inner,
+            dir,
+            interactions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Rewrites the request's file on every answer so a recording that is
+    // cut short still replays up to that point
+    fn record(&self, request: RpcRequest, params: Value, response: RecordedResponse) -> Result<()> {
+        let key = interaction_key(request, &params);
+        let mut interactions = self.interactions.lock().unwrap();
+        let interaction = interactions.entry(key.clone()).or_insert_with(|| Interaction {
+            method: request.to_string(),
+            params,
+            responses: Vec::new(),
+        });
+        interaction.responses.push(response);
+
+        let path = self.dir.join(format!("{}.json", key));
+        std::fs::write(&path, serde_json::to_vec_pretty(interaction)?)
+            .with_context(|| format!("Failed to write RPC fixture {}", path.display()))
+    }
+}
+
+#[async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for RecordingSender<S> {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let result = self.inner.send(request, params.clone()).await;
+
+        let recorded = match &result {
+            Ok(value) => Some(RecordedResponse::Result(value.clone())),
+            Err(error) => match error.kind() {
+                ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, data }) => {
+                    Some(RecordedResponse::Error {
+                        code: *code,
+                        message: message.clone(),
+                        data: error_data_json(data),
+                    })
+                }
+                _ => None,
+            },
+        };
+        if let Some(recorded) = recorded {
+            if let Err(e) = self.record(request, params, recorded) {
+                warn!("Failed to record {} response: {}", request, e);
+            }
+        }
+
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+// Serves recorded answers back as the JSON the node returned; RpcClient
+// parses them as it would a live response. A request seen several times
+// while recording gets its answers back in the same order, with the last one
+// repeated once they run out. Anything not recorded is an error rather than
+// a network call.
+//
+// Requests are matched on the method and params stored in each file, not on
+// the file name, so fixtures can be written or renamed by hand.
+pub struct ReplaySender {
+    dir: PathBuf,
+    interactions: Vec<(Interaction, Mutex<usize>)>,
+}
+
+impl ReplaySender {
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut interactions = Vec::new();
+
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read RPC fixture directory {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            let contents = std::fs::read(&path)?;
+            let interaction: Interaction = serde_json::from_slice(&contents)
+                .with_context(|| format!("Failed to parse RPC fixture {}", path.display()))?;
+            interactions.push((interaction, Mutex::new(0)));
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            interactions,
+        })
+    }
+
+    // Whether anything was recorded for `method`, e.g. to tell whether the
+    // recording session had a DAS indexer
+    pub fn has_method(&self, method: &str) -> bool {
+        self.interactions
+            .iter()
+            .any(|(interaction, _)| interaction.method == method)
+    }
+}
+
+#[async_trait]
+impl RpcSender for ReplaySender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let method = request.to_string();
+        let Some((interaction, cursor)) = self
+            .interactions
+            .iter()
+            .find(|(interaction, _)| interaction.method == method && interaction.params == params)
+        else {
+            return Err(ClientErrorKind::Custom(format!("No recorded response for {} {}", request, params)).into());
+        };
+
+        let response = {
+            let mut cursor = cursor.lock().unwrap();
+            let index = (*cursor).min(interaction.responses.len().saturating_sub(1));
+            *cursor += 1;
+            interaction.responses.get(index).cloned()
+        };
+
+        match response {
+            Some(RecordedResponse::Result(value)) => Ok(value),
+            Some(RecordedResponse::Error { code, message, data }) => Err(RpcError::RpcResponseError {
+                data: response_error_data(code, &data),
+                code,
+                message,
+            }
+            .into()),
+            None => Err(ClientErrorKind::Custom(format!("Empty RPC fixture for {} {}", request, params)).into()),
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        format!("replay:{}", self.dir.display())
+    }
+}
+
+// Lets several RpcClients (one per commitment level) share a sender
+#[derive(Clone)]
+pub struct SharedSender(pub Arc<dyn RpcSender + Send + Sync>);
+
+#[async_trait]
+impl RpcSender for SharedSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        self.0.send(request, params).await
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.0.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.0.url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    // Answers every request from a script, recording what it was asked
+    struct ScriptedSender {
+        answers: Mutex<Vec<ClientResult<Value>>>,
+    }
+
+    #[async_trait]
+    impl RpcSender for ScriptedSender {
+        async fn send(&self, _request: RpcRequest, _params: Value) -> ClientResult<Value> {
+            self.answers.lock().unwrap().remove(0)
+        }
+
+        fn get_transport_stats(&self) -> RpcTransportStats {
+            RpcTransportStats::default()
+        }
+
+        fn url(&self) -> String {
+            "scripted".to_string()
+        }
+    }
+
+    fn fixture_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("rpc-fixtures-{}", Pubkey::new_unique()))
+    }
+
+    #[tokio::test]
+    async fn test_replays_recorded_answers_in_order() {
+        let dir = fixture_dir();
+        let params = json!([{ "commitment": "finalized" }]);
+        let scripted = ScriptedSender {
+            answers: Mutex::new(vec![
+                Ok(json!(100)),
+                Ok(json!(101)),
+                Err(RpcError::RpcResponseError {
+                    code: -32602,
+                    message: "Invalid params".to_string(),
+                    data: RpcResponseErrorData::Empty,
+                }
+                .into()),
+                Err(ClientErrorKind::Custom("connection reset".to_string()).into()),
+                Err(RpcError::RpcResponseError {
+                    code: -32005,
+                    message: "Node is behind by 42 slots".to_string(),
+                    data: RpcResponseErrorData::NodeUnhealthy {
+                        num_slots_behind: Some(42),
+                    },
+                }
+                .into()),
+            ]),
+        };
+
+        let recorder = RecordingSender::new(scripted, &dir).unwrap();
+        assert_eq!(
+            recorder.send(RpcRequest::GetSlot, params.clone()).await.unwrap(),
+            json!(100)
+        );
+        assert_eq!(
+            recorder.send(RpcRequest::GetSlot, params.clone()).await.unwrap(),
+            json!(101)
+        );
+        assert!(recorder.send(RpcRequest::GetBalance, json!(["a"])).await.is_err());
+        // Transport failures are not recorded
+        assert!(recorder.send(RpcRequest::GetBalance, json!(["b"])).await.is_err());
+        assert!(recorder.send(RpcRequest::GetHealth, json!([])).await.is_err());
+
+        let replay = ReplaySender::load(&dir).unwrap();
+        assert_eq!(
+            replay.send(RpcRequest::GetSlot, params.clone()).await.unwrap(),
+            json!(100)
+        );
+        assert_eq!(
+            replay.send(RpcRequest::GetSlot, params.clone()).await.unwrap(),
+            json!(101)
+        );
+        // The last answer repeats once the recording runs out
+        assert_eq!(replay.send(RpcRequest::GetSlot, params).await.unwrap(), json!(101));
+
+        let error = replay.send(RpcRequest::GetBalance, json!(["a"])).await.unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ClientErrorKind::RpcError(RpcError::RpcResponseError { code: -32602, .. })
+        ));
+        assert!(replay.send(RpcRequest::GetBalance, json!(["b"])).await.is_err());
+        assert!(replay.send(RpcRequest::GetSlot, json!([])).await.is_err());
+
+        // The error's data comes back too
+        let error = replay.send(RpcRequest::GetHealth, json!([])).await.unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                code: -32005,
+                data: RpcResponseErrorData::NodeUnhealthy {
+                    num_slots_behind: Some(42)
+                },
+                ..
+            })
+        ));
+        assert!(replay.has_method("getHealth"));
+        assert!(!replay.has_method("getAssetsByOwner"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,729 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, register_int_gauge_vec,
+    GaugeVec, HistogramVec, IntCounterVec, IntGaugeVec,
+};
+use reqwest::header::{CONTENT_TYPE, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde_json::Value;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_custom_error::{
+    NodeUnhealthyErrorData, JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP, JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+    JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET, JSON_RPC_SERVER_ERROR_KEY_EXCLUDED_FROM_SECONDARY_INDEX,
+    JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED, JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED,
+    JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY, JSON_RPC_SERVER_ERROR_NO_SNAPSHOT,
+    JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE, JSON_RPC_SERVER_ERROR_TRANSACTION_HISTORY_NOT_AVAILABLE,
+};
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use tracing::{debug, info, warn};
+
+use crate::utils::helpers::redact_url;
+
+// Weight given to the newest sample in the latency / error-rate moving averages
+const EWMA_ALPHA: f64 = 0.2;
+// Retry-After values beyond this are treated as "take the endpoint out of rotation"
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+// JSON-RPC errors that describe what one node has (pruned blocks, missing
+// history or indexes, not caught up yet) rather than the request itself
+const NODE_SPECIFIC_ERRORS: [i64; 8] = [
+    JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP,
+    JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+    JSON_RPC_SERVER_ERROR_NO_SNAPSHOT,
+    JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED,
+    JSON_RPC_SERVER_ERROR_KEY_EXCLUDED_FROM_SECONDARY_INDEX,
+    JSON_RPC_SERVER_ERROR_TRANSACTION_HISTORY_NOT_AVAILABLE,
+    JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET,
+    JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED,
+];
+
+#[derive(Debug, Clone)]
+pub struct EndpointConfig {
+    pub url: String,
+    pub weight: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct EndpointHealth {
+    latency_secs: Option<f64>,
+    error_rate: f64,
+    requests: u64,
+    errors: u64,
+    slot: u64,
+    slot_lag: u64,
+    // Set from Retry-After; the endpoint is skipped until then
+    rate_limited_until: Option<Instant>,
+}
+
+struct Endpoint {
+    url: String,
+    // What logs and metrics call the endpoint; the URL may hold an API key
+    name: String,
+    weight: f64,
+    health: Mutex<EndpointHealth>,
+}
+
+impl Endpoint {
+    fn score(&self, max_slot_lag: u64) -> f64 {
+        let health = self.health.lock().unwrap();
+        // Unmeasured endpoints start out looking like a decent 250ms node
+        let latency = health.latency_secs.unwrap_or(0.25);
+        let mut score = self.weight * (1.0 - health.error_rate) / (latency + 0.05);
+        if health.slot_lag > max_slot_lag {
+            score *= 0.01;
+        }
+        score
+    }
+
+    fn is_rate_limited(&self) -> bool {
+        self.health
+            .lock()
+            .unwrap()
+            .rate_limited_until
+            .is_some_and(|until| until > Instant::now())
+    }
+}
+
+// Outcome of a single attempt against one endpoint
+enum Attempt {
+    Success(Value),
+    // The node answered with a JSON-RPC error; another endpoint would say the same
+    Fatal(ClientError),
+    // This node cannot serve the request, but another endpoint might
+    Failover(ClientError),
+    Retry { error: ClientError, delay: Option<Duration> },
+}
+
+// A set of RPC endpoints with per-endpoint latency, error and slot-lag
+// tracking. Requests are spread across endpoints in proportion to their
+// scores and fail over down the ranking; each endpoint gets its own
+// exponential-backoff retries.
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+    // Smooth weighted round-robin state, one entry per endpoint
+    rotation: Mutex<Vec<f64>>,
+    http: reqwest::Client,
+    retry: RetryPolicy,
+    max_slot_lag: u64,
+    request_id: AtomicU64,
+    stats: RwLock<RpcTransportStats>,
+}
+
+impl RpcPool {
+    pub fn new(endpoints: Vec<EndpointConfig>, retry: RetryPolicy, max_slot_lag: u64) -> Result<Self> {
+        if endpoints.is_empty() {
+            return Err(anyhow!("RPC pool needs at least one endpoint"));
+        }
+
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+
+        Ok(Self {
+            rotation: Mutex::new(vec![0.0; endpoints.len()]),
+            endpoints: endpoints
+                .into_iter()
+                .enumerate()
+                .map(|(index, config)| Endpoint {
+                    name: format!("{}#{}", redact_url(&config.url), index),
+                    url: config.url,
+                    weight: config.weight,
+                    health: Mutex::new(EndpointHealth::default()),
+                })
+                .collect(),
+            http,
+            retry,
+            max_slot_lag,
+            request_id: AtomicU64::new(0),
+            stats: RwLock::new(RpcTransportStats::default()),
+        })
+    }
+
+    // SOLANA_RPC_URLS is a comma separated list of `url` or `url|weight`;
+    // a single SOLANA_RPC_URL still works on its own
+    pub fn from_env() -> Result<Self> {
+        let urls = std::env::var("SOLANA_RPC_URLS").or_else(|_| std::env::var("SOLANA_RPC_URL"))?;
+        let max_slot_lag = std::env::var("SOLANA_RPC_MAX_SLOT_LAG")
+            .ok()
+            .and_then(|lag| lag.parse().ok())
+            .unwrap_or(50);
+
+        Self::new(parse_endpoints(&urls)?, RetryPolicy::default(), max_slot_lag)
+    }
+
+    pub fn endpoint_names(&self) -> Vec<String> {
+        self.endpoints.iter().map(|endpoint| endpoint.name.clone()).collect()
+    }
+
+    // Polls getSlot on every endpoint and records how far each one is
+    // behind the most advanced endpoint
+    pub async fn refresh_slots(&self) {
+        let mut slots = Vec::with_capacity(self.endpoints.len());
+        for endpoint in &self.endpoints {
+            let slot = match self.attempt(endpoint, RpcRequest::GetSlot, Value::Array(vec![])).await {
+                Attempt::Success(value) => value.as_u64(),
+                _ => None,
+            };
+            slots.push(slot);
+        }
+
+        let highest = slots.iter().flatten().copied().max().unwrap_or_default();
+        for (endpoint, slot) in self.endpoints.iter().zip(slots) {
+            let Some(slot) = slot else { continue };
+            let lag = highest.saturating_sub(slot);
+
+            let mut health = endpoint.health.lock().unwrap();
+            let was_lagging = health.slot_lag > self.max_slot_lag;
+            health.slot = slot;
+            health.slot_lag = lag;
+            drop(health);
+
+            metrics().slot_lag.with_label_values(&[&endpoint.name]).set(lag as i64);
+            match (was_lagging, lag > self.max_slot_lag) {
+                (false, true) => warn!("RPC endpoint {} is {} slots behind, deprioritising", endpoint.name, lag),
+                (true, false) => info!("RPC endpoint {} caught up (lag {})", endpoint.name, lag),
+                _ => {}
+            }
+        }
+
+        for endpoint in &self.endpoints {
+            let score = endpoint.score(self.max_slot_lag);
+            metrics().score.with_label_values(&[&endpoint.name]).set(score);
+
+            let health = endpoint.health.lock().unwrap();
+            debug!(
+                "RPC endpoint {}: slot {} (lag {}), latency {:.0}ms, error rate {:.2} ({}/{} failed), score {:.2}",
+                endpoint.name,
+                health.slot,
+                health.slot_lag,
+                health.latency_secs.unwrap_or_default() * 1000.0,
+                health.error_rate,
+                health.errors,
+                health.requests,
+                score
+            );
+        }
+    }
+
+    pub fn spawn_health_monitor(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let pool = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                pool.refresh_slots().await;
+            }
+        })
+    }
+
+    // The endpoint to try first, picked by smooth weighted round-robin over
+    // the scores of endpoints that are not rate limited, so each one leads
+    // in proportion to its score. The rest follow best score first.
+    fn ranked_endpoints(&self) -> Vec<&Endpoint> {
+        let scores: Vec<f64> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.score(self.max_slot_lag))
+            .collect();
+        let candidates: Vec<usize> = (0..self.endpoints.len())
+            .filter(|index| !self.endpoints[*index].is_rate_limited())
+            .collect();
+
+        let lead = {
+            let mut rotation = self.rotation.lock().unwrap();
+            for &index in &candidates {
+                rotation[index] += scores[index];
+            }
+            let lead = candidates
+                .iter()
+                .copied()
+                .max_by(|a, b| rotation[*a].partial_cmp(&rotation[*b]).unwrap_or(std::cmp::Ordering::Equal));
+            if let Some(lead) = lead {
+                rotation[lead] -= candidates.iter().map(|index| scores[*index]).sum::<f64>();
+            }
+            lead
+        };
+
+        let mut ranked: Vec<usize> = (0..self.endpoints.len()).collect();
+        ranked.sort_by(|a, b| scores[*b].partial_cmp(&scores[*a]).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some(lead) = lead {
+            ranked.retain(|index| *index != lead);
+            ranked.insert(0, lead);
+        }
+        let ranked = ranked.into_iter().map(|index| &self.endpoints[index]);
+
+        // Rate-limited endpoints go last rather than disappearing, so a pool
+        // where everything is throttled still makes progress
+        let (available, throttled): (Vec<_>, Vec<_>) =
+            ranked.into_iter().partition(|endpoint| !endpoint.is_rate_limited());
+        available.into_iter().chain(throttled).collect()
+    }
+
+    pub async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let mut last_error = None;
+
+        for (rank, endpoint) in self.ranked_endpoints().into_iter().enumerate() {
+            if rank > 0 {
+                warn!("Failing over {} to RPC endpoint {}", request, endpoint.name);
+            }
+
+            for attempt in 0..=self.retry.max_retries {
+                match self.attempt(endpoint, request, params.clone()).await {
+                    Attempt::Success(value) => return Ok(value),
+                    Attempt::Fatal(error) => return Err(error),
+                    Attempt::Failover(error) => {
+                        debug!("{} not served by {}: {}", request, endpoint.name, error);
+                        last_error = Some(error);
+                        break;
+                    }
+                    Attempt::Retry { error, delay } => {
+                        debug!("{} to {} failed (attempt {}): {}", request, endpoint.name, attempt + 1, error);
+                        last_error = Some(error);
+
+                        // A long Retry-After means this endpoint is done for now
+                        if delay.is_some_and(|delay| delay > self.retry.max_delay) {
+                            break;
+                        }
+                        if attempt < self.retry.max_retries {
+                            if let Some(delay) = delay {
+                                self.stats.write().unwrap().rate_limited_time += delay;
+                            }
+                            tokio::time::sleep(delay.unwrap_or_else(|| self.retry.backoff(attempt))).await;
+                        }
+                    }
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| ClientErrorKind::Custom("no RPC endpoints available".to_string()).into()))
+    }
+
+    async fn attempt(&self, endpoint: &Endpoint, request: RpcRequest, params: Value) -> Attempt {
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let body = request.build_request_json(request_id, params).to_string();
+        let method = request.to_string();
+        let started = Instant::now();
+
+        let response = self
+            .http
+            .post(&endpoint.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await;
+        let elapsed = started.elapsed();
+
+        {
+            let mut stats = self.stats.write().unwrap();
+            stats.request_count += 1;
+            stats.elapsed_time += elapsed;
+        }
+        metrics()
+            .latency
+            .with_label_values(&[&endpoint.name, &method])
+            .observe(elapsed.as_secs_f64());
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                self.record_failure(endpoint, "transport");
+                return Attempt::Retry { error: e.into(), delay: None };
+            }
+        };
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let delay = retry_after(&response).unwrap_or_else(|| self.retry.backoff(0));
+            warn!("RPC endpoint {} rate limited, retry after {:?}", endpoint.name, delay);
+            endpoint.health.lock().unwrap().rate_limited_until = Some(Instant::now() + delay.min(MAX_RETRY_AFTER));
+            self.record_failure(endpoint, "rate_limited");
+            let error = response.error_for_status().unwrap_err();
+            return Attempt::Retry { error: error.into(), delay: Some(delay) };
+        }
+
+        if !response.status().is_success() {
+            self.record_failure(endpoint, "http_status");
+            let error = response.error_for_status().unwrap_err();
+            return Attempt::Retry { error: error.into(), delay: None };
+        }
+
+        let mut json = match response.json::<Value>().await {
+            Ok(json) => json,
+            Err(e) => {
+                self.record_failure(endpoint, "decode");
+                return Attempt::Retry { error: e.into(), delay: None };
+            }
+        };
+
+        if json["error"].is_object() {
+            let code = json["error"]["code"].as_i64().unwrap_or_default();
+            let message = json["error"]["message"].as_str().unwrap_or_default().to_string();
+            let error: ClientError = RpcError::RpcResponseError {
+                code,
+                message,
+                data: response_error_data(code, &json["error"]["data"]),
+            }
+            .into();
+
+            // An unhealthy node is an endpoint problem, and a node missing the
+            // data is worth asking the next endpoint; anything else is the
+            // request's fault and would fail the same way everywhere
+            if code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY {
+                self.record_failure(endpoint, "node_unhealthy");
+                return Attempt::Retry { error, delay: None };
+            }
+            if NODE_SPECIFIC_ERRORS.contains(&code) {
+                self.record_failure(endpoint, "unavailable");
+                return Attempt::Failover(error);
+            }
+            self.record_success(endpoint, elapsed);
+            return Attempt::Fatal(error);
+        }
+
+        self.record_success(endpoint, elapsed);
+        Attempt::Success(json["result"].take())
+    }
+
+    fn record_success(&self, endpoint: &Endpoint, elapsed: Duration) {
+        let mut health = endpoint.health.lock().unwrap();
+        let latency = elapsed.as_secs_f64();
+        health.latency_secs = Some(match health.latency_secs {
+            Some(previous) => previous + EWMA_ALPHA * (latency - previous),
+            None => latency,
+        });
+        health.error_rate *= 1.0 - EWMA_ALPHA;
+        health.requests += 1;
+        health.rate_limited_until = None;
+    }
+
+    fn record_failure(&self, endpoint: &Endpoint, reason: &str) {
+        let mut health = endpoint.health.lock().unwrap();
+        health.error_rate += EWMA_ALPHA * (1.0 - health.error_rate);
+        health.requests += 1;
+        health.errors += 1;
+        drop(health);
+
+        metrics()
+            .errors
+            .with_label_values(&[&endpoint.name, reason])
+            .inc();
+    }
+}
+
+// The `data` of a JSON-RPC error, read the way solana-client's own sender
+// reads it; only preflight failures and unhealthy nodes carry any
+pub fn response_error_data(code: i64, data: &Value) -> RpcResponseErrorData {
+    match code {
+        JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE => serde_json::from_value(data.clone())
+            .map(RpcResponseErrorData::SendTransactionPreflightFailure)
+            .unwrap_or(RpcResponseErrorData::Empty),
+        JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => serde_json::from_value::<NodeUnhealthyErrorData>(data.clone())
+            .map(|data| RpcResponseErrorData::NodeUnhealthy {
+                num_slots_behind: data.num_slots_behind,
+            })
+            .unwrap_or(RpcResponseErrorData::Empty),
+        _ => RpcResponseErrorData::Empty,
+    }
+}
+
+// Lets an RpcClient route through a shared pool
+pub struct PooledSender(pub Arc<RpcPool>);
+
+#[async_trait]
+impl RpcSender for PooledSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        self.0.send(request, params).await
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.0.stats.read().unwrap().clone()
+    }
+
+    fn url(&self) -> String {
+        self.0.endpoint_names().join(",")
+    }
+}
+
+fn parse_endpoints(urls: &str) -> Result<Vec<EndpointConfig>> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('|') {
+            Some((url, weight)) => Ok(EndpointConfig {
+                url: url.to_string(),
+                weight: weight.parse()?,
+            }),
+            None => Ok(EndpointConfig {
+                url: entry.to_string(),
+                weight: 1.0,
+            }),
+        })
+        .collect()
+}
+
+// Retry-After in delta-seconds form; HTTP-date values are rare for RPC providers
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+struct PoolMetrics {
+    latency: HistogramVec,
+    errors: IntCounterVec,
+    slot_lag: IntGaugeVec,
+    score: GaugeVec,
+}
+
+fn metrics() -> &'static PoolMetrics {
+    static METRICS: OnceLock<PoolMetrics> = OnceLock::new();
+    METRICS.get_or_init(|| PoolMetrics {
+        latency: register_histogram_vec!(
+            "solana_rpc_request_duration_seconds",
+            "Latency of Solana RPC requests per endpoint",
+            &["endpoint", "method"]
+        )
+        .expect("Failed to register RPC latency metric"),
+        errors: register_int_counter_vec!(
+            "solana_rpc_errors_total",
+            "Failed Solana RPC requests per endpoint",
+            &["endpoint", "reason"]
+        )
+        .expect("Failed to register RPC error metric"),
+        slot_lag: register_int_gauge_vec!(
+            "solana_rpc_slot_lag",
+            "Slots behind the most advanced endpoint in the pool",
+            &["endpoint"]
+        )
+        .expect("Failed to register RPC slot lag metric"),
+        score: register_gauge_vec!(
+            "solana_rpc_endpoint_score",
+            "Routing score of each RPC endpoint",
+            &["endpoint"]
+        )
+        .expect("Failed to register RPC score metric"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn fast_retry() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(20),
+        }
+    }
+
+    fn endpoint(url: String, weight: f64) -> EndpointConfig {
+        EndpointConfig { url, weight }
+    }
+
+    #[test]
+    fn test_parse_endpoints() {
+        let endpoints = parse_endpoints("https://a.example|2.5, https://b.example").unwrap();
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[0].url, "https://a.example");
+        assert_eq!(endpoints[0].weight, 2.5);
+        assert_eq!(endpoints[1].weight, 1.0);
+    }
+
+    #[test]
+    fn test_traffic_follows_weights() {
+        let pool = RpcPool::new(
+            vec![
+                endpoint("https://a.example/?api-key=secret".to_string(), 3.0),
+                endpoint("https://b.example/secret".to_string(), 1.0),
+            ],
+            fast_retry(),
+            50,
+        )
+        .unwrap();
+
+        let leads: Vec<String> = (0..40)
+            .map(|_| pool.ranked_endpoints()[0].name.clone())
+            .collect();
+        assert_eq!(leads.iter().filter(|name| *name == "https://a.example#0").count(), 30);
+        assert_eq!(leads.iter().filter(|name| *name == "https://b.example#1").count(), 10);
+        // Both endpoints are always there to fail over to
+        assert_eq!(pool.ranked_endpoints().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_healthy_endpoint() {
+        let broken = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&broken)
+            .await;
+
+        let healthy = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0", "result": 42, "id": 0
+            })))
+            .mount(&healthy)
+            .await;
+
+        // The broken endpoint is weighted higher so it is tried first
+        let pool = RpcPool::new(
+            vec![endpoint(broken.uri(), 3.0), endpoint(healthy.uri(), 1.0)],
+            fast_retry(),
+            50,
+        )
+        .unwrap();
+
+        let result = pool.send(RpcRequest::GetSlot, json!([])).await.unwrap();
+        assert_eq!(result, json!(42));
+        assert_eq!(broken.received_requests().await.unwrap().len(), 3);
+
+        // After the failures the healthy endpoint ranks first
+        assert_eq!(pool.ranked_endpoints()[0].url, healthy.uri());
+    }
+
+    #[tokio::test]
+    async fn test_honours_retry_after_then_succeeds() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0", "result": 7, "id": 1
+            })))
+            .mount(&server)
+            .await;
+
+        let pool = RpcPool::new(vec![endpoint(server.uri(), 1.0)], fast_retry(), 50).unwrap();
+        let result = pool.send(RpcRequest::GetSlot, json!([])).await.unwrap();
+
+        assert_eq!(result, json!(7));
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_errors_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32602, "message": "Invalid params" },
+                "id": 0
+            })))
+            .mount(&server)
+            .await;
+
+        let pool = RpcPool::new(vec![endpoint(server.uri(), 1.0)], fast_retry(), 50).unwrap();
+        assert!(pool.send(RpcRequest::GetSlot, json!([])).await.is_err());
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_node_specific_errors_fail_over() {
+        let pruned = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32004, "message": "Block not available for slot 1" },
+                "id": 0
+            })))
+            .mount(&pruned)
+            .await;
+
+        let archive = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0", "result": 42, "id": 0
+            })))
+            .mount(&archive)
+            .await;
+
+        let pool = RpcPool::new(
+            vec![endpoint(pruned.uri(), 3.0), endpoint(archive.uri(), 1.0)],
+            fast_retry(),
+            50,
+        )
+        .unwrap();
+
+        let result = pool.send(RpcRequest::GetBlock, json!([1])).await.unwrap();
+        assert_eq!(result, json!(42));
+        // Asked once, not retried against the same node
+        assert_eq!(pruned.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_sender_url_is_redacted() {
+        let pool = RpcPool::new(
+            vec![endpoint("https://a.example/?api-key=secret".to_string(), 1.0)],
+            fast_retry(),
+            50,
+        )
+        .unwrap();
+        let sender = PooledSender(Arc::new(pool));
+        assert!(!sender.url().contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_lagging_endpoint_is_deprioritised() {
+        let ahead = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0", "result": 1_000, "id": 0
+            })))
+            .mount(&ahead)
+            .await;
+
+        let behind = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0", "result": 900, "id": 0
+            })))
+            .mount(&behind)
+            .await;
+
+        let pool = RpcPool::new(
+            vec![endpoint(behind.uri(), 5.0), endpoint(ahead.uri(), 1.0)],
+            fast_retry(),
+            50,
+        )
+        .unwrap();
+        pool.refresh_slots().await;
+
+        assert_eq!(pool.ranked_endpoints()[0].url, ahead.uri());
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::{anyhow, bail, Result};
+use solana_sdk::hash::hashv;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+
+// Solana Name Service: every name is a registry account at a PDA of the name
+// program, derived from the hashed name and its parent. `.sol` domains are
+// children of the `.sol` TLD account; subdomains are children of their domain.
+pub const NAME_PROGRAM_ID: Pubkey = pubkey!("namesLPneVptA9Z5rqUDD9tMTWEJwofgaYwp8cawRkX");
+pub const SOL_TLD_AUTHORITY: Pubkey = pubkey!("58PwtjSDuFHuUkYjH9BYnnQKHfwo9reZhC2zMJv9JPkx");
+
+// Tokenizing a domain hands the registry to the name tokenizer's central
+// state and mints an NFT for it; whoever holds the NFT controls the domain
+pub const NAME_TOKENIZER_ID: Pubkey = pubkey!("nftD3vbNkNqfj2Sd3HZwbpw4BxxKWr4AjGb9X38JeZk");
+const TOKENIZED_MINT_PREFIX: &[u8] = b"tokenized_name";
+
+const HASH_PREFIX: &str = "SPL Name Service";
+// Registry header: parent name, owner, class
+const OWNER_OFFSET: usize = 32;
+const REGISTRY_HEADER_LEN: usize = 96;
+// SPL Token account: mint, then owner
+const TOKEN_ACCOUNT_OWNER_OFFSET: usize = 32;
+
+pub fn is_sol_domain(input: &str) -> bool {
+    input.trim().to_lowercase().ends_with(".sol")
+}
+
+// Registry account of `domain`, e.g. `bonfida.sol` or `dex.bonfida.sol`
+pub fn domain_key(domain: &str) -> Result<Pubkey> {
+    let domain = domain.trim().to_lowercase();
+    let name = domain
+        .strip_suffix(".sol")
+        .ok_or_else(|| anyhow!("{} is not a .sol domain", domain))?;
+
+    let labels: Vec<&str> = name.split('.').collect();
+    if labels.iter().any(|label| label.is_empty()) {
+        bail!("{} is not a valid .sol domain", domain);
+    }
+    match labels.as_slice() {
+        [domain] => Ok(name_account_key(domain, &SOL_TLD_AUTHORITY)),
+        [subdomain, domain] => {
+            let parent = name_account_key(domain, &SOL_TLD_AUTHORITY);
+            // Subdomain names carry a leading NUL byte
+            Ok(name_account_key(&format!("\0{}", subdomain), &parent))
+        }
+        _ => bail!("{} is nested too deeply", domain),
+    }
+}
+
+// Names without a class, which is how every `.sol` domain is registered
+fn name_account_key(name: &str, parent: &Pubkey) -> Pubkey {
+    let hashed = hashv(&[HASH_PREFIX.as_bytes(), name.as_bytes()]);
+    let class = Pubkey::default();
+    Pubkey::find_program_address(&[hashed.as_ref(), class.as_ref(), parent.as_ref()], &NAME_PROGRAM_ID).0
+}
+
+// The name tokenizer's central state, which owns every tokenized domain
+pub fn tokenizer_escrow() -> Pubkey {
+    Pubkey::find_program_address(&[NAME_TOKENIZER_ID.as_ref()], &NAME_TOKENIZER_ID).0
+}
+
+// Mint of the NFT for the tokenized domain at `name_account`
+pub fn tokenized_mint(name_account: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[TOKENIZED_MINT_PREFIX, name_account.as_ref()], &NAME_TOKENIZER_ID).0
+}
+
+// Owner of the token account holding a domain NFT
+pub fn parse_token_account_owner(data: &[u8]) -> Result<Pubkey> {
+    let owner = data
+        .get(TOKEN_ACCOUNT_OWNER_OFFSET..TOKEN_ACCOUNT_OWNER_OFFSET + 32)
+        .ok_or_else(|| anyhow!("Token account is {} bytes, too short for an owner", data.len()))?;
+    Ok(Pubkey::try_from(owner)?)
+}
+
+// Owner of a name registry account. Tokenized domains report the
+// tokenizer's escrow here rather than the holder of the domain NFT.
+pub fn parse_owner(data: &[u8]) -> Result<Pubkey> {
+    if data.len() < REGISTRY_HEADER_LEN {
+        bail!(
+            "Name registry account is {} bytes, expected at least {}",
+            data.len(),
+            REGISTRY_HEADER_LEN
+        );
+    }
+    Ok(Pubkey::try_from(&data[OWNER_OFFSET..OWNER_OFFSET + 32])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_key() {
+        assert_eq!(domain_key("bonfida.sol").unwrap(), domain_key(" Bonfida.SOL ").unwrap());
+        assert_ne!(
+            domain_key("dex.bonfida.sol").unwrap(),
+            domain_key("bonfida.sol").unwrap()
+        );
+        assert!(domain_key("bonfida").is_err());
+        assert!(domain_key(".sol").is_err());
+        assert!(domain_key("a.b.c.sol").is_err());
+    }
+
+    #[test]
+    fn test_parse_owner() {
+        let owner = Pubkey::new_unique();
+        let mut data = vec![0; REGISTRY_HEADER_LEN];
+        data[OWNER_OFFSET..OWNER_OFFSET + 32].copy_from_slice(owner.as_ref());
+        data.extend_from_slice(b"record data");
+
+        assert_eq!(parse_owner(&data).unwrap(), owner);
+        assert!(parse_owner(&data[..64]).is_err());
+    }
+
+    #[test]
+    fn test_tokenized_domain_accounts() {
+        let name_account = domain_key("bonfida.sol").unwrap();
+        assert_eq!(tokenized_mint(&name_account), tokenized_mint(&name_account));
+        assert_ne!(tokenized_mint(&name_account), tokenized_mint(&Pubkey::new_unique()));
+
+        let holder = Pubkey::new_unique();
+        let mut data = vec![0; 165];
+        data[TOKEN_ACCOUNT_OWNER_OFFSET..TOKEN_ACCOUNT_OWNER_OFFSET + 32].copy_from_slice(holder.as_ref());
+        assert_eq!(parse_token_account_owner(&data).unwrap(), holder);
+        assert!(parse_token_account_owner(&data[..40]).is_err());
+    }
+}
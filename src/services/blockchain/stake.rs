@@ -0,0 +1,131 @@
+use anyhow::{bail, Result};
+use solana_sdk::pubkey::Pubkey;
+
+use super::layout::ByteReader;
+use crate::models::{StakePosition, StakeState};
+
+// Bincode `StakeStateV2`: a u32 variant tag, then Meta (rent reserve,
+// authorized staker / withdrawer, lockup) and, for delegated accounts, the
+// Delegation
+pub const STAKE_ACCOUNT_LEN: usize = 200;
+pub const STAKER_OFFSET: usize = 12;
+pub const WITHDRAWER_OFFSET: usize = 44;
+const DELEGATION_OFFSET: usize = 124;
+
+const STATE_INITIALIZED: u32 = 1;
+const STATE_STAKE: u32 = 2;
+
+// Derives where a delegation is in its lifecycle from the current epoch.
+// Stake warms up and cools down at the epoch boundary after the
+// (de)activating instruction; the network-wide warmup rate limit is ignored.
+pub fn stake_state(activation_epoch: u64, deactivation_epoch: u64, current_epoch: u64) -> StakeState {
+    if deactivation_epoch == u64::MAX {
+        if activation_epoch >= current_epoch && activation_epoch != u64::MAX {
+            StakeState::Activating
+        } else {
+            StakeState::Active
+        }
+    } else if activation_epoch == deactivation_epoch || deactivation_epoch < current_epoch {
+        // Deactivated in the epoch it was delegated, or fully cooled down
+        StakeState::Inactive
+    } else {
+        StakeState::Deactivating
+    }
+}
+
+pub fn parse_stake_account(
+    address: &Pubkey,
+    lamports: u64,
+    data: &[u8],
+    current_epoch: u64,
+) -> Result<StakePosition> {
+    let mut reader = ByteReader::new(data);
+    let tag = reader.read_u32()?;
+    if tag != STATE_INITIALIZED && tag != STATE_STAKE {
+        bail!("Stake account {} is not initialized", address);
+    }
+
+    reader.skip(8)?; // rent exempt reserve
+    let staker = reader.read_pubkey()?;
+    let withdrawer = reader.read_pubkey()?;
+
+    let mut position = StakePosition {
+        address: address.to_string(),
+        staker: staker.to_string(),
+        withdrawer: withdrawer.to_string(),
+        state: StakeState::Initialized,
+        validator: None,
+        lamports,
+        delegated_lamports: 0,
+        value_usd: 0.0,
+    };
+
+    if tag == STATE_STAKE {
+        let mut delegation = ByteReader::at(data, DELEGATION_OFFSET);
+        let voter = delegation.read_pubkey()?;
+        let stake = delegation.read_u64()?;
+        let activation_epoch = delegation.read_u64()?;
+        let deactivation_epoch = delegation.read_u64()?;
+
+        position.state = stake_state(activation_epoch, deactivation_epoch, current_epoch);
+        position.validator = Some(voter.to_string());
+        position.delegated_lamports = stake;
+    }
+
+    Ok(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stake_account(staker: &Pubkey, voter: &Pubkey, activation: u64, deactivation: u64) -> Vec<u8> {
+        let mut data = STATE_STAKE.to_le_bytes().to_vec();
+        data.extend_from_slice(&2_282_880u64.to_le_bytes());
+        data.extend_from_slice(staker.as_ref());
+        data.extend_from_slice(staker.as_ref());
+        data.extend_from_slice(&[0; 48]); // lockup
+        data.extend_from_slice(voter.as_ref());
+        data.extend_from_slice(&5_000_000_000u64.to_le_bytes());
+        data.extend_from_slice(&activation.to_le_bytes());
+        data.extend_from_slice(&deactivation.to_le_bytes());
+        data.resize(STAKE_ACCOUNT_LEN, 0);
+        data
+    }
+
+    #[test]
+    fn test_parse_delegated_stake_account() {
+        let (address, staker, voter) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let data = stake_account(&staker, &voter, 500, u64::MAX);
+
+        let position = parse_stake_account(&address, 5_002_282_880, &data, 600).unwrap();
+        assert_eq!(position.staker, staker.to_string());
+        assert_eq!(position.validator, Some(voter.to_string()));
+        assert_eq!(position.delegated_lamports, 5_000_000_000);
+        assert_eq!(position.lamports, 5_002_282_880);
+        assert_eq!(position.state, StakeState::Active);
+    }
+
+    #[test]
+    fn test_stake_state_lifecycle() {
+        assert_eq!(stake_state(600, u64::MAX, 600), StakeState::Activating);
+        assert_eq!(stake_state(599, u64::MAX, 600), StakeState::Active);
+        // Genesis stake is active from the start
+        assert_eq!(stake_state(u64::MAX, u64::MAX, 600), StakeState::Active);
+        assert_eq!(stake_state(500, 600, 600), StakeState::Deactivating);
+        assert_eq!(stake_state(500, 599, 600), StakeState::Inactive);
+        assert_eq!(stake_state(600, 600, 600), StakeState::Inactive);
+    }
+
+    #[test]
+    fn test_initialized_stake_account_has_no_validator() {
+        let staker = Pubkey::new_unique();
+        let mut data = stake_account(&staker, &Pubkey::new_unique(), 0, 0);
+        data[..4].copy_from_slice(&STATE_INITIALIZED.to_le_bytes());
+
+        let position = parse_stake_account(&Pubkey::new_unique(), 2_282_880, &data, 600).unwrap();
+        assert_eq!(position.state, StakeState::Initialized);
+        assert_eq!(position.validator, None);
+        assert_eq!(position.delegated_lamports, 0);
+    }
+}
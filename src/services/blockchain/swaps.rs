@@ -0,0 +1,621 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::UiTransactionTokenBalance;
+
+use super::decoder::{InstructionContext, InstructionDecoder};
+use crate::models::{EventKind, SwapLeg, TransactionEvent};
+use crate::utils::helpers::format_token_amount;
+
+pub const JUPITER_V6_PROGRAM_ID: Pubkey = pubkey!("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4");
+pub const RAYDIUM_AMM_V4_PROGRAM_ID: Pubkey = pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+pub const RAYDIUM_CLMM_PROGRAM_ID: Pubkey = pubkey!("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK");
+pub const ORCA_WHIRLPOOL_PROGRAM_ID: Pubkey = pubkey!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc");
+
+// Anchor instruction discriminators: sha256("global:<name>")[..8]
+const ROUTE: [u8; 8] = [229, 23, 203, 151, 122, 227, 173, 42];
+const SHARED_ACCOUNTS_ROUTE: [u8; 8] = [193, 32, 155, 51, 65, 214, 156, 129];
+const EXACT_OUT_ROUTE: [u8; 8] = [208, 51, 239, 151, 123, 43, 237, 92];
+const SHARED_ACCOUNTS_EXACT_OUT_ROUTE: [u8; 8] = [176, 209, 105, 168, 154, 125, 69, 62];
+const SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+const SWAP_V2: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+
+// Raydium AMM v4 SwapBaseIn / SwapBaseOut take 17 or 18 accounts depending on
+// whether the deprecated target orders account is passed
+const RAYDIUM_AMM_MIN_SWAP_ACCOUNTS: usize = 17;
+// Whirlpool swap args: amount, other_amount_threshold, sqrt_price_limit (u128),
+// amount_specified_is_input, then a_to_b
+const WHIRLPOOL_A_TO_B_OFFSET: usize = 8 + 8 + 8 + 16 + 1;
+
+// Decoders only know which of the trader's token accounts are involved;
+// mints, amounts and price are filled in by `settle_swaps`
+fn pending_swap(
+    dex: &str,
+    trader: String,
+    pool: Option<String>,
+    source_account: String,
+    destination_account: String,
+) -> EventKind {
+    EventKind::Swap {
+        dex: dex.to_string(),
+        trader,
+        pool,
+        route: Vec::new(),
+        input: SwapLeg {
+            account: source_account,
+            ..Default::default()
+        },
+        output: SwapLeg {
+            account: destination_account,
+            ..Default::default()
+        },
+        price: None,
+    }
+}
+
+fn discriminator(data: &[u8]) -> Option<[u8; 8]> {
+    data.get(..8)?.try_into().ok()
+}
+
+pub struct JupiterDecoder;
+
+impl JupiterDecoder {
+    // Anchor passes the program id in place of an omitted optional account
+    fn optional_account(instruction: &InstructionContext, index: usize) -> Option<String> {
+        instruction
+            .accounts
+            .get(index)
+            .filter(|key| **key != instruction.program_id)
+            .map(|key| key.to_string())
+    }
+}
+
+impl InstructionDecoder for JupiterDecoder {
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind> {
+        let (trader, source, destination) = match discriminator(instruction.data)? {
+            // The optional destination account overrides the user's own
+            ROUTE | EXACT_OUT_ROUTE => (
+                instruction.account(1)?,
+                instruction.account(2)?,
+                Self::optional_account(instruction, 4).or_else(|| instruction.account(3))?,
+            ),
+            SHARED_ACCOUNTS_ROUTE | SHARED_ACCOUNTS_EXACT_OUT_ROUTE => (
+                instruction.account(2)?,
+                instruction.account(3)?,
+                instruction.account(6)?,
+            ),
+            _ => return None,
+        };
+
+        // The route is recovered from the hop swaps Jupiter invokes
+        Some(pending_swap("jupiter", trader, None, source, destination))
+    }
+}
+
+pub struct RaydiumAmmDecoder;
+
+impl InstructionDecoder for RaydiumAmmDecoder {
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind> {
+        match instruction.data.first()? {
+            // SwapBaseIn / SwapBaseOut; the user's source, destination and
+            // owner are always the last three accounts
+            9 | 11 if instruction.accounts.len() >= RAYDIUM_AMM_MIN_SWAP_ACCOUNTS => {
+                let last = instruction.accounts.len() - 1;
+                Some(pending_swap(
+                    "raydium_amm",
+                    instruction.account(last)?,
+                    instruction.account(1),
+                    instruction.account(last - 2)?,
+                    instruction.account(last - 1)?,
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct RaydiumClmmDecoder;
+
+impl InstructionDecoder for RaydiumClmmDecoder {
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind> {
+        match discriminator(instruction.data)? {
+            // swap and swap_v2 share the leading accounts
+            SWAP | SWAP_V2 => Some(pending_swap(
+                "raydium_clmm",
+                instruction.account(0)?,
+                instruction.account(2),
+                instruction.account(3)?,
+                instruction.account(4)?,
+            )),
+            _ => None,
+        }
+    }
+}
+
+pub struct OrcaWhirlpoolDecoder;
+
+impl InstructionDecoder for OrcaWhirlpoolDecoder {
+    fn decode(&self, instruction: &InstructionContext) -> Option<EventKind> {
+        // (authority, whirlpool, owner account A, owner account B)
+        let (authority, whirlpool, account_a, account_b) = match discriminator(instruction.data)? {
+            SWAP => (1, 2, 3, 5),
+            SWAP_V2 => (3, 4, 7, 9),
+            _ => return None,
+        };
+
+        let a_to_b = *instruction.data.get(WHIRLPOOL_A_TO_B_OFFSET)? != 0;
+        let (source, destination) = if a_to_b {
+            (account_a, account_b)
+        } else {
+            (account_b, account_a)
+        };
+
+        Some(pending_swap(
+            "orca_whirlpool",
+            instruction.account(authority)?,
+            instruction.account(whirlpool),
+            instruction.account(source)?,
+            instruction.account(destination)?,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenAccountInfo {
+    pub mint: String,
+    pub owner: Option<String>,
+    pub decimals: u8,
+}
+
+// Token account address -> mint / owner, built from the status meta's token
+// balances. Pass pre balances before post so accounts closed or created in
+// the transaction are both covered.
+pub fn token_account_index<'a>(
+    account_keys: &[String],
+    token_balances: impl IntoIterator<Item = &'a UiTransactionTokenBalance>,
+) -> HashMap<String, TokenAccountInfo> {
+    token_balances
+        .into_iter()
+        .filter_map(|balance| {
+            let address = account_keys.get(balance.account_index as usize)?;
+            Some((
+                address.clone(),
+                TokenAccountInfo {
+                    mint: balance.mint.clone(),
+                    owner: Option::from(balance.owner.clone()),
+                    decimals: balance.ui_token_amount.decimals,
+                },
+            ))
+        })
+        .collect()
+}
+
+// Fills in transfer mints and recipients, then settles every swap against
+// the token transfers it caused
+pub fn settle_swaps(events: &mut [TransactionEvent], token_accounts: &HashMap<String, TokenAccountInfo>) {
+    for event in events.iter_mut() {
+        if let EventKind::TokenTransfer {
+            source,
+            destination,
+            mint,
+            decimals,
+            destination_owner,
+            ..
+        } = &mut event.kind
+        {
+            let info = token_accounts
+                .get(source.as_str())
+                .or_else(|| token_accounts.get(destination.as_str()));
+            if let Some(info) = info {
+                mint.get_or_insert_with(|| info.mint.clone());
+                decimals.get_or_insert(info.decimals);
+            }
+            if destination_owner.is_none() {
+                *destination_owner = token_accounts
+                    .get(destination.as_str())
+                    .and_then(|info| info.owner.clone());
+            }
+        }
+    }
+
+    for position in 0..events.len() {
+        let window = swap_window(events, position);
+        let (route, input, output) = match &events[position].kind {
+            EventKind::Swap {
+                pool, input, output, ..
+            } => settle(
+                &events[window],
+                pool.as_ref(),
+                &input.account,
+                &output.account,
+                token_accounts,
+            ),
+            _ => continue,
+        };
+
+        if let EventKind::Swap {
+            route: swap_route,
+            input: swap_input,
+            output: swap_output,
+            price,
+            ..
+        } = &mut events[position].kind
+        {
+            *price = effective_price(&input, &output);
+            *swap_route = route;
+            *swap_input = input;
+            *swap_output = output;
+        }
+    }
+}
+
+// Swaps that are not a hop of another swap, i.e. what the trader actually did
+pub fn outermost_swaps(events: &[TransactionEvent]) -> impl Iterator<Item = &TransactionEvent> {
+    events.iter().filter(move |event| {
+        matches!(event.kind, EventKind::Swap { .. })
+            && (event.inner_index.is_none()
+                || !events.iter().any(|parent| {
+                    parent.instruction_index == event.instruction_index
+                        && parent.inner_index.is_none()
+                        && matches!(parent.kind, EventKind::Swap { .. })
+                }))
+    })
+}
+
+// Events a swap is responsible for. A top-level swap owns all of its inner
+// instructions; a swap invoked by another program owns the inner
+// instructions up to the next swap (the next hop).
+fn swap_window(events: &[TransactionEvent], position: usize) -> Range<usize> {
+    let swap = &events[position];
+    let start = position + 1;
+    let end = events[start..]
+        .iter()
+        .position(|event| {
+            event.instruction_index != swap.instruction_index
+                || (swap.inner_index.is_some() && matches!(event.kind, EventKind::Swap { .. }))
+        })
+        .map_or(events.len(), |offset| start + offset);
+    start..end
+}
+
+fn settle(
+    window: &[TransactionEvent],
+    pool: Option<&String>,
+    source_account: &str,
+    destination_account: &str,
+    token_accounts: &HashMap<String, TokenAccountInfo>,
+) -> (Vec<String>, SwapLeg, SwapLeg) {
+    let leg = |account: &str| {
+        let info = token_accounts.get(account);
+        SwapLeg {
+            account: account.to_string(),
+            mint: info.map(|info| info.mint.clone()),
+            decimals: info.map(|info| info.decimals),
+            amount: 0,
+        }
+    };
+    let mut input = leg(source_account);
+    let mut output = leg(destination_account);
+    let mut route: Vec<String> = pool.cloned().into_iter().collect();
+
+    for event in window {
+        match &event.kind {
+            EventKind::TokenTransfer {
+                source,
+                destination,
+                mint,
+                amount,
+                decimals,
+                ..
+            } => {
+                if source == source_account {
+                    record_transfer(&mut input, *amount, mint, *decimals);
+                }
+                if destination == destination_account {
+                    record_transfer(&mut output, *amount, mint, *decimals);
+                }
+            }
+            EventKind::Swap { pool: Some(hop), .. } => route.push(hop.clone()),
+            _ => {}
+        }
+    }
+
+    (route, input, output)
+}
+
+fn record_transfer(leg: &mut SwapLeg, amount: u64, mint: &Option<String>, decimals: Option<u8>) {
+    leg.amount = leg.amount.saturating_add(amount);
+    if leg.mint.is_none() {
+        leg.mint = mint.clone();
+    }
+    leg.decimals = leg.decimals.or(decimals);
+}
+
+fn effective_price(input: &SwapLeg, output: &SwapLeg) -> Option<f64> {
+    match (input.decimals, output.decimals) {
+        (Some(input_decimals), Some(output_decimals)) if input.amount > 0 => Some(
+            format_token_amount(output.amount, output_decimals) / format_token_amount(input.amount, input_decimals),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::blockchain::decoder::DecoderRegistry;
+    use crate::services::blockchain::TOKEN_PROGRAM_ID;
+    use solana_sdk::instruction::CompiledInstruction;
+
+    const USER: u8 = 0;
+    const USER_SOL: u8 = 1;
+    const USER_USDC: u8 = 2;
+    const USER_BONK: u8 = 3;
+    const VAULT_SOL: u8 = 4;
+    const VAULT_USDC: u8 = 5;
+    const VAULT_BONK: u8 = 6;
+    const RAYDIUM_POOL: u8 = 7;
+    const WHIRLPOOL: u8 = 8;
+    const TOKEN_PROGRAM: u8 = 9;
+    const JUPITER: u8 = 10;
+    const RAYDIUM: u8 = 11;
+    const ORCA: u8 = 12;
+    const FILLER: u8 = 13;
+
+    fn account_keys() -> Vec<Pubkey> {
+        let mut keys: Vec<Pubkey> = (0..TOKEN_PROGRAM).map(|_| Pubkey::new_unique()).collect();
+        keys.extend([
+            TOKEN_PROGRAM_ID,
+            JUPITER_V6_PROGRAM_ID,
+            RAYDIUM_AMM_V4_PROGRAM_ID,
+            ORCA_WHIRLPOOL_PROGRAM_ID,
+            Pubkey::new_unique(),
+        ]);
+        keys
+    }
+
+    fn transfer(source: u8, destination: u8, amount: u64) -> CompiledInstruction {
+        let mut data = vec![3];
+        data.extend_from_slice(&amount.to_le_bytes());
+        CompiledInstruction {
+            program_id_index: TOKEN_PROGRAM,
+            accounts: vec![source, destination, USER],
+            data,
+        }
+    }
+
+    fn raydium_swap(source: u8, destination: u8) -> CompiledInstruction {
+        let mut accounts = vec![TOKEN_PROGRAM, RAYDIUM_POOL];
+        accounts.extend([FILLER; 13]);
+        accounts.extend([source, destination, USER]);
+
+        let mut data = vec![9];
+        data.extend_from_slice(&[0; 16]);
+        CompiledInstruction {
+            program_id_index: RAYDIUM,
+            accounts,
+            data,
+        }
+    }
+
+    fn whirlpool_swap(account_a: u8, account_b: u8, a_to_b: bool) -> CompiledInstruction {
+        let mut data = SWAP.to_vec();
+        data.resize(WHIRLPOOL_A_TO_B_OFFSET, 0);
+        data.push(a_to_b as u8);
+        CompiledInstruction {
+            program_id_index: ORCA,
+            accounts: vec![
+                TOKEN_PROGRAM,
+                USER,
+                WHIRLPOOL,
+                account_a,
+                FILLER,
+                account_b,
+                FILLER,
+                FILLER,
+                FILLER,
+                FILLER,
+                FILLER,
+            ],
+            data,
+        }
+    }
+
+    fn token_accounts(keys: &[Pubkey]) -> HashMap<String, TokenAccountInfo> {
+        [
+            (USER_SOL, "sol", 9),
+            (VAULT_SOL, "sol", 9),
+            (USER_USDC, "usdc", 6),
+            (VAULT_USDC, "usdc", 6),
+            (USER_BONK, "bonk", 5),
+            (VAULT_BONK, "bonk", 5),
+        ]
+        .into_iter()
+        .map(|(index, mint, decimals)| {
+            (
+                keys[index as usize].to_string(),
+                TokenAccountInfo {
+                    mint: mint.to_string(),
+                    owner: None,
+                    decimals,
+                },
+            )
+        })
+        .collect()
+    }
+
+    fn swap_fields(event: &TransactionEvent) -> (Option<&str>, u64, Option<&str>, u64, Option<f64>, &[String]) {
+        match &event.kind {
+            EventKind::Swap {
+                input,
+                output,
+                price,
+                route,
+                ..
+            } => (
+                input.mint.as_deref(),
+                input.amount,
+                output.mint.as_deref(),
+                output.amount,
+                *price,
+                route,
+            ),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_jupiter_route_settles_across_hops() {
+        let keys = account_keys();
+        let mut route_data = ROUTE.to_vec();
+        route_data.extend_from_slice(&[0; 19]);
+        let route = CompiledInstruction {
+            program_id_index: JUPITER,
+            // No destination override: the program id stands in for it
+            accounts: vec![TOKEN_PROGRAM, USER, USER_SOL, USER_BONK, JUPITER],
+            data: route_data,
+        };
+        let inner = HashMap::from([(
+            0,
+            vec![
+                raydium_swap(USER_SOL, USER_USDC),
+                transfer(USER_SOL, VAULT_SOL, 1_000_000_000),
+                transfer(VAULT_USDC, USER_USDC, 150_000_000),
+                whirlpool_swap(USER_BONK, USER_USDC, false),
+                transfer(USER_USDC, VAULT_USDC, 150_000_000),
+                transfer(VAULT_BONK, USER_BONK, 750_000_000_000),
+            ],
+        )]);
+
+        let registry = DecoderRegistry::with_builtin_decoders();
+        let mut events = registry.decode_transaction(&keys, &[route], &inner);
+        settle_swaps(&mut events, &token_accounts(&keys));
+
+        let pools = [
+            keys[RAYDIUM_POOL as usize].to_string(),
+            keys[WHIRLPOOL as usize].to_string(),
+        ];
+        assert_eq!(
+            swap_fields(&events[0]),
+            (
+                Some("sol"),
+                1_000_000_000,
+                Some("bonk"),
+                750_000_000_000,
+                Some(7_500_000.0),
+                &pools[..]
+            )
+        );
+        assert_eq!(
+            swap_fields(&events[1]),
+            (
+                Some("sol"),
+                1_000_000_000,
+                Some("usdc"),
+                150_000_000,
+                Some(150.0),
+                &pools[..1]
+            )
+        );
+        assert_eq!(
+            swap_fields(&events[4]),
+            (
+                Some("usdc"),
+                150_000_000,
+                Some("bonk"),
+                750_000_000_000,
+                Some(50_000.0),
+                &pools[1..]
+            )
+        );
+
+        // Transfers pick up their mint from the token balances
+        assert!(matches!(
+            &events[2].kind,
+            EventKind::TokenTransfer { mint: Some(mint), decimals: Some(9), .. } if mint == "sol"
+        ));
+
+        // Only the aggregator swap counts as something the trader did
+        let outermost: Vec<usize> = outermost_swaps(&events)
+            .map(|event| event.inner_index.map_or(0, |index| index + 1))
+            .collect();
+        assert_eq!(outermost, vec![0]);
+    }
+
+    #[test]
+    fn test_direct_swap_invoked_by_another_program_is_outermost() {
+        let keys = account_keys();
+        let outer = CompiledInstruction {
+            program_id_index: FILLER,
+            accounts: vec![USER],
+            data: vec![1],
+        };
+        let inner = HashMap::from([(
+            0,
+            vec![
+                raydium_swap(USER_USDC, USER_SOL),
+                transfer(USER_USDC, VAULT_USDC, 300_000_000),
+                transfer(VAULT_SOL, USER_SOL, 2_000_000_000),
+            ],
+        )]);
+
+        let registry = DecoderRegistry::with_builtin_decoders();
+        let mut events = registry.decode_transaction(&keys, &[outer], &inner);
+        settle_swaps(&mut events, &token_accounts(&keys));
+
+        let outermost: Vec<&TransactionEvent> = outermost_swaps(&events).collect();
+        assert_eq!(outermost.len(), 1);
+        let (input_mint, _, output_mint, _, price, _) = swap_fields(outermost[0]);
+        assert_eq!((input_mint, output_mint), (Some("usdc"), Some("sol")));
+        assert!((price.unwrap() - 1.0 / 150.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_unmatched_swap_has_no_price() {
+        let keys = account_keys();
+        let registry = DecoderRegistry::with_builtin_decoders();
+        let mut events =
+            registry.decode_transaction(&keys, &[whirlpool_swap(USER_SOL, USER_USDC, true)], &HashMap::new());
+        settle_swaps(&mut events, &HashMap::new());
+
+        assert_eq!(
+            swap_fields(&events[0]),
+            (None, 0, None, 0, None, &[keys[WHIRLPOOL as usize].to_string()][..])
+        );
+    }
+
+    #[test]
+    fn test_transfer_recipient_is_resolved_from_token_balances() {
+        let mut events = vec![TransactionEvent {
+            program_id: "token".to_string(),
+            instruction_index: 0,
+            inner_index: None,
+            kind: EventKind::TokenTransfer {
+                source: "source".to_string(),
+                destination: "destination".to_string(),
+                authority: "sender".to_string(),
+                mint: None,
+                amount: 10,
+                decimals: None,
+                destination_owner: None,
+            },
+        }];
+        let token_accounts = HashMap::from([(
+            "destination".to_string(),
+            TokenAccountInfo {
+                mint: "usdc".to_string(),
+                owner: Some("recipient".to_string()),
+                decimals: 6,
+            },
+        )]);
+
+        settle_swaps(&mut events, &token_accounts);
+        assert!(matches!(
+            &events[0].kind,
+            EventKind::TokenTransfer { mint: Some(mint), destination_owner: Some(owner), .. }
+                if mint == "usdc" && owner == "recipient"
+        ));
+    }
+}
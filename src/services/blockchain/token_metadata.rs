@@ -0,0 +1,257 @@
+use anyhow::{bail, Result};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+
+use super::layout::ByteReader;
+
+pub const METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+const MINT_LEN: usize = 82;
+// Token-2022 pads mints to the token account size before the account type byte
+const TOKEN_2022_ACCOUNT_TYPE_OFFSET: usize = 165;
+const TOKEN_2022_MINT_ACCOUNT_TYPE: u8 = 1;
+const TOKEN_METADATA_EXTENSION: u16 = 19;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MintInfo {
+    pub mint_authority: Option<Pubkey>,
+    pub supply: u64,
+    pub decimals: u8,
+    pub freeze_authority: Option<Pubkey>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    // Metaplex only; None on accounts written before the field existed
+    pub token_standard: Option<u8>,
+    pub collection: Option<CollectionRef>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionRef {
+    pub key: Pubkey,
+    pub verified: bool,
+}
+
+pub fn metadata_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &METADATA_PROGRAM_ID,
+    )
+    .0
+}
+
+// Base mint layout, shared by SPL Token and Token-2022
+pub fn parse_mint(data: &[u8]) -> Result<MintInfo> {
+    if data.len() < MINT_LEN {
+        bail!("Not a mint account: {} bytes", data.len());
+    }
+
+    let mut reader = ByteReader::new(data);
+    let mint_authority = reader.read_coption_pubkey()?;
+    let supply = reader.read_u64()?;
+    let decimals = reader.read_u8()?;
+    if !reader.read_bool()? {
+        bail!("Mint account is not initialized");
+    }
+    let freeze_authority = reader.read_coption_pubkey()?;
+
+    Ok(MintInfo {
+        mint_authority,
+        supply,
+        decimals,
+        freeze_authority,
+    })
+}
+
+pub fn parse_metaplex_metadata(data: &[u8]) -> Result<TokenMetadata> {
+    let mut reader = ByteReader::new(data);
+    reader.read_u8()?; // key
+    reader.read_pubkey()?; // update authority
+    reader.read_pubkey()?; // mint
+
+    let mut metadata = TokenMetadata {
+        name: reader.read_string()?,
+        symbol: reader.read_string()?,
+        uri: reader.read_string()?,
+        ..Default::default()
+    };
+
+    // Older metadata accounts end before these fields; keep what was read
+    let _ = parse_metaplex_extensions(&mut reader, &mut metadata);
+    Ok(metadata)
+}
+
+// The fields after `uri`, up to and including the collection
+fn parse_metaplex_extensions(reader: &mut ByteReader, metadata: &mut TokenMetadata) -> Result<()> {
+    reader.skip(2)?; // seller fee basis points
+    if reader.read_bool()? {
+        // creators: address, verified, share
+        let creators = reader.read_u32()? as usize;
+        reader.skip(creators * 34)?;
+    }
+    reader.skip(2)?; // primary sale happened, is mutable
+    if reader.read_bool()? {
+        reader.skip(1)?; // edition nonce
+    }
+    if reader.read_bool()? {
+        metadata.token_standard = Some(reader.read_u8()?);
+    }
+    if reader.read_bool()? {
+        let verified = reader.read_bool()?;
+        metadata.collection = Some(CollectionRef {
+            key: reader.read_pubkey()?,
+            verified,
+        });
+    }
+    Ok(())
+}
+
+// Walks the Token-2022 extension TLVs looking for the TokenMetadata extension
+pub fn parse_token_2022_metadata(data: &[u8]) -> Result<Option<TokenMetadata>> {
+    if data.len() <= TOKEN_2022_ACCOUNT_TYPE_OFFSET
+        || data[TOKEN_2022_ACCOUNT_TYPE_OFFSET] != TOKEN_2022_MINT_ACCOUNT_TYPE
+    {
+        return Ok(None);
+    }
+
+    let mut reader = ByteReader::at(data, TOKEN_2022_ACCOUNT_TYPE_OFFSET + 1);
+    while reader.remaining() >= 4 {
+        let extension_type = reader.read_u16()?;
+        let length = reader.read_u16()? as usize;
+        let value = reader.read_bytes(length)?;
+
+        if extension_type == TOKEN_METADATA_EXTENSION {
+            let mut value = ByteReader::new(value);
+            value.read_pubkey()?; // update authority
+            value.read_pubkey()?; // mint
+
+            return Ok(Some(TokenMetadata {
+                name: value.read_string()?,
+                symbol: value.read_string()?,
+                uri: value.read_string()?,
+                ..Default::default()
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn borsh_string(value: &str, padded_len: usize) -> Vec<u8> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize(padded_len.max(bytes.len()), 0);
+        let mut out = (bytes.len() as u32).to_le_bytes().to_vec();
+        out.extend(bytes);
+        out
+    }
+
+    fn mint_bytes(authority: Option<Pubkey>, supply: u64, decimals: u8) -> Vec<u8> {
+        let mut data = Vec::with_capacity(MINT_LEN);
+        data.extend_from_slice(&(authority.is_some() as u32).to_le_bytes());
+        data.extend_from_slice(authority.unwrap_or_default().as_ref());
+        data.extend_from_slice(&supply.to_le_bytes());
+        data.push(decimals);
+        data.push(1);
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(Pubkey::default().as_ref());
+        data
+    }
+
+    #[test]
+    fn test_parse_mint() {
+        let authority = Pubkey::new_unique();
+        let mint = parse_mint(&mint_bytes(Some(authority), 1_000_000, 6)).unwrap();
+
+        assert_eq!(mint.mint_authority, Some(authority));
+        assert_eq!(mint.supply, 1_000_000);
+        assert_eq!(mint.decimals, 6);
+        assert_eq!(mint.freeze_authority, None);
+    }
+
+    #[test]
+    fn test_parse_metaplex_metadata_trims_padding() {
+        let mut data = vec![4];
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend(borsh_string("Bonk", 32));
+        data.extend(borsh_string("BONK", 10));
+        data.extend(borsh_string("https://arweave.net/bonk.json", 200));
+
+        let metadata = parse_metaplex_metadata(&data).unwrap();
+        assert_eq!(metadata.name, "Bonk");
+        assert_eq!(metadata.symbol, "BONK");
+        assert_eq!(metadata.uri, "https://arweave.net/bonk.json");
+        assert_eq!(metadata.collection, None);
+    }
+
+    #[test]
+    fn test_parse_metaplex_metadata_collection() {
+        let collection = Pubkey::new_unique();
+        let mut data = vec![4];
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend(borsh_string("Mad Lad #1", 32));
+        data.extend(borsh_string("MAD", 10));
+        data.extend(borsh_string("https://madlads.s3.us-west-2.amazonaws.com/json/1.json", 200));
+        data.extend_from_slice(&500u16.to_le_bytes());
+        data.push(1); // creators
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[9u8; 68]);
+        data.extend_from_slice(&[1, 1]); // primary sale happened, is mutable
+        data.extend_from_slice(&[1, 254]); // edition nonce
+        data.extend_from_slice(&[1, 4]); // programmable non-fungible
+        data.extend_from_slice(&[1, 1]); // verified collection
+        data.extend_from_slice(collection.as_ref());
+
+        let metadata = parse_metaplex_metadata(&data).unwrap();
+        assert_eq!(metadata.name, "Mad Lad #1");
+        assert_eq!(metadata.token_standard, Some(4));
+        assert_eq!(
+            metadata.collection,
+            Some(CollectionRef {
+                key: collection,
+                verified: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_token_2022_metadata_extension() {
+        let mut data = mint_bytes(None, 5, 9);
+        data.resize(TOKEN_2022_ACCOUNT_TYPE_OFFSET, 0);
+        data.push(TOKEN_2022_MINT_ACCOUNT_TYPE);
+
+        // An unrelated extension (metadata pointer) before the metadata itself
+        data.extend_from_slice(&18u16.to_le_bytes());
+        data.extend_from_slice(&64u16.to_le_bytes());
+        data.extend_from_slice(&[7u8; 64]);
+
+        let mut value = Vec::new();
+        value.extend_from_slice(Pubkey::new_unique().as_ref());
+        value.extend_from_slice(Pubkey::new_unique().as_ref());
+        value.extend(borsh_string("PayPal USD", 0));
+        value.extend(borsh_string("PYUSD", 0));
+        value.extend(borsh_string("", 0));
+        value.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&TOKEN_METADATA_EXTENSION.to_le_bytes());
+        data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        data.extend(value);
+
+        let metadata = parse_token_2022_metadata(&data).unwrap().unwrap();
+        assert_eq!(metadata.name, "PayPal USD");
+        assert_eq!(metadata.symbol, "PYUSD");
+    }
+
+    #[test]
+    fn test_plain_mint_has_no_token_2022_metadata() {
+        assert_eq!(parse_token_2022_metadata(&mint_bytes(None, 1, 0)).unwrap(), None);
+    }
+}
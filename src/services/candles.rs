@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use tracing::warn;
+
+use crate::db::mongodb::MongoDB;
+use crate::models::{Candle, Resolution};
+use crate::services::prices::PriceProvider;
+
+// Where candles are kept. `MongoDB` stores them in time-series collections.
+#[async_trait]
+pub trait CandleStorage: Send + Sync {
+    // Candles opening in `[start, end)`, oldest first, one per open time
+    async fn get_candles(
+        &self,
+        mint: &str,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>>;
+
+    async fn insert_candles(&self, resolution: Resolution, candles: &[Candle]) -> Result<()>;
+}
+
+#[async_trait]
+impl CandleStorage for MongoDB {
+    async fn get_candles(
+        &self,
+        mint: &str,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        MongoDB::get_candles(self, mint, resolution, start, end).await
+    }
+
+    async fn insert_candles(&self, resolution: Resolution, candles: &[Candle]) -> Result<()> {
+        MongoDB::insert_candles(self, resolution, candles).await
+    }
+}
+
+// OHLCV history per mint, kept in storage and filled in on demand
+pub struct CandleStore {
+    storage: Arc<dyn CandleStorage>,
+    provider: Arc<dyn PriceProvider>,
+    // One fill at a time per series, so concurrent requests for the same gap
+    // do not both insert it
+    fills: Mutex<HashMap<(String, Resolution), Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl CandleStore {
+    pub fn new(storage: Arc<dyn CandleStorage>, provider: Arc<dyn PriceProvider>) -> Self {
+        Self {
+            storage,
+            provider,
+            fills: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Candles for `mint` opening in `[start, end)`, oldest first. Periods
+    // missing from the store are rolled up from stored finer candles where
+    // those cover the whole period, then fetched from the price provider, and
+    // saved either way. Periods the provider has no data for are left out.
+    pub async fn candles(
+        &self,
+        mint: &str,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let start = resolution.floor(start);
+        // The current period is still moving, so it is never stored
+        let end = end.min(resolution.floor(Utc::now()));
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let fill = self
+            .fills
+            .lock()
+            .unwrap()
+            .entry((mint.to_string(), resolution))
+            .or_default()
+            .clone();
+        let _fill = fill.lock().await;
+
+        let mut candles = self.storage.get_candles(mint, resolution, start, end).await?;
+        let mut filled = Vec::new();
+        for (gap_start, gap_end) in missing_ranges(&candles, resolution, start, end) {
+            let mut gap = match resolution.finer() {
+                Some(finer) => rollup(
+                    &self.storage.get_candles(mint, finer, gap_start, gap_end).await?,
+                    resolution,
+                ),
+                None => Vec::new(),
+            };
+            for (from, to) in missing_ranges(&gap, resolution, gap_start, gap_end) {
+                match self.provider.get_candles(mint, resolution, from, to).await {
+                    Ok(provided) => gap.extend(provided),
+                    Err(e) => warn!("Failed to backfill {} candles for {}: {}", resolution.as_str(), mint, e),
+                }
+            }
+            filled.extend(gap);
+        }
+
+        let stored: HashSet<DateTime<Utc>> = candles.iter().map(|candle| candle.open_time).collect();
+        filled.retain(|candle| {
+            candle.open_time >= start
+                && candle.open_time < end
+                && candle.open_time == resolution.floor(candle.open_time)
+                && !stored.contains(&candle.open_time)
+        });
+        filled.sort_by_key(|candle| candle.open_time);
+        filled.dedup_by_key(|candle| candle.open_time);
+        self.storage.insert_candles(resolution, &filled).await?;
+
+        candles.extend(filled);
+        candles.sort_by_key(|candle| candle.open_time);
+        Ok(candles)
+    }
+}
+
+// Candles of `resolution` built from finer ones. Only periods with every
+// finer candle present are rolled up; a partial period would misreport its
+// open, close and range.
+pub fn rollup(finer: &[Candle], resolution: Resolution) -> Vec<Candle> {
+    let mut periods: BTreeMap<DateTime<Utc>, BTreeMap<DateTime<Utc>, &Candle>> = BTreeMap::new();
+    for candle in finer
+        .iter()
+        .filter(|candle| candle.resolution.seconds() < resolution.seconds())
+    {
+        periods
+            .entry(resolution.floor(candle.open_time))
+            .or_default()
+            .insert(candle.open_time, candle);
+    }
+
+    periods
+        .into_iter()
+        .filter_map(|(open_time, period)| {
+            let first = *period.values().next()?;
+            let last = *period.values().next_back()?;
+            if period.len() as i64 != resolution.seconds() / first.resolution.seconds() {
+                return None;
+            }
+            Some(Candle {
+                mint: first.mint.clone(),
+                resolution,
+                open_time,
+                open: first.open,
+                high: period.values().map(|candle| candle.high).fold(f64::MIN, f64::max),
+                low: period.values().map(|candle| candle.low).fold(f64::MAX, f64::min),
+                close: last.close,
+                volume: period.values().map(|candle| candle.volume).sum(),
+            })
+        })
+        .collect()
+}
+
+// Ranges within `[start, end)` with no candle in `candles`, adjacent missing
+// periods merged into one range
+pub fn missing_ranges(
+    candles: &[Candle],
+    resolution: Resolution,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let present: HashSet<DateTime<Utc>> = candles.iter().map(|candle| candle.open_time).collect();
+    let step = Duration::seconds(resolution.seconds());
+
+    let mut ranges: Vec<(DateTime<Utc>, DateTime<Utc>)> = Vec::new();
+    let mut time = resolution.floor(start);
+    while time < end {
+        let period_end = (time + step).min(end);
+        if !present.contains(&time) {
+            match ranges.last_mut() {
+                Some((_, range_end)) if *range_end == time => *range_end = period_end,
+                _ => ranges.push((time, period_end)),
+            }
+        }
+        time += step;
+    }
+    ranges
+}
+
+// Candles kept in memory, standing in for Mongo in tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryCandles {
+    candles: Mutex<Vec<Candle>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl CandleStorage for MemoryCandles {
+    async fn get_candles(
+        &self,
+        mint: &str,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let mut candles: Vec<Candle> = self
+            .candles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|candle| candle.mint == mint && candle.resolution == resolution)
+            .filter(|candle| candle.open_time >= start && candle.open_time < end)
+            .cloned()
+            .collect();
+        candles.sort_by_key(|candle| candle.open_time);
+        candles.dedup_by_key(|candle| candle.open_time);
+        Ok(candles)
+    }
+
+    async fn insert_candles(&self, _resolution: Resolution, candles: &[Candle]) -> Result<()> {
+        self.candles.lock().unwrap().extend_from_slice(candles);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::prices::StaticPriceProvider;
+    use crate::utils::helpers::from_unix_timestamp;
+
+    // 2023-11-14 22:00 UTC, on an hour boundary
+    const HOUR: i64 = 1_700_000_000 - 1_700_000_000 % 3_600;
+
+    fn candle(resolution: Resolution, open_time: i64, open: f64, close: f64) -> Candle {
+        Candle {
+            mint: "mint".to_string(),
+            resolution,
+            open_time: from_unix_timestamp(open_time),
+            open,
+            high: open.max(close) + 1.0,
+            low: open.min(close) - 1.0,
+            close,
+            volume: 10.0,
+        }
+    }
+
+    #[test]
+    fn test_rollup_uses_complete_periods_only() {
+        let mut minutes: Vec<Candle> = (0..60)
+            .map(|i| candle(Resolution::Minute, HOUR + i * 60, 100.0 + i as f64, 101.0 + i as f64))
+            .collect();
+        // The next hour is missing its last minute
+        minutes.extend((0..59).map(|i| candle(Resolution::Minute, HOUR + 3_600 + i * 60, 1.0, 1.0)));
+
+        let hours = rollup(&minutes, Resolution::Hour);
+        assert_eq!(hours.len(), 1);
+        let hour = &hours[0];
+        assert_eq!(hour.resolution, Resolution::Hour);
+        assert_eq!(hour.open_time, from_unix_timestamp(HOUR));
+        assert_eq!((hour.open, hour.close), (100.0, 160.0));
+        assert_eq!((hour.high, hour.low), (161.0, 99.0));
+        assert_eq!(hour.volume, 600.0);
+    }
+
+    #[test]
+    fn test_missing_ranges() {
+        let candles = vec![
+            candle(Resolution::Hour, HOUR + 3_600, 1.0, 1.0),
+            candle(Resolution::Hour, HOUR + 4 * 3_600, 1.0, 1.0),
+        ];
+        let start = from_unix_timestamp(HOUR + 60);
+        let end = from_unix_timestamp(HOUR + 5 * 3_600 + 1_800);
+
+        assert_eq!(
+            missing_ranges(&candles, Resolution::Hour, start, end),
+            vec![
+                (from_unix_timestamp(HOUR), from_unix_timestamp(HOUR + 3_600)),
+                (
+                    from_unix_timestamp(HOUR + 2 * 3_600),
+                    from_unix_timestamp(HOUR + 4 * 3_600)
+                ),
+                (from_unix_timestamp(HOUR + 5 * 3_600), end),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_candles_fill_gaps_from_finer_candles_then_provider() {
+        let storage = Arc::new(MemoryCandles::default());
+        let mut stored = vec![candle(Resolution::Hour, HOUR, 10.0, 11.0)];
+        // A complete second hour of minutes
+        stored.extend((0..60).map(|i| candle(Resolution::Minute, HOUR + 3_600 + i * 60, 20.0, 21.0)));
+        storage.candles.lock().unwrap().extend(stored);
+
+        let provider = StaticPriceProvider::default().with_candles(vec![
+            candle(Resolution::Hour, HOUR + 2 * 3_600, 30.0, 31.0),
+            candle(Resolution::Hour, HOUR + 3 * 3_600, 40.0, 41.0),
+        ]);
+        let store = CandleStore::new(storage.clone(), Arc::new(provider));
+        let start = from_unix_timestamp(HOUR);
+        let end = from_unix_timestamp(HOUR + 4 * 3_600);
+
+        let candles = store.candles("mint", Resolution::Hour, start, end).await.unwrap();
+        let closes: Vec<f64> = candles.iter().map(|candle| candle.close).collect();
+        assert_eq!(closes, vec![11.0, 21.0, 31.0, 41.0]);
+        assert_eq!(candles[1].volume, 600.0);
+
+        // The filled hours were saved, once
+        let hourly = || {
+            storage
+                .candles
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|candle| candle.resolution == Resolution::Hour)
+                .count()
+        };
+        assert_eq!(hourly(), 4);
+        assert_eq!(
+            store.candles("mint", Resolution::Hour, start, end).await.unwrap(),
+            candles
+        );
+        assert_eq!(hourly(), 4);
+    }
+}
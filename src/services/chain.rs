@@ -0,0 +1,69 @@
+// src/services/chain.rs
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use solana_client::rpc_response::RpcPrioritizationFee;
+use tokio::sync::mpsc;
+
+use crate::models::{Commitment, Nft, StakePosition, Token, TokenBalance, Transaction};
+use crate::services::subscriptions::{WalletTracker, WalletUpdate};
+
+pub mod fixture;
+
+pub use fixture::FixtureChain;
+
+// Buffer between a chain subscription and whoever consumes its updates
+pub const SUBSCRIPTION_BUFFER: usize = 1024;
+
+// Live updates for a set of wallets, and a handle to change the set
+pub struct Subscription {
+    pub updates: mpsc::Receiver<WalletUpdate>,
+    pub tracker: Arc<dyn WalletTracker>,
+}
+
+// Everything the API needs from a chain. `SolanaClient` is the production
+// implementation; `FixtureChain` serves canned data for tests and local runs.
+#[async_trait]
+pub trait ChainClient: Send + Sync {
+    // Commitment used when a caller does not ask for one
+    fn commitment(&self) -> Commitment;
+
+    async fn get_wallet_tokens(&self, address: &str, commitment: Commitment) -> Result<Vec<TokenBalance>>;
+
+    async fn get_stake_positions(&self, address: &str, commitment: Commitment) -> Result<Vec<StakePosition>>;
+
+    // SOL per token for each liquid staking token among `mints`
+    async fn get_liquid_staking_rates(&self, mints: &[String]) -> Result<HashMap<String, f64>>;
+
+    async fn get_nfts(&self, address: &str) -> Result<Vec<Nft>>;
+
+    async fn get_token_info(&self, address: &str) -> Result<Token>;
+
+    // Token info for each of `mints` in one pass; addresses that are not
+    // token mints are left out
+    async fn get_token_infos(&self, mints: &[String]) -> Result<HashMap<String, Token>>;
+
+    // Owner address of a Solana Name Service `.sol` domain
+    async fn resolve_domain(&self, domain: &str) -> Result<String>;
+
+    // The wallet's most recent transactions, newest first
+    async fn get_transactions(&self, address: &str, commitment: Commitment) -> Result<Vec<Transaction>>;
+
+    async fn get_transaction(
+        &self,
+        signature: &str,
+        wallet_address: &str,
+        commitment: Commitment,
+    ) -> Result<Transaction>;
+
+    // Per-slot minimum priority fees over recent slots, in micro-lamports
+    // per compute unit, to write-lock all of `accounts`
+    async fn get_recent_prioritization_fees(&self, accounts: &[String]) -> Result<Vec<RpcPrioritizationFee>>;
+
+    // Live updates for `wallets`. The first update is always `Subscribed`,
+    // and another follows whenever the tracked set changes; the subscription
+    // ends when the receiver is dropped.
+    async fn subscribe(&self, wallets: Vec<String>) -> Result<Subscription>;
+}
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use solana_client::rpc_response::RpcPrioritizationFee;
+use tokio::sync::mpsc;
+
+use super::{ChainClient, Subscription, SUBSCRIPTION_BUFFER};
+use crate::models::{Commitment, Nft, StakePosition, Token, TokenBalance, Transaction};
+use crate::services::subscriptions::{WalletTracker, WalletUpdate};
+
+// On-disk shape of a fixture chain, as JSON
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ChainFixture {
+    pub commitment: Commitment,
+    pub wallets: HashMap<String, WalletFixture>,
+    pub tokens: Vec<Token>,
+    pub liquid_staking_rates: HashMap<String, f64>,
+    pub prioritization_fees: Vec<RpcPrioritizationFee>,
+    // `.sol` domain to owner address
+    pub domains: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WalletFixture {
+    pub tokens: Vec<TokenBalance>,
+    pub stake_positions: Vec<StakePosition>,
+    pub nfts: Vec<Nft>,
+    // Newest first, like the RPC returns them
+    pub transactions: Vec<Transaction>,
+}
+
+// An in-memory chain serving a fixture. Wallets missing from the fixture
+// are empty; unknown tokens and signatures are errors. Subscriptions only
+// deliver what is pushed through `emit`.
+pub struct FixtureChain {
+    fixture: ChainFixture,
+    subscribers: Mutex<Vec<(Arc<Mutex<HashSet<String>>>, mpsc::Sender<WalletUpdate>)>>,
+}
+
+impl FixtureChain {
+    pub fn new(fixture: ChainFixture) -> Self {
+        Self {
+            fixture,
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read chain fixture {}", path.display()))?;
+        let fixture = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse chain fixture {}", path.display()))?;
+        Ok(Self::new(fixture))
+    }
+
+    // Delivers an update to every subscriber watching its wallet
+    pub fn emit(&self, update: WalletUpdate) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|(_, sender)| !sender.is_closed());
+
+        for (wallets, sender) in subscribers.iter() {
+            let watching = match &update {
+                WalletUpdate::Subscribed { .. } => true,
+                WalletUpdate::NativeBalance { wallet, .. }
+                | WalletUpdate::TokenAccount { wallet, .. }
+                | WalletUpdate::Activity { wallet, .. } => wallets.lock().unwrap().contains(wallet),
+            };
+            if watching {
+                // A full buffer drops the update, as a lagging websocket would
+                let _ = sender.try_send(update.clone());
+            }
+        }
+    }
+
+    fn wallet(&self, address: &str) -> Option<&WalletFixture> {
+        self.fixture.wallets.get(address)
+    }
+}
+
+#[async_trait]
+impl ChainClient for FixtureChain {
+    fn commitment(&self) -> Commitment {
+        self.fixture.commitment
+    }
+
+    async fn get_wallet_tokens(&self, address: &str, _commitment: Commitment) -> Result<Vec<TokenBalance>> {
+        Ok(self
+            .wallet(address)
+            .map(|wallet| wallet.tokens.clone())
+            .unwrap_or_default())
+    }
+
+    async fn get_stake_positions(&self, address: &str, _commitment: Commitment) -> Result<Vec<StakePosition>> {
+        Ok(self
+            .wallet(address)
+            .map(|wallet| wallet.stake_positions.clone())
+            .unwrap_or_default())
+    }
+
+    async fn get_liquid_staking_rates(&self, mints: &[String]) -> Result<HashMap<String, f64>> {
+        Ok(mints
+            .iter()
+            .filter_map(|mint| Some((mint.clone(), *self.fixture.liquid_staking_rates.get(mint)?)))
+            .collect())
+    }
+
+    async fn get_nfts(&self, address: &str) -> Result<Vec<Nft>> {
+        Ok(self
+            .wallet(address)
+            .map(|wallet| wallet.nfts.clone())
+            .unwrap_or_default())
+    }
+
+    async fn get_token_info(&self, address: &str) -> Result<Token> {
+        self.fixture
+            .tokens
+            .iter()
+            .find(|token| token.address == address)
+            .cloned()
+            .ok_or_else(|| anyhow!("{} is not a token mint", address))
+    }
+
+    async fn get_token_infos(&self, mints: &[String]) -> Result<HashMap<String, Token>> {
+        Ok(self
+            .fixture
+            .tokens
+            .iter()
+            .filter(|token| mints.contains(&token.address))
+            .map(|token| (token.address.clone(), token.clone()))
+            .collect())
+    }
+
+    async fn resolve_domain(&self, domain: &str) -> Result<String> {
+        self.fixture
+            .domains
+            .get(&domain.trim().to_lowercase())
+            .cloned()
+            .ok_or_else(|| anyhow!("{} is not registered", domain))
+    }
+
+    // A finalized read leaves out transactions the fixture marks confirmed
+    async fn get_transactions(&self, address: &str, commitment: Commitment) -> Result<Vec<Transaction>> {
+        Ok(self
+            .wallet(address)
+            .into_iter()
+            .flat_map(|wallet| &wallet.transactions)
+            .filter(|transaction| commitment == Commitment::Confirmed || transaction.commitment == commitment)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_transaction(
+        &self,
+        signature: &str,
+        wallet_address: &str,
+        commitment: Commitment,
+    ) -> Result<Transaction> {
+        self.get_transactions(wallet_address, commitment)
+            .await?
+            .into_iter()
+            .find(|transaction| transaction.signature == signature)
+            .ok_or_else(|| anyhow!("Transaction {} not found", signature))
+    }
+
+    // The fixture's fee levels apply regardless of accounts
+    async fn get_recent_prioritization_fees(&self, _accounts: &[String]) -> Result<Vec<RpcPrioritizationFee>> {
+        Ok(self.fixture.prioritization_fees.clone())
+    }
+
+    async fn subscribe(&self, wallets: Vec<String>) -> Result<Subscription> {
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        sender.try_send(WalletUpdate::Subscribed {
+            wallets: wallets.clone(),
+        })?;
+        let wallets = Arc::new(Mutex::new(wallets.into_iter().collect()));
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push((wallets.clone(), sender.clone()));
+        Ok(Subscription {
+            updates: receiver,
+            tracker: Arc::new(FixtureTracker { wallets, sender }),
+        })
+    }
+}
+
+// Changes what one fixture subscription watches. Like the websocket
+// streamer, every change is announced with a fresh `Subscribed`.
+struct FixtureTracker {
+    wallets: Arc<Mutex<HashSet<String>>>,
+    sender: mpsc::Sender<WalletUpdate>,
+}
+
+impl FixtureTracker {
+    fn update(&self, change: impl FnOnce(&mut HashSet<String>) -> bool) {
+        let wallets = {
+            let mut wallets = self.wallets.lock().unwrap();
+            if !change(&mut wallets) {
+                return;
+            }
+            wallets.iter().cloned().collect()
+        };
+        let _ = self.sender.try_send(WalletUpdate::Subscribed { wallets });
+    }
+}
+
+#[async_trait]
+impl WalletTracker for FixtureTracker {
+    async fn track(&self, wallet: String) {
+        self.update(|wallets| wallets.insert(wallet));
+    }
+
+    async fn untrack(&self, wallet: &str) {
+        self.update(|wallets| wallets.remove(wallet));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const WALLET: &str = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM";
+
+    fn transaction(signature: &str, commitment: &str) -> serde_json::Value {
+        json!({
+            "signature": signature,
+            "slot": 250_000_000u64,
+            "block_time": "2024-01-01T00:00:00Z",
+            "success": true,
+            "from_address": WALLET,
+            "to_address": "",
+            "amount": 0.5,
+            "token_address": null,
+            "fee": 5000,
+            "commitment": commitment
+        })
+    }
+
+    fn chain() -> FixtureChain {
+        let fixture = serde_json::from_value(json!({
+            "wallets": {
+                WALLET: {
+                    "tokens": [{
+                        "token_address": "So11111111111111111111111111111111111111112",
+                        "amount": 1.5,
+                        "raw_amount": 1_500_000_000u64,
+                        "decimals": 9,
+                        "value_usd": 0.0
+                    }],
+                    "transactions": [transaction("newer", "confirmed"), transaction("older", "finalized")]
+                }
+            },
+            "liquid_staking_rates": { "J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn": 1.15 }
+        }))
+        .unwrap();
+        FixtureChain::new(fixture)
+    }
+
+    #[tokio::test]
+    async fn test_fixture_chain_serves_wallet_data() {
+        let chain = chain();
+
+        let tokens = chain.get_wallet_tokens(WALLET, Commitment::Confirmed).await.unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].raw_amount, 1_500_000_000);
+        assert!(chain
+            .get_wallet_tokens("unknown", Commitment::Confirmed)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let confirmed = chain.get_transactions(WALLET, Commitment::Confirmed).await.unwrap();
+        assert_eq!(confirmed.len(), 2);
+        let finalized = chain.get_transactions(WALLET, Commitment::Finalized).await.unwrap();
+        assert_eq!(
+            finalized.iter().map(|t| t.signature.as_str()).collect::<Vec<_>>(),
+            vec!["older"]
+        );
+        assert!(chain
+            .get_transaction("newer", WALLET, Commitment::Finalized)
+            .await
+            .is_err());
+
+        let rates = chain
+            .get_liquid_staking_rates(&[
+                "J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn".to_string(),
+                "other".to_string(),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rates.len(), 1);
+        assert!(chain.get_token_info("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fixture_subscription_only_delivers_watched_wallets() {
+        let chain = chain();
+        let subscription = chain.subscribe(vec![WALLET.to_string()]).await.unwrap();
+        let mut updates = subscription.updates;
+        assert_eq!(
+            updates.recv().await.unwrap(),
+            WalletUpdate::Subscribed {
+                wallets: vec![WALLET.to_string()]
+            }
+        );
+
+        let balance = |wallet: &str| WalletUpdate::NativeBalance {
+            wallet: wallet.to_string(),
+            lamports: 1,
+            slot: 1,
+        };
+        chain.emit(balance("someone else"));
+        chain.emit(balance(WALLET));
+
+        assert_eq!(updates.recv().await.unwrap(), balance(WALLET));
+        assert!(updates.try_recv().is_err());
+
+        // Tracking a wallet resubscribes and starts delivering its updates
+        subscription.tracker.track("someone else".to_string()).await;
+        assert!(matches!(
+            updates.recv().await.unwrap(),
+            WalletUpdate::Subscribed { wallets } if wallets.len() == 2
+        ));
+        chain.emit(balance("someone else"));
+        assert_eq!(updates.recv().await.unwrap(), balance("someone else"));
+    }
+}
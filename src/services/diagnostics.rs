@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::Transaction;
+
+pub const FAILURE_HISTORY_LIMIT: i64 = 10_000;
+// Causes listed in a failure report, most frequent first
+const TOP_FAILURE_CAUSES: usize = 10;
+
+// One kind of failure: a program error, or a transaction-level error when
+// `program_id` is unset
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailureCause {
+    pub program_id: Option<String>,
+    pub code: Option<u32>,
+    pub name: Option<String>,
+    pub message: Option<String>,
+    pub count: usize,
+    // Most recent transaction that failed this way, to look up in detail
+    pub latest_signature: String,
+}
+
+// Failures among transactions the wallet signed and paid for
+#[derive(Debug, Serialize)]
+pub struct FailureReport {
+    pub address: String,
+    pub since: DateTime<Utc>,
+    pub transactions: usize,
+    pub failed: usize,
+    pub failure_rate: f64,
+    pub causes: Vec<FailureCause>,
+}
+
+pub fn summarize_failures(address: &str, since: DateTime<Utc>, transactions: &[Transaction]) -> FailureReport {
+    let mut signed: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|transaction| transaction.block_time >= since)
+        .filter(|transaction| transaction.account_keys.first().map(String::as_str) == Some(address))
+        .collect();
+    signed.sort_by_key(|transaction| std::cmp::Reverse((transaction.slot, transaction.block_time)));
+
+    let mut causes: HashMap<(Option<String>, Option<u32>, Option<String>), FailureCause> = HashMap::new();
+    let mut failed = 0;
+    for transaction in signed.iter().filter(|transaction| !transaction.success) {
+        failed += 1;
+        // Unset for transactions stored before failures were diagnosed
+        let failure = transaction.failure.as_ref();
+        let program_id = failure.and_then(|failure| failure.program_id.clone());
+        let code = failure.and_then(|failure| failure.code);
+        let name = failure.and_then(|failure| failure.name.clone());
+
+        causes
+            .entry((program_id.clone(), code, name.clone()))
+            .or_insert_with(|| FailureCause {
+                program_id,
+                code,
+                name,
+                message: failure.and_then(|failure| failure.message.clone()),
+                count: 0,
+                latest_signature: transaction.signature.clone(),
+            })
+            .count += 1;
+    }
+
+    let mut causes: Vec<FailureCause> = causes.into_values().collect();
+    causes.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    causes.truncate(TOP_FAILURE_CAUSES);
+
+    FailureReport {
+        address: address.to_string(),
+        since,
+        transactions: signed.len(),
+        failed,
+        failure_rate: if signed.is_empty() {
+            0.0
+        } else {
+            failed as f64 / signed.len() as f64
+        },
+        causes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TransactionFailure;
+    use crate::utils::helpers::from_unix_timestamp;
+
+    const WALLET: &str = "wallet";
+
+    fn transaction(slot: u64, failure: Option<(&str, u32, &str)>) -> Transaction {
+        Transaction {
+            success: failure.is_none(),
+            failure: failure.map(|(program_id, code, name)| TransactionFailure {
+                instruction_index: Some(1),
+                program_id: Some(program_id.to_string()),
+                error: format!("InstructionError(1, Custom({}))", code),
+                code: Some(code),
+                name: Some(name.to_string()),
+                message: None,
+                logs: Vec::new(),
+            }),
+            ..Transaction::paid_by(slot, WALLET)
+        }
+    }
+
+    #[test]
+    fn test_summarize_failures() {
+        let slippage = Some(("jupiter", 6001, "SlippageToleranceExceeded"));
+        let insufficient = Some(("token", 1, "InsufficientFunds"));
+        let mut legacy = transaction(6, None);
+        legacy.success = false;
+
+        let transactions = vec![
+            transaction(1, None),
+            transaction(2, slippage),
+            transaction(3, insufficient),
+            transaction(4, slippage),
+            transaction(5, None),
+            legacy,
+        ];
+        let report = summarize_failures(WALLET, from_unix_timestamp(0), &transactions);
+
+        assert_eq!(report.transactions, 6);
+        assert_eq!(report.failed, 4);
+        assert_eq!(report.failure_rate, 4.0 / 6.0);
+        assert_eq!(report.causes.len(), 3);
+        assert_eq!(report.causes[0].name.as_deref(), Some("SlippageToleranceExceeded"));
+        assert_eq!(report.causes[0].count, 2);
+        assert_eq!(report.causes[0].latest_signature, "sig4");
+        assert!(report
+            .causes
+            .iter()
+            .any(|cause| cause.name.is_none() && cause.count == 1));
+    }
+}
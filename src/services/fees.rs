@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use solana_client::rpc_response::RpcPrioritizationFee;
+
+use crate::models::{FeeBreakdown, Transaction};
+use crate::services::blockchain::fees::priority_fee;
+use crate::services::blockchain::NATIVE_DECIMALS;
+use crate::utils::helpers::format_token_amount;
+
+pub const FEE_HISTORY_LIMIT: i64 = 10_000;
+// getRecentPrioritizationFees takes at most this many accounts
+const PRIORITY_FEE_ACCOUNTS: usize = 128;
+// A priority price this many times the network's 75th percentile is overpaid
+const OVERPAY_MULTIPLE: u64 = 10;
+// Requesting this many times the compute actually used pays priority on
+// units that were never needed
+const UNUSED_COMPUTE_MULTIPLE: u64 = 4;
+
+// Distribution of recent per-slot priority fees, in micro-lamports per
+// compute unit, over slots `first_slot..=last_slot`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NetworkFeeLevels {
+    pub median: u64,
+    pub p75: u64,
+    pub p90: u64,
+    pub first_slot: u64,
+    pub last_slot: u64,
+}
+
+impl NetworkFeeLevels {
+    pub fn from_samples(samples: Vec<RpcPrioritizationFee>) -> Self {
+        let mut fees: Vec<u64> = samples.iter().map(|sample| sample.prioritization_fee).collect();
+        fees.sort_unstable();
+        Self {
+            median: percentile(&fees, 50),
+            p75: percentile(&fees, 75),
+            p90: percentile(&fees, 90),
+            first_slot: samples.iter().map(|sample| sample.slot).min().unwrap_or_default(),
+            last_slot: samples.iter().map(|sample| sample.slot).max().unwrap_or_default(),
+        }
+    }
+
+    // The sample only says what the network charged around its own slots
+    fn covers(&self, slot: u64) -> bool {
+        self.first_slot > 0 && slot >= self.first_slot
+    }
+}
+
+// The accounts the wallet's transactions write-locked most often, which are
+// the fee markets it competes in
+pub fn priority_fee_accounts(address: &str, transactions: &[Transaction]) -> Vec<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for transaction in transactions
+        .iter()
+        .filter(|transaction| transaction.account_keys.first().map(String::as_str) == Some(address))
+    {
+        for account in &transaction.writable_accounts {
+            *counts.entry(account.as_str()).or_default() += 1;
+        }
+    }
+    let mut accounts: Vec<(&str, usize)> = counts.into_iter().collect();
+    accounts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    accounts
+        .into_iter()
+        .take(PRIORITY_FEE_ACCOUNTS)
+        .map(|(account, _)| account.to_string())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum OverpaidReason {
+    AboveNetwork { network_p75: u64 },
+    UnusedCompute { requested: u64, consumed: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OverpaidFee {
+    pub signature: String,
+    pub block_time: DateTime<Utc>,
+    pub compute_unit_price: u64,
+    pub priority_fee: u64,
+    // Lamports above what the network's 75th percentile price would have
+    // cost for the compute actually consumed
+    pub excess_lamports: u64,
+    #[serde(flatten)]
+    pub reason: OverpaidReason,
+}
+
+// Fee spend for transactions the wallet paid for. USD is at the current SOL
+// price, not the price at the time of each transaction. Only transactions in
+// the slots `network` was sampled over are compared with it.
+#[derive(Debug, Serialize)]
+pub struct FeeReport {
+    pub address: String,
+    pub since: DateTime<Utc>,
+    pub transactions: usize,
+    pub base_fees_sol: f64,
+    pub priority_fees_sol: f64,
+    pub jito_tips_sol: f64,
+    pub total_sol: f64,
+    pub total_usd: f64,
+    pub compute_units_requested: u64,
+    pub compute_units_consumed: u64,
+    // Over transactions that set a priority price
+    pub median_compute_unit_price: u64,
+    pub network: NetworkFeeLevels,
+    pub overpaid: Vec<OverpaidFee>,
+}
+
+pub fn summarize_fees(
+    address: &str,
+    since: DateTime<Utc>,
+    transactions: &[Transaction],
+    sol_price: f64,
+    network: NetworkFeeLevels,
+) -> FeeReport {
+    let paid: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|transaction| transaction.block_time >= since)
+        .filter(|transaction| transaction.account_keys.first().map(String::as_str) == Some(address))
+        .collect();
+
+    let (mut base_fees, mut priority_fees, mut jito_tips) = (0u64, 0u64, 0u64);
+    let (mut requested, mut consumed) = (0u64, 0u64);
+    let mut prices = Vec::new();
+    let mut overpaid = Vec::new();
+    let unsampled = NetworkFeeLevels::default();
+
+    for transaction in &paid {
+        let fees = &transaction.fees;
+        // Stored before fees were broken down; all of it counts as base fee
+        if *fees == FeeBreakdown::default() {
+            base_fees += transaction.fee;
+            continue;
+        }
+
+        base_fees += fees.base_fee;
+        priority_fees += fees.priority_fee;
+        jito_tips += fees.jito_tip;
+        requested += fees.compute_units_requested;
+        consumed += fees.compute_units_consumed.unwrap_or_default();
+        if fees.compute_unit_price > 0 {
+            prices.push(fees.compute_unit_price);
+        }
+
+        let contemporary = if network.covers(transaction.slot) { &network } else { &unsampled };
+        if let Some(reason) = overpaid_reason(fees, contemporary) {
+            let used = fees.compute_units_consumed.unwrap_or(fees.compute_units_requested);
+            overpaid.push(OverpaidFee {
+                signature: transaction.signature.clone(),
+                block_time: transaction.block_time,
+                compute_unit_price: fees.compute_unit_price,
+                priority_fee: fees.priority_fee,
+                excess_lamports: fees.priority_fee.saturating_sub(priority_fee(network.p75, used)),
+                reason,
+            });
+        }
+    }
+    prices.sort_unstable();
+
+    let total = base_fees + priority_fees + jito_tips;
+    let sol = |lamports| format_token_amount(lamports, NATIVE_DECIMALS);
+    FeeReport {
+        address: address.to_string(),
+        since,
+        transactions: paid.len(),
+        base_fees_sol: sol(base_fees),
+        priority_fees_sol: sol(priority_fees),
+        jito_tips_sol: sol(jito_tips),
+        total_sol: sol(total),
+        total_usd: sol(total) * sol_price,
+        compute_units_requested: requested,
+        compute_units_consumed: consumed,
+        median_compute_unit_price: percentile(&prices, 50),
+        network,
+        overpaid,
+    }
+}
+
+// A price far above the network's is flagged first; otherwise a priority fee
+// paid mostly on compute that went unused. An idle network (p75 of zero)
+// gives nothing to compare the price against.
+pub fn overpaid_reason(fees: &FeeBreakdown, network: &NetworkFeeLevels) -> Option<OverpaidReason> {
+    if fees.priority_fee == 0 {
+        return None;
+    }
+    if network.p75 > 0 && fees.compute_unit_price > network.p75.saturating_mul(OVERPAY_MULTIPLE) {
+        return Some(OverpaidReason::AboveNetwork {
+            network_p75: network.p75,
+        });
+    }
+    match fees.compute_units_consumed {
+        Some(consumed) if consumed > 0 && fees.compute_units_requested > consumed * UNUSED_COMPUTE_MULTIPLE => {
+            Some(OverpaidReason::UnusedCompute {
+                requested: fees.compute_units_requested,
+                consumed,
+            })
+        }
+        _ => None,
+    }
+}
+
+// Nearest-rank percentile of already sorted samples
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * pct).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::helpers::from_unix_timestamp;
+
+    const WALLET: &str = "wallet";
+
+    fn transaction(signature: &str, payer: &str, fees: FeeBreakdown) -> Transaction {
+        Transaction {
+            signature: signature.to_string(),
+            block_time: from_unix_timestamp(1_700_000_000),
+            fee: fees.base_fee + fees.priority_fee,
+            fees,
+            ..Transaction::paid_by(1, payer)
+        }
+    }
+
+    fn fees(compute_unit_price: u64, requested: u64, consumed: u64) -> FeeBreakdown {
+        FeeBreakdown {
+            base_fee: 5000,
+            priority_fee: priority_fee(compute_unit_price, requested),
+            compute_unit_price,
+            compute_units_requested: requested,
+            compute_units_consumed: Some(consumed),
+            jito_tip: 0,
+        }
+    }
+
+    fn network(first_slot: u64) -> NetworkFeeLevels {
+        NetworkFeeLevels {
+            median: 1000,
+            p75: 2000,
+            p90: 5000,
+            first_slot,
+            last_slot: first_slot + 150,
+        }
+    }
+
+    #[test]
+    fn test_network_fee_levels() {
+        let samples = (1..=100)
+            .rev()
+            .map(|fee| RpcPrioritizationFee {
+                slot: 1_000 + fee,
+                prioritization_fee: fee,
+            })
+            .collect();
+        assert_eq!(
+            NetworkFeeLevels::from_samples(samples),
+            NetworkFeeLevels {
+                median: 50,
+                p75: 75,
+                p90: 90,
+                first_slot: 1_001,
+                last_slot: 1_100
+            }
+        );
+        assert_eq!(NetworkFeeLevels::from_samples(Vec::new()), NetworkFeeLevels::default());
+    }
+
+    #[test]
+    fn test_priority_fee_accounts_ranks_the_wallets_writable_accounts() {
+        let write = |payer: &str, accounts: &[&str]| Transaction {
+            writable_accounts: accounts.iter().map(|account| account.to_string()).collect(),
+            ..Transaction::paid_by(1, payer)
+        };
+        let transactions = vec![
+            write(WALLET, &[WALLET, "pool"]),
+            write(WALLET, &[WALLET, "pool", "vault"]),
+            write("other", &["other", "elsewhere"]),
+        ];
+        assert_eq!(
+            priority_fee_accounts(WALLET, &transactions),
+            vec![WALLET.to_string(), "pool".to_string(), "vault".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_overpaid_reason() {
+        let network = network(1);
+
+        assert_eq!(overpaid_reason(&fees(2000, 200_000, 150_000), &network), None);
+        assert_eq!(
+            overpaid_reason(&fees(50_000, 200_000, 150_000), &network),
+            Some(OverpaidReason::AboveNetwork { network_p75: 2000 })
+        );
+        assert_eq!(
+            overpaid_reason(&fees(2000, 1_400_000, 50_000), &network),
+            Some(OverpaidReason::UnusedCompute {
+                requested: 1_400_000,
+                consumed: 50_000
+            })
+        );
+        // No priority fee, nothing overpaid
+        assert_eq!(overpaid_reason(&fees(0, 1_400_000, 50_000), &network), None);
+        assert_eq!(
+            overpaid_reason(&fees(50_000, 200_000, 150_000), &NetworkFeeLevels::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_summarize_fees_counts_only_fees_the_wallet_paid() {
+        let network = network(1);
+        let mut tipped = fees(1000, 200_000, 150_000);
+        tipped.jito_tip = 1_000_000;
+        let mut legacy = transaction("legacy", WALLET, FeeBreakdown::default());
+        legacy.fee = 5000;
+
+        let transactions = vec![
+            transaction("tipped", WALLET, tipped),
+            transaction("overpaid", WALLET, fees(100_000, 200_000, 100_000)),
+            transaction("someone_else", "other", fees(100_000, 200_000, 100_000)),
+            legacy,
+        ];
+        let report = summarize_fees(WALLET, from_unix_timestamp(0), &transactions, 100.0, network);
+
+        assert_eq!(report.transactions, 3);
+        assert_eq!(report.base_fees_sol, format_token_amount(15_000, NATIVE_DECIMALS));
+        assert_eq!(
+            report.priority_fees_sol,
+            format_token_amount(200 + 20_000, NATIVE_DECIMALS)
+        );
+        assert_eq!(report.jito_tips_sol, format_token_amount(1_000_000, NATIVE_DECIMALS));
+        assert_eq!(report.total_usd, report.total_sol * 100.0);
+        assert_eq!(report.overpaid.len(), 1);
+        assert_eq!(report.overpaid[0].signature, "overpaid");
+        // p75 of 2000 on 100k consumed units would have cost 200 lamports
+        assert_eq!(report.overpaid[0].excess_lamports, 19_800);
+
+        // Older than the network sample, so the price is not judged against it
+        let stale = summarize_fees(WALLET, from_unix_timestamp(0), &transactions, 100.0, network(2));
+        assert!(stale.overpaid.is_empty());
+
+        let since = from_unix_timestamp(1_800_000_000);
+        let later = summarize_fees(WALLET, since, &transactions, 100.0, NetworkFeeLevels::default());
+        assert_eq!(later.transactions, 0);
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::db::store::Store;
+use crate::models::{EventKind, Transaction};
+use crate::services::blockchain::{NATIVE_DECIMALS, NATIVE_MINT};
+use crate::utils::helpers::format_token_amount;
+
+pub const MAX_HOPS: usize = 3;
+// Transactions read per hop when expanding the graph from Mongo
+const HOP_TRANSACTION_LIMIT: i64 = 5000;
+// Addresses expanded per hop; the most active counterparties go first
+const MAX_FRONTIER: usize = 250;
+
+// One movement of funds between two wallets. Token transfers are attributed
+// to the signing authority and the destination account's owner, falling
+// back to the token account itself when the owner was not recorded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    pub asset: String,
+    pub amount: f64,
+    pub signature: String,
+    pub block_time: DateTime<Utc>,
+}
+
+pub fn transfers(transaction: &Transaction) -> Vec<Transfer> {
+    if !transaction.success {
+        return Vec::new();
+    }
+
+    transaction
+        .events
+        .iter()
+        .filter_map(|event| match &event.kind {
+            EventKind::SolTransfer { from, to, lamports } => Some((
+                from.clone(),
+                to.clone(),
+                NATIVE_MINT.to_string(),
+                format_token_amount(*lamports, NATIVE_DECIMALS),
+            )),
+            EventKind::TokenTransfer {
+                destination,
+                authority,
+                mint,
+                amount,
+                decimals,
+                destination_owner,
+                ..
+            } => Some((
+                authority.clone(),
+                destination_owner.clone().unwrap_or_else(|| destination.clone()),
+                mint.clone().unwrap_or_else(|| "unknown".to_string()),
+                format_token_amount(*amount, decimals.unwrap_or_default()),
+            )),
+            _ => None,
+        })
+        .filter(|(from, to, _, _)| from != to)
+        .map(|(from, to, asset, amount)| Transfer {
+            from,
+            to,
+            asset,
+            amount,
+            signature: transaction.signature.clone(),
+            block_time: transaction.block_time,
+        })
+        .collect()
+}
+
+// All transfers from one address to another, with volume per asset
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub transfers: usize,
+    pub volume: BTreeMap<String, f64>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CounterpartyGraph {
+    pub nodes: BTreeSet<String>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl CounterpartyGraph {
+    // Transactions are counted once even if they appear more than once
+    pub fn from_transactions<'a>(transactions: impl IntoIterator<Item = &'a Transaction>) -> Self {
+        let mut seen = HashSet::new();
+        let mut edges: BTreeMap<(String, String), GraphEdge> = BTreeMap::new();
+
+        for transaction in transactions {
+            if !seen.insert(transaction.signature.as_str()) {
+                continue;
+            }
+            for transfer in transfers(transaction) {
+                let edge = edges
+                    .entry((transfer.from.clone(), transfer.to.clone()))
+                    .or_insert_with(|| GraphEdge {
+                        from: transfer.from.clone(),
+                        to: transfer.to.clone(),
+                        transfers: 0,
+                        volume: BTreeMap::new(),
+                        first_seen: transfer.block_time,
+                        last_seen: transfer.block_time,
+                    });
+                edge.transfers += 1;
+                *edge.volume.entry(transfer.asset).or_default() += transfer.amount;
+                edge.first_seen = edge.first_seen.min(transfer.block_time);
+                edge.last_seen = edge.last_seen.max(transfer.block_time);
+            }
+        }
+
+        Self::from_edges(edges.into_values().collect())
+    }
+
+    pub fn from_edges(edges: Vec<GraphEdge>) -> Self {
+        Self {
+            nodes: edges
+                .iter()
+                .flat_map(|edge| [edge.from.clone(), edge.to.clone()])
+                .collect(),
+            edges,
+        }
+    }
+
+    // Counterparties in either direction, weighted by transfer count
+    fn counterparties<'a>(&'a self, address: &'a str) -> impl Iterator<Item = (&'a str, usize)> + 'a {
+        self.edges.iter().filter_map(move |edge| {
+            if edge.from == address {
+                Some((edge.to.as_str(), edge.transfers))
+            } else if edge.to == address {
+                Some((edge.from.as_str(), edge.transfers))
+            } else {
+                None
+            }
+        })
+    }
+
+    // Everything within `hops` transfers of `center`, in either direction,
+    // with the edges between those addresses
+    pub fn neighbourhood(&self, center: &str, hops: usize) -> CounterpartyGraph {
+        let mut reached: HashSet<&str> = HashSet::from([center]);
+        let mut frontier = vec![center];
+        for _ in 0..hops {
+            let mut next = Vec::new();
+            for address in frontier {
+                for (counterparty, _) in self.counterparties(address) {
+                    if reached.insert(counterparty) {
+                        next.push(counterparty);
+                    }
+                }
+            }
+            frontier = next;
+        }
+
+        Self::from_edges(
+            self.edges
+                .iter()
+                .filter(|edge| reached.contains(edge.from.as_str()) && reached.contains(edge.to.as_str()))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    // Shortest chain of transfers carrying funds from `from` to `to`,
+    // following edge direction
+    pub fn find_path(&self, from: &str, to: &str, max_hops: usize) -> Option<Vec<GraphEdge>> {
+        let mut outgoing: HashMap<&str, Vec<&GraphEdge>> = HashMap::new();
+        for edge in &self.edges {
+            outgoing.entry(edge.from.as_str()).or_default().push(edge);
+        }
+
+        let mut previous: HashMap<&str, &GraphEdge> = HashMap::new();
+        let mut queue = VecDeque::from([(from, 0)]);
+        while let Some((address, depth)) = queue.pop_front() {
+            if address == to {
+                let mut path = Vec::new();
+                let mut current = to;
+                while current != from {
+                    let edge = previous[current];
+                    path.push(edge.clone());
+                    current = edge.from.as_str();
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if depth == max_hops {
+                continue;
+            }
+            for edge in outgoing.get(address).into_iter().flatten() {
+                let next = edge.to.as_str();
+                if next != from && !previous.contains_key(next) {
+                    previous.insert(next, edge);
+                    queue.push_back((next, depth + 1));
+                }
+            }
+        }
+        None
+    }
+
+    // GraphML with one edge attribute per asset's volume, for graph tools
+    pub fn to_graphml(&self) -> String {
+        let assets: BTreeSet<&str> = self
+            .edges
+            .iter()
+            .flat_map(|edge| edge.volume.keys().map(String::as_str))
+            .collect();
+        let asset_keys: HashMap<&str, String> = assets
+            .iter()
+            .enumerate()
+            .map(|(index, asset)| (*asset, format!("v{}", index)))
+            .collect();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        xml.push_str("  <key id=\"transfers\" for=\"edge\" attr.name=\"transfers\" attr.type=\"int\"/>\n");
+        xml.push_str("  <key id=\"first_seen\" for=\"edge\" attr.name=\"first_seen\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"last_seen\" for=\"edge\" attr.name=\"last_seen\" attr.type=\"string\"/>\n");
+        for asset in &assets {
+            let _ = writeln!(
+                xml,
+                "  <key id=\"{}\" for=\"edge\" attr.name=\"volume_{}\" attr.type=\"double\"/>",
+                asset_keys[asset],
+                escape_xml(asset)
+            );
+        }
+
+        xml.push_str("  <graph id=\"counterparties\" edgedefault=\"directed\">\n");
+        for node in &self.nodes {
+            let _ = writeln!(xml, "    <node id=\"{}\"/>", escape_xml(node));
+        }
+        for (index, edge) in self.edges.iter().enumerate() {
+            let _ = writeln!(
+                xml,
+                "    <edge id=\"e{}\" source=\"{}\" target=\"{}\">",
+                index,
+                escape_xml(&edge.from),
+                escape_xml(&edge.to)
+            );
+            let _ = writeln!(xml, "      <data key=\"transfers\">{}</data>", edge.transfers);
+            let _ = writeln!(
+                xml,
+                "      <data key=\"first_seen\">{}</data>",
+                edge.first_seen.to_rfc3339()
+            );
+            let _ = writeln!(
+                xml,
+                "      <data key=\"last_seen\">{}</data>",
+                edge.last_seen.to_rfc3339()
+            );
+            for (asset, volume) in &edge.volume {
+                let _ = writeln!(
+                    xml,
+                    "      <data key=\"{}\">{}</data>",
+                    asset_keys[asset.as_str()],
+                    volume
+                );
+            }
+            xml.push_str("    </edge>\n");
+        }
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Builds counterparty graphs from the `transactions` collection. Only
+// stored transactions are seen, so addresses outside the tracked wallets'
+// history show up as leaves.
+pub struct CounterpartyGraphService {
+    db: Arc<dyn Store>,
+}
+
+impl CounterpartyGraphService {
+    pub fn new(db: Arc<dyn Store>) -> Self {
+        Self { db }
+    }
+
+    pub async fn neighbourhood(&self, address: &str, hops: usize) -> Result<CounterpartyGraph> {
+        let hops = hops.clamp(1, MAX_HOPS);
+        Ok(self.expand(address, hops).await?.neighbourhood(address, hops))
+    }
+
+    pub async fn find_path(&self, from: &str, to: &str, max_hops: usize) -> Result<Option<Vec<GraphEdge>>> {
+        let max_hops = max_hops.clamp(1, MAX_HOPS);
+        Ok(self.expand(from, max_hops).await?.find_path(from, to, max_hops))
+    }
+
+    // Reads transactions hop by hop outwards from `seed`
+    async fn expand(&self, seed: &str, hops: usize) -> Result<CounterpartyGraph> {
+        let mut transactions: Vec<Transaction> = Vec::new();
+        let mut expanded: HashSet<String> = HashSet::new();
+        let mut frontier = vec![seed.to_string()];
+        let mut graph = CounterpartyGraph::default();
+
+        for _ in 0..hops {
+            expanded.extend(frontier.iter().cloned());
+            transactions.extend(
+                self.db
+                    .get_transactions_involving(&frontier, HOP_TRANSACTION_LIMIT)
+                    .await?,
+            );
+            graph = CounterpartyGraph::from_transactions(&transactions);
+
+            let mut weights: HashMap<&str, usize> = HashMap::new();
+            for address in &frontier {
+                for (counterparty, transfers) in graph.counterparties(address) {
+                    if !expanded.contains(counterparty) {
+                        *weights.entry(counterparty).or_default() += transfers;
+                    }
+                }
+            }
+            let mut next: Vec<(&str, usize)> = weights.into_iter().collect();
+            next.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            frontier = next
+                .into_iter()
+                .take(MAX_FRONTIER)
+                .map(|(address, _)| address.to_string())
+                .collect();
+            if frontier.is_empty() {
+                break;
+            }
+        }
+
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TransactionEvent;
+    use crate::utils::helpers::from_unix_timestamp;
+
+    fn sol_transfer(signature: &str, from: &str, to: &str, lamports: u64) -> Transaction {
+        Transaction {
+            signature: signature.to_string(),
+            block_time: from_unix_timestamp(1_700_000_000),
+            to_address: to.to_string(),
+            amount: format_token_amount(lamports, NATIVE_DECIMALS),
+            account_keys: vec![from.to_string(), to.to_string()],
+            events: vec![TransactionEvent {
+                program_id: "11111111111111111111111111111111".to_string(),
+                instruction_index: 0,
+                inner_index: None,
+                kind: EventKind::SolTransfer {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    lamports,
+                },
+            }],
+            ..Transaction::paid_by(1, from)
+        }
+    }
+
+    fn graph() -> CounterpartyGraph {
+        let transactions = vec![
+            sol_transfer("1", "alice", "bob", 1_000_000_000),
+            sol_transfer("2", "alice", "bob", 500_000_000),
+            sol_transfer("3", "bob", "carol", 1_000_000_000),
+            sol_transfer("4", "carol", "dave", 1_000_000_000),
+            sol_transfer("5", "erin", "alice", 1_000_000_000),
+            // Duplicate of the first, as returned by two hops
+            sol_transfer("1", "alice", "bob", 1_000_000_000),
+        ];
+        CounterpartyGraph::from_transactions(&transactions)
+    }
+
+    #[test]
+    fn test_edges_aggregate_transfers() {
+        let graph = graph();
+        assert_eq!(graph.nodes.len(), 5);
+        assert_eq!(graph.edges.len(), 4);
+
+        let alice_bob = graph.edges.iter().find(|edge| edge.from == "alice").unwrap();
+        assert_eq!(alice_bob.transfers, 2);
+        assert_eq!(alice_bob.volume[&NATIVE_MINT.to_string()], 1.5);
+    }
+
+    #[test]
+    fn test_neighbourhood() {
+        let graph = graph();
+        let one_hop = graph.neighbourhood("bob", 1);
+        assert_eq!(
+            one_hop.nodes.iter().map(String::as_str).collect::<Vec<_>>(),
+            vec!["alice", "bob", "carol"]
+        );
+        assert_eq!(one_hop.edges.len(), 2);
+        assert_eq!(graph.neighbourhood("bob", 2).nodes.len(), 5);
+    }
+
+    #[test]
+    fn test_find_path_follows_funds() {
+        let graph = graph();
+        let path = graph.find_path("erin", "dave", 4).unwrap();
+        assert_eq!(
+            path.iter().map(|edge| edge.to.as_str()).collect::<Vec<_>>(),
+            vec!["alice", "bob", "carol", "dave"]
+        );
+        assert!(graph.find_path("erin", "dave", 3).is_none());
+        // Funds never moved from dave back to erin
+        assert!(graph.find_path("dave", "erin", 4).is_none());
+    }
+
+    #[test]
+    fn test_graphml_export() {
+        let xml = graph().neighbourhood("alice", 1).to_graphml();
+        assert!(xml.contains("edgedefault=\"directed\""));
+        assert!(xml.contains("<node id=\"erin\"/>"));
+        assert!(xml.contains("source=\"alice\" target=\"bob\""));
+        assert!(xml.contains("<data key=\"v0\">1.5</data>"));
+        assert_eq!(escape_xml("a<b&\"c\""), "a&lt;b&amp;&quot;c&quot;");
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+
+use crate::db::store::Store;
+use crate::models::{AccountBalance, BalanceCheckpoint, Commitment, TokenBalance, Transaction};
+use crate::services::blockchain::{aggregate_balances, NativeBalance, TokenAccountBalance};
+
+// Transactions replayed past the newest checkpoint before another is taken
+const CHECKPOINT_INTERVAL: u64 = 500;
+
+// A point in a wallet's history: the end of a slot, or a moment in time.
+// Parsed from a slot number, an RFC 3339 timestamp, or a date, which means
+// the end of that day in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum AsOf {
+    Slot(u64),
+    Time(DateTime<Utc>),
+}
+
+impl AsOf {
+    pub fn includes(&self, slot: u64, block_time: DateTime<Utc>) -> bool {
+        match self {
+            AsOf::Slot(as_of) => slot <= *as_of,
+            AsOf::Time(as_of) => block_time <= *as_of,
+        }
+    }
+
+    fn until_slot(&self) -> Option<u64> {
+        match self {
+            AsOf::Slot(slot) => Some(*slot),
+            AsOf::Time(_) => None,
+        }
+    }
+
+    fn until_time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            AsOf::Slot(_) => None,
+            AsOf::Time(time) => Some(*time),
+        }
+    }
+}
+
+impl std::str::FromStr for AsOf {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        if let Ok(slot) = value.parse::<u64>() {
+            return Ok(AsOf::Slot(slot));
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+            return Ok(AsOf::Time(time.with_timezone(&Utc)));
+        }
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(23, 59, 59))
+            .map(|end_of_day| AsOf::Time(end_of_day.and_utc()))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unsupported as_of {:?}; expected a slot, an RFC 3339 timestamp or a date",
+                    value
+                )
+            })
+    }
+}
+
+impl TryFrom<String> for AsOf {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+// A wallet's account balances, rebuilt by replaying the post balances its
+// transactions recorded. Accounts no stored transaction touched are
+// unknown, so history is only as complete as the backfill behind it.
+#[derive(Debug, Default)]
+pub struct BalanceLedger {
+    address: String,
+    accounts: BTreeMap<String, AccountBalance>,
+    // Newest transaction replayed
+    last: Option<(u64, DateTime<Utc>)>,
+}
+
+impl BalanceLedger {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn from_checkpoint(checkpoint: BalanceCheckpoint) -> Self {
+        Self {
+            address: checkpoint.address,
+            accounts: checkpoint
+                .accounts
+                .into_iter()
+                .map(|account| (account.account.clone(), account))
+                .collect(),
+            last: Some((checkpoint.slot, checkpoint.block_time)),
+        }
+    }
+
+    pub fn apply(&mut self, transaction: &Transaction) {
+        for change in transaction
+            .balance_changes
+            .iter()
+            .filter(|change| change.owner == self.address)
+        {
+            self.accounts.insert(
+                change.account.clone(),
+                AccountBalance {
+                    account: change.account.clone(),
+                    mint: change.mint.clone(),
+                    decimals: change.decimals,
+                    amount: change.post,
+                    slot: transaction.slot,
+                },
+            );
+        }
+        self.last = Some((transaction.slot, transaction.block_time));
+    }
+
+    pub fn balances(&self, commitment: Commitment) -> Vec<TokenBalance> {
+        let mut native = NativeBalance::default();
+        let mut token_accounts = Vec::new();
+        for account in self.accounts.values() {
+            match &account.mint {
+                Some(mint) => token_accounts.push(TokenAccountBalance {
+                    address: account.account.clone(),
+                    mint: mint.clone(),
+                    raw_amount: account.amount,
+                    decimals: account.decimals,
+                    slot: account.slot,
+                }),
+                None => {
+                    native = NativeBalance {
+                        lamports: account.amount,
+                        slot: account.slot,
+                    }
+                }
+            }
+        }
+        // NFTs cannot be told apart without their metadata, so replayed
+        // balances keep every holding
+        aggregate_balances(&token_accounts, native, commitment, &HashSet::new())
+    }
+
+    // `transactions` counts every transaction replayed into the ledger,
+    // including those behind the checkpoint it started from
+    pub fn checkpoint(&self, transactions: u64) -> Option<BalanceCheckpoint> {
+        let (slot, block_time) = self.last?;
+        Some(BalanceCheckpoint::new(
+            self.address.clone(),
+            slot,
+            block_time,
+            transactions,
+            self.accounts.values().cloned().collect(),
+        ))
+    }
+}
+
+pub struct BalanceHistoryService {
+    db: Arc<dyn Store>,
+}
+
+impl BalanceHistoryService {
+    pub fn new(db: Arc<dyn Store>) -> Self {
+        Self { db }
+    }
+
+    // Balances held at `as_of`, replayed from the newest checkpoint before it.
+    // A finalized read replays finalized transactions only.
+    pub async fn balances_as_of(
+        &self,
+        address: &str,
+        as_of: AsOf,
+        commitment: Commitment,
+    ) -> Result<Vec<TokenBalance>> {
+        let mut ledger = BalanceLedger::new(address);
+        let mut replayed = 0;
+        for checkpoint in self.db.get_balance_checkpoints(address).await?.into_iter().rev() {
+            if !as_of.includes(checkpoint.slot, checkpoint.block_time) {
+                continue;
+            }
+            let transactions = self
+                .db
+                .count_balance_transactions(address, checkpoint.slot, commitment)
+                .await?;
+            if transactions == checkpoint.transactions {
+                replayed = checkpoint.transactions;
+                ledger = BalanceLedger::from_checkpoint(checkpoint);
+                break;
+            }
+            // History at or before it changed since it was taken
+            self.db.delete_balance_checkpoint(&checkpoint.id).await?;
+        }
+
+        let after_slot = ledger.last.map(|(slot, _)| slot);
+        let transactions = self
+            .db
+            .get_balance_transactions(address, after_slot, as_of.until_slot(), as_of.until_time(), commitment)
+            .await?;
+        let mut applied = 0;
+        let mut finalized = true;
+        for transaction in &transactions {
+            ledger.apply(transaction);
+            applied += 1;
+            finalized &= transaction.commitment == Commitment::Finalized;
+        }
+
+        // Checkpoints are shared by both commitments, so one that took in a
+        // transaction that may still be dropped is never saved
+        if applied >= CHECKPOINT_INTERVAL && finalized {
+            if let Some(checkpoint) = ledger.checkpoint(replayed + applied) {
+                self.db.save_balance_checkpoint(&checkpoint).await?;
+            }
+        }
+        Ok(ledger.balances(commitment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::store::MemoryStore;
+    use crate::models::BalanceChange;
+    use crate::services::blockchain::NATIVE_MINT;
+    use crate::utils::helpers::from_unix_timestamp;
+
+    const WALLET: &str = "wallet";
+
+    fn change(account: &str, mint: Option<&str>, post: u64) -> BalanceChange {
+        BalanceChange {
+            account: account.to_string(),
+            owner: WALLET.to_string(),
+            mint: mint.map(str::to_string),
+            decimals: if mint.is_some() { 6 } else { 9 },
+            pre: 0,
+            post,
+        }
+    }
+
+    fn transaction(slot: u64, balance_changes: Vec<BalanceChange>) -> Transaction {
+        Transaction {
+            balance_changes,
+            ..Transaction::paid_by(slot, WALLET)
+        }
+    }
+
+    #[test]
+    fn test_parse_as_of() {
+        assert_eq!("250000000".parse::<AsOf>().unwrap(), AsOf::Slot(250_000_000));
+        assert_eq!(
+            "2023-11-14T22:13:20Z".parse::<AsOf>().unwrap(),
+            AsOf::Time(from_unix_timestamp(1_700_000_000))
+        );
+        assert_eq!(
+            "2023-11-14".parse::<AsOf>().unwrap(),
+            AsOf::Time(from_unix_timestamp(1_700_006_399))
+        );
+        assert!("yesterday".parse::<AsOf>().is_err());
+    }
+
+    #[test]
+    fn test_ledger_replays_post_balances() {
+        let transactions = vec![
+            transaction(
+                1,
+                vec![
+                    change(WALLET, None, 2_000_000_000),
+                    change("usdc_a", Some("usdc"), 5_000_000),
+                ],
+            ),
+            transaction(2, vec![change("usdc_b", Some("usdc"), 1_000_000)]),
+            // Closes the first USDC account
+            transaction(
+                3,
+                vec![change(WALLET, None, 1_500_000_000), change("usdc_a", Some("usdc"), 0)],
+            ),
+        ];
+
+        let mut ledger = BalanceLedger::new(WALLET);
+        ledger.apply(&transactions[0]);
+        ledger.apply(&transactions[1]);
+        let checkpoint = ledger.checkpoint(2).unwrap();
+        assert_eq!((checkpoint.slot, checkpoint.transactions), (2, 2));
+
+        let at_slot_two = ledger.balances(Commitment::default());
+        assert_eq!(at_slot_two.len(), 2);
+        let usdc = at_slot_two
+            .iter()
+            .find(|balance| balance.token_address == "usdc")
+            .unwrap();
+        assert_eq!((usdc.raw_amount, usdc.slot), (6_000_000, 2));
+
+        // Resuming from the checkpoint gives the same result as a full replay
+        let mut resumed = BalanceLedger::from_checkpoint(checkpoint);
+        resumed.apply(&transactions[2]);
+        ledger.apply(&transactions[2]);
+        let latest = resumed.balances(Commitment::default());
+        let amounts = |balances: &[TokenBalance]| -> Vec<(String, u64)> {
+            balances
+                .iter()
+                .map(|balance| (balance.token_address.clone(), balance.raw_amount))
+                .collect()
+        };
+        assert_eq!(amounts(&latest), amounts(&ledger.balances(Commitment::default())));
+
+        let sol = latest
+            .iter()
+            .find(|balance| balance.token_address == NATIVE_MINT.to_string())
+            .unwrap();
+        assert_eq!(sol.raw_amount, 1_500_000_000);
+        let usdc = latest.iter().find(|balance| balance.token_address == "usdc").unwrap();
+        assert_eq!(usdc.raw_amount, 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_balances_as_of_time() {
+        let store = Arc::new(MemoryStore::default());
+        store.transactions.lock().unwrap().extend([
+            transaction(1, vec![change(WALLET, None, 1_000_000_000)]),
+            transaction(5, vec![change(WALLET, None, 3_000_000_000)]),
+            // Slot 3's block was produced late, after slot 5's
+            Transaction {
+                block_time: from_unix_timestamp(1_700_000_010),
+                ..transaction(3, vec![change("usdc_a", Some("usdc"), 5_000_000)])
+            },
+        ]);
+        let service = BalanceHistoryService::new(store);
+
+        let as_of = AsOf::Time(from_unix_timestamp(1_700_000_005));
+        let balances = service.balances_as_of(WALLET, as_of, Commitment::Confirmed).await.unwrap();
+        assert_eq!(balances.len(), 1);
+        assert_eq!(balances[0].raw_amount, 3_000_000_000);
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use futures::{stream, StreamExt, TryStreamExt};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{info, warn};
+
+use crate::db::mongodb::MongoDB;
+use crate::models::IndexerCursor;
+use crate::services::blockchain::SolanaClient;
+
+// Slots walked per pass, and blocks fetched at once within a pass
+const BLOCK_BATCH: u64 = 100;
+const BLOCK_CONCURRENCY: usize = 8;
+// Wait before polling again once caught up with the tip, or after a failure
+const IDLE_POLL: Duration = Duration::from_secs(2);
+const CURSOR_ID: &str = "block_indexer";
+
+// Walks the chain block by block and stores every transaction that touches a
+// tracked wallet. The last indexed slot is persisted after each batch, so
+// after downtime the indexer catches up from where it stopped. A fresh
+// cursor starts at the current tip; older history is the backfill's job.
+pub struct BlockIndexer {
+    client: Arc<SolanaClient>,
+    db: Arc<MongoDB>,
+}
+
+impl BlockIndexer {
+    pub fn new(client: Arc<SolanaClient>, db: Arc<MongoDB>) -> Self {
+        Self { client, db }
+    }
+
+    pub async fn run(&self) {
+        loop {
+            match self.index_batch().await {
+                Ok(Some(_)) => {}
+                Ok(None) => tokio::time::sleep(IDLE_POLL).await,
+                Err(e) => {
+                    warn!("Block indexing failed: {}", e);
+                    tokio::time::sleep(IDLE_POLL).await;
+                }
+            }
+        }
+    }
+
+    // Indexes the next window of slots after the cursor. Returns the number
+    // of transactions stored, or None if there was nothing new to walk.
+    pub async fn index_batch(&self) -> Result<Option<usize>> {
+        let commitment = self.client.commitment();
+        let tip = self.client.get_slot(commitment).await?;
+        let Some(mut cursor) = self.db.get_indexer_cursor(CURSOR_ID).await? else {
+            info!("Starting block indexer at slot {}", tip);
+            self.db
+                .save_indexer_cursor(&IndexerCursor::new(CURSOR_ID.to_string(), tip))
+                .await?;
+            return Ok(None);
+        };
+        let Some((start, end)) = next_window(cursor.last_slot, tip, BLOCK_BATCH) else {
+            return Ok(None);
+        };
+
+        let tracked: HashSet<Pubkey> = self
+            .db
+            .get_wallet_addresses()
+            .await?
+            .iter()
+            .filter_map(|address| Pubkey::from_str(address).ok())
+            .collect();
+
+        let mut stored = 0;
+        // Nothing to match against; just move the cursor along
+        if !tracked.is_empty() {
+            let slots = self.client.get_blocks(start, end, commitment).await?;
+            let blocks: Vec<_> = stream::iter(slots)
+                .map(|slot| self.client.get_block_transactions(slot, &tracked, commitment))
+                .buffered(BLOCK_CONCURRENCY)
+                .try_collect()
+                .await?;
+            for transaction in blocks.into_iter().flatten() {
+                self.db.save_transaction(&transaction).await?;
+                stored += 1;
+            }
+        }
+
+        cursor.last_slot = end;
+        cursor.updated_at = Utc::now();
+        self.db.save_indexer_cursor(&cursor).await?;
+        if stored > 0 {
+            info!("Indexed slots {}..={}: {} transactions stored", start, end, stored);
+        }
+
+        Ok(Some(stored))
+    }
+}
+
+// The inclusive slot range to walk after `last_slot`, at most `batch` slots
+// long and never past `tip`
+pub fn next_window(last_slot: u64, tip: u64, batch: u64) -> Option<(u64, u64)> {
+    if last_slot >= tip || batch == 0 {
+        return None;
+    }
+    let start = last_slot + 1;
+    Some((start, tip.min(start + batch - 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_window() {
+        assert_eq!(next_window(100, 100, 10), None);
+        assert_eq!(next_window(100, 90, 10), None);
+        assert_eq!(next_window(100, 105, 10), Some((101, 105)));
+        assert_eq!(next_window(100, 500, 10), Some((101, 110)));
+        assert_eq!(next_window(100, 101, 10), Some((101, 101)));
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db::store::Store;
+use crate::models::{Commitment, EventKind, Transaction, Wallet};
+use crate::services::blockchain::swaps::outermost_swaps;
+use crate::services::history::AsOf;
+use crate::utils::helpers::format_token_amount;
+
+// Swap legs in these mints are valued at $1; they anchor every USD cost
+const USD_STABLECOINS: [&str; 2] = [
+    "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", // USDC
+    "Es9vMFrzaCERmJfrF4H2FYD4KCoNkxZNc6ds3tPUwhyr", // USDT
+];
+
+pub struct PortfolioService {
+    db: Arc<dyn Store>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PortfolioRecommendation {
+    pub suggested_allocations: Vec<Allocation>,
+    pub expected_return: f64,
+    pub risk_reduction: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Allocation {
+    pub token_address: String,
+    pub weight: f64,
+}
+
+// `total_value` and `risk_level` are the stored wallet's figures; no value
+// history is kept yet, so `daily_change` stays zero
+#[derive(Debug, Serialize)]
+pub struct PortfolioMetrics {
+    pub total_value: f64,
+    pub daily_change: f64,
+    pub risk_level: f64,
+    pub cost_basis: Vec<CostBasis>,
+}
+
+// Average-cost position built from the wallet's swap history. Holdings that
+// predate the stored history have no known cost, so `amount` can be lower
+// than the wallet's actual balance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CostBasis {
+    pub token_address: String,
+    pub amount: f64,
+    pub cost_usd: f64,
+    pub average_cost_usd: f64,
+    pub realized_pnl_usd: f64,
+}
+
+impl CostBasis {
+    fn new(token_address: String) -> Self {
+        Self {
+            token_address,
+            amount: 0.0,
+            cost_usd: 0.0,
+            average_cost_usd: 0.0,
+            realized_pnl_usd: 0.0,
+        }
+    }
+}
+
+impl PortfolioService {
+    pub fn new(db: Arc<dyn Store>) -> Self {
+        Self { db }
+    }
+
+    pub async fn optimize_portfolio(&self, _wallet: &Wallet) -> Result<PortfolioRecommendation> {
+        // Implement portfolio optimization logic
+        Ok(PortfolioRecommendation {
+            suggested_allocations: Vec::new(),
+            expected_return: 0.0,
+            risk_reduction: 0.0,
+        })
+    }
+
+    // With `Commitment::Finalized`, transactions that could still be rolled
+    // back are left out of the cost basis; with `as_of`, so are transactions
+    // after it
+    pub async fn calculate_metrics(
+        &self,
+        wallet: &Wallet,
+        commitment: Commitment,
+        as_of: Option<AsOf>,
+    ) -> Result<PortfolioMetrics> {
+        let mut transactions = self.db.get_all_wallet_transactions(&wallet.address, commitment).await?;
+        if let Some(as_of) = as_of {
+            transactions.retain(|transaction| as_of.includes(transaction.slot, transaction.block_time));
+        }
+
+        Ok(PortfolioMetrics {
+            total_value: wallet.total_value_usd,
+            daily_change: 0.0,
+            risk_level: wallet.risk_score as f64,
+            cost_basis: calculate_cost_basis(&wallet.address, transactions),
+        })
+    }
+}
+
+fn is_stablecoin(mint: &str) -> bool {
+    USD_STABLECOINS.contains(&mint)
+}
+
+// Replays the wallet's swaps oldest first. A swap against a stablecoin is
+// valued at the stablecoin amount; a swap between two other tokens carries
+// the input's average cost over to the output.
+pub fn calculate_cost_basis(wallet_address: &str, mut transactions: Vec<Transaction>) -> Vec<CostBasis> {
+    transactions.sort_by_key(|transaction| (transaction.slot, transaction.block_time));
+    let mut positions: BTreeMap<String, CostBasis> = BTreeMap::new();
+
+    for transaction in transactions.iter().filter(|transaction| transaction.success) {
+        for event in outermost_swaps(&transaction.events) {
+            let EventKind::Swap {
+                trader, input, output, ..
+            } = &event.kind
+            else {
+                continue;
+            };
+            let (Some(input_mint), Some(input_decimals), Some(output_mint), Some(output_decimals)) =
+                (&input.mint, input.decimals, &output.mint, output.decimals)
+            else {
+                continue;
+            };
+            if trader != wallet_address || input.amount == 0 || output.amount == 0 {
+                continue;
+            }
+
+            let input_amount = format_token_amount(input.amount, input_decimals);
+            let output_amount = format_token_amount(output.amount, output_decimals);
+
+            let released_cost = if is_stablecoin(input_mint) {
+                input_amount
+            } else {
+                let position = positions
+                    .entry(input_mint.clone())
+                    .or_insert_with(|| CostBasis::new(input_mint.clone()));
+                let disposed = input_amount.min(position.amount);
+                let released_cost = if position.amount > 0.0 {
+                    position.cost_usd * disposed / position.amount
+                } else {
+                    0.0
+                };
+
+                // Only the part of the sale with a known cost realizes PnL
+                if is_stablecoin(output_mint) {
+                    position.realized_pnl_usd += output_amount * disposed / input_amount - released_cost;
+                }
+                position.amount -= disposed;
+                position.cost_usd -= released_cost;
+                released_cost
+            };
+
+            if !is_stablecoin(output_mint) {
+                let position = positions
+                    .entry(output_mint.clone())
+                    .or_insert_with(|| CostBasis::new(output_mint.clone()));
+                position.amount += output_amount;
+                position.cost_usd += released_cost;
+            }
+        }
+    }
+
+    positions
+        .into_values()
+        .map(|mut position| {
+            if position.amount > 0.0 {
+                position.average_cost_usd = position.cost_usd / position.amount;
+            }
+            position
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::store::MemoryStore;
+    use crate::models::{SwapLeg, TransactionEvent};
+    use crate::utils::helpers::from_unix_timestamp;
+
+    const WALLET: &str = "wallet";
+    const USDC: &str = USD_STABLECOINS[0];
+
+    fn leg(mint: &str, decimals: u8, amount: u64) -> SwapLeg {
+        SwapLeg {
+            account: format!("{}-account", mint),
+            mint: Some(mint.to_string()),
+            decimals: Some(decimals),
+            amount,
+        }
+    }
+
+    fn swap(slot: u64, trader: &str, input: SwapLeg, output: SwapLeg) -> Transaction {
+        Transaction {
+            block_time: from_unix_timestamp(slot as i64),
+            events: vec![TransactionEvent {
+                program_id: "dex".to_string(),
+                instruction_index: 0,
+                inner_index: None,
+                kind: EventKind::Swap {
+                    dex: "raydium_amm".to_string(),
+                    trader: trader.to_string(),
+                    pool: None,
+                    route: Vec::new(),
+                    input,
+                    output,
+                    price: None,
+                },
+            }],
+            commitment: Commitment::Finalized,
+            ..Transaction::paid_by(slot, trader)
+        }
+    }
+
+    #[test]
+    fn test_cost_basis_tracks_average_cost_and_carries_over() {
+        let mut failed = swap(5, WALLET, leg(USDC, 6, 1_000_000), leg("bonk", 5, 100_000_000_000));
+        failed.success = false;
+
+        // Deliberately out of order; replay is by slot
+        let transactions = vec![
+            swap(4, WALLET, leg("sol", 9, 1_000_000_000), leg(USDC, 6, 150_000_000)),
+            swap(1, WALLET, leg(USDC, 6, 100_000_000), leg("bonk", 5, 100_000_000_000)),
+            swap(2, WALLET, leg(USDC, 6, 300_000_000), leg("bonk", 5, 100_000_000_000)),
+            swap(3, WALLET, leg("bonk", 5, 100_000_000_000), leg("sol", 9, 2_000_000_000)),
+            swap(
+                6,
+                "someone-else",
+                leg(USDC, 6, 1_000_000),
+                leg("bonk", 5, 100_000_000_000),
+            ),
+            failed,
+        ];
+
+        let positions = calculate_cost_basis(WALLET, transactions);
+        assert_eq!(
+            positions,
+            vec![
+                CostBasis {
+                    token_address: "bonk".to_string(),
+                    amount: 1_000_000.0,
+                    cost_usd: 200.0,
+                    average_cost_usd: 0.0002,
+                    realized_pnl_usd: 0.0,
+                },
+                CostBasis {
+                    token_address: "sol".to_string(),
+                    amount: 1.0,
+                    cost_usd: 100.0,
+                    average_cost_usd: 100.0,
+                    realized_pnl_usd: 50.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selling_untracked_holdings_realizes_only_known_cost() {
+        // 2 SOL sold, but history only shows 1 SOL bought at $100
+        let transactions = vec![
+            swap(1, WALLET, leg(USDC, 6, 100_000_000), leg("sol", 9, 1_000_000_000)),
+            swap(2, WALLET, leg("sol", 9, 2_000_000_000), leg(USDC, 6, 300_000_000)),
+        ];
+
+        let positions = calculate_cost_basis(WALLET, transactions);
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].amount, 0.0);
+        assert_eq!(positions[0].realized_pnl_usd, 50.0);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_cover_the_whole_history() {
+        let store = Arc::new(MemoryStore::default());
+        store.transactions.lock().unwrap().extend(
+            (1..=2_500).map(|slot| swap(slot, WALLET, leg(USDC, 6, 1_000_000), leg("bonk", 5, 100_000_000))),
+        );
+        let mut wallet = Wallet::new(WALLET.to_string());
+        wallet.total_value_usd = 1_250.0;
+        wallet.risk_score = 0.5;
+
+        let metrics = PortfolioService::new(store)
+            .calculate_metrics(&wallet, Commitment::Finalized, None)
+            .await
+            .unwrap();
+        assert_eq!(metrics.total_value, 1_250.0);
+        assert_eq!(metrics.risk_level, 0.5);
+        assert_eq!(metrics.cost_basis.len(), 1);
+        assert_eq!(metrics.cost_basis[0].amount, 2_500_000.0);
+        assert_eq!(metrics.cost_basis[0].cost_usd, 2_500.0);
+    }
+}
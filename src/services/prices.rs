@@ -0,0 +1,164 @@
+// src/services/prices.rs
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::db::store::Store;
+use crate::models::{Candle, Resolution};
+
+pub mod pyth;
+pub mod static_file;
+
+pub use pyth::PythPriceProvider;
+pub use static_file::StaticPriceProvider;
+
+// A USD price and how far it can be trusted: `confidence` is the
+// publisher's uncertainty around it, in USD
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Price {
+    pub mint: String,
+    pub price_usd: f64,
+    #[serde(default)]
+    pub confidence: f64,
+    #[serde(default)]
+    pub publish_time: DateTime<Utc>,
+}
+
+// Where token prices come from. `PythPriceProvider` reads Pyth price
+// accounts on-chain; `StaticPriceProvider` serves a fixed file for tests and
+// local runs.
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    // Current prices for the mints among `mints` the provider knows; the
+    // rest are left out
+    async fn get_prices(&self, mints: &[String]) -> Result<HashMap<String, Price>>;
+
+    // Candles for `mint` opening in `[start, end)`, oldest first. Providers
+    // without price history have none.
+    async fn get_candles(
+        &self,
+        _mint: &str,
+        _resolution: Resolution,
+        _start: DateTime<Utc>,
+        _end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        Ok(Vec::new())
+    }
+}
+
+// USD price per mint from `provider`, falling back to the last price cached
+// with the token in `cache`. Mints with neither are left out.
+pub async fn load_prices(
+    provider: &dyn PriceProvider,
+    cache: &dyn Store,
+    mints: &[String],
+) -> HashMap<String, f64> {
+    let mut prices: HashMap<String, f64> = match provider.get_prices(mints).await {
+        Ok(prices) => prices
+            .into_iter()
+            .map(|(mint, price)| (mint, price.price_usd))
+            .collect(),
+        Err(e) => {
+            warn!("Failed to load prices: {}", e);
+            HashMap::new()
+        }
+    };
+
+    let unpriced: Vec<&String> = mints.iter().filter(|mint| !prices.contains_key(*mint)).collect();
+    for mint in unpriced {
+        match cache.get_token(mint).await {
+            Ok(Some(cached)) if cached.price_usd > 0.0 => {
+                prices.insert(mint.clone(), cached.price_usd);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to read cached price for {}: {}", mint, e),
+        }
+    }
+    prices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::store::MemoryStore;
+    use crate::models::Token;
+    use anyhow::anyhow;
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const BONK: &str = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263";
+    const UNKNOWN: &str = "Unknown111111111111111111111111111111111111";
+
+    fn price(mint: &str, price_usd: f64) -> Price {
+        Price {
+            mint: mint.to_string(),
+            price_usd,
+            confidence: 0.0,
+            publish_time: DateTime::default(),
+        }
+    }
+
+    // Caches BONK at $0.00002 and knows nothing else
+    async fn bonk_cache() -> MemoryStore {
+        let cache = MemoryStore::default();
+        cache
+            .save_token(&Token {
+                address: BONK.to_string(),
+                symbol: "BONK".to_string(),
+                name: "Bonk".to_string(),
+                decimals: 5,
+                total_supply: 0,
+                price_usd: 0.00002,
+                market_cap_usd: 0.0,
+                volume_24h: 0.0,
+                price_change_24h: 0.0,
+                mint_authority: None,
+                freeze_authority: None,
+                uri: None,
+                liquidity_usd: None,
+                refreshed_at: None,
+            })
+            .await
+            .unwrap();
+        cache
+    }
+
+    struct FailingProvider;
+
+    #[async_trait]
+    impl PriceProvider for FailingProvider {
+        async fn get_prices(&self, _mints: &[String]) -> Result<HashMap<String, Price>> {
+            Err(anyhow!("provider unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_static_provider_serves_known_mints_only() {
+        let provider = StaticPriceProvider::new(vec![price(SOL, 150.0)]);
+        let prices = provider
+            .get_prices(&[SOL.to_string(), UNKNOWN.to_string()])
+            .await
+            .unwrap();
+        assert_eq!(prices.len(), 1);
+        assert_eq!(prices[SOL].price_usd, 150.0);
+    }
+
+    #[tokio::test]
+    async fn test_load_prices_falls_back_to_cached_price() {
+        let mints = vec![SOL.to_string(), BONK.to_string(), UNKNOWN.to_string()];
+
+        let cache = bonk_cache().await;
+        let provider = StaticPriceProvider::new(vec![price(SOL, 150.0)]);
+        let prices = load_prices(&provider, &cache, &mints).await;
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[SOL], 150.0);
+        assert_eq!(prices[BONK], 0.00002);
+
+        // A provider outage leaves only the cached prices
+        let prices = load_prices(&FailingProvider, &cache, &mints).await;
+        assert_eq!(prices, HashMap::from([(BONK.to_string(), 0.00002)]));
+    }
+}
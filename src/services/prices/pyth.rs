@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use tracing::warn;
+
+use super::{Price, PriceProvider};
+use crate::models::{Candle, Resolution};
+use crate::services::blockchain::layout::ByteReader;
+use crate::services::blockchain::SolanaClient;
+use crate::utils::helpers::from_unix_timestamp;
+
+// Shipped feed list, used unless PYTH_FEEDS points at another file
+const BUNDLED_FEEDS: &str = include_str!("../../../data/pyth_feeds.json");
+const DEFAULT_BENCHMARKS_URL: &str = "https://benchmarks.pyth.network";
+
+// Pyth v2 price account: a header identifying the account, then the
+// exponent every price is scaled by, the aggregate's publish time and the
+// aggregate price itself
+const MAGIC: u32 = 0xa1b2c3d4;
+const ACCOUNT_TYPE_PRICE: u32 = 3;
+const ACCOUNT_TYPE_OFFSET: usize = 8;
+const EXPONENT_OFFSET: usize = 20;
+const PUBLISH_TIME_OFFSET: usize = 96;
+const AGGREGATE_OFFSET: usize = 208;
+// The aggregate is only meaningful while the feed is trading
+const STATUS_TRADING: u32 = 1;
+const DEFAULT_MAX_PRICE_AGE_SECS: i64 = 60;
+const DEFAULT_MAX_CONFIDENCE_RATIO: f64 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PythPrice {
+    pub price: i64,
+    pub confidence: u64,
+    pub exponent: i32,
+    pub status: u32,
+    pub publish_time: i64,
+}
+
+impl PythPrice {
+    pub fn is_trading(&self) -> bool {
+        self.status == STATUS_TRADING
+    }
+
+    pub fn to_price(&self, mint: &str) -> Price {
+        let scale = 10f64.powi(self.exponent);
+        Price {
+            mint: mint.to_string(),
+            price_usd: self.price as f64 * scale,
+            confidence: self.confidence as f64 * scale,
+            publish_time: from_unix_timestamp(self.publish_time),
+        }
+    }
+}
+
+// How old and how uncertain a trading price may be before it is ignored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceLimits {
+    pub max_age: chrono::Duration,
+    // Confidence interval as a fraction of the price
+    pub max_confidence_ratio: f64,
+}
+
+impl Default for PriceLimits {
+    fn default() -> Self {
+        Self {
+            max_age: chrono::Duration::seconds(DEFAULT_MAX_PRICE_AGE_SECS),
+            max_confidence_ratio: DEFAULT_MAX_CONFIDENCE_RATIO,
+        }
+    }
+}
+
+impl PriceLimits {
+    // PYTH_MAX_PRICE_AGE_SECS and PYTH_MAX_CONFIDENCE_RATIO replace the defaults
+    pub fn from_env() -> Result<Self> {
+        let mut limits = Self::default();
+        if let Ok(secs) = std::env::var("PYTH_MAX_PRICE_AGE_SECS") {
+            limits.max_age = chrono::Duration::seconds(secs.parse().context("Invalid PYTH_MAX_PRICE_AGE_SECS")?);
+        }
+        if let Ok(ratio) = std::env::var("PYTH_MAX_CONFIDENCE_RATIO") {
+            limits.max_confidence_ratio = ratio.parse().context("Invalid PYTH_MAX_CONFIDENCE_RATIO")?;
+        }
+        Ok(limits)
+    }
+
+    // Why `price` should not be used at `now`, if it should not
+    pub fn reject(&self, price: &PythPrice, now: DateTime<Utc>) -> Option<String> {
+        let published = from_unix_timestamp(price.publish_time);
+        if now - published > self.max_age {
+            return Some(format!("published at {}, older than {}s", published, self.max_age.num_seconds()));
+        }
+        if price.price <= 0 {
+            return Some(format!("non-positive price {}", price.price));
+        }
+        let ratio = price.confidence as f64 / price.price as f64;
+        if ratio > self.max_confidence_ratio {
+            return Some(format!(
+                "confidence is {:.2}% of the price, above {:.2}%",
+                ratio * 100.0,
+                self.max_confidence_ratio * 100.0
+            ));
+        }
+        None
+    }
+}
+
+pub fn parse_price_account(data: &[u8]) -> Result<PythPrice> {
+    let mut reader = ByteReader::new(data);
+    if reader.read_u32()? != MAGIC {
+        bail!("Not a Pyth account");
+    }
+    let account_type = ByteReader::at(data, ACCOUNT_TYPE_OFFSET).read_u32()?;
+    if account_type != ACCOUNT_TYPE_PRICE {
+        bail!("Pyth account type {} is not a price account", account_type);
+    }
+
+    let exponent = ByteReader::at(data, EXPONENT_OFFSET).read_i32()?;
+    let publish_time = ByteReader::at(data, PUBLISH_TIME_OFFSET).read_i64()?;
+    let mut aggregate = ByteReader::at(data, AGGREGATE_OFFSET);
+    Ok(PythPrice {
+        price: aggregate.read_i64()?,
+        confidence: aggregate.read_u64()?,
+        status: aggregate.read_u32()?,
+        exponent,
+        publish_time,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedFile {
+    feeds: Vec<PythFeed>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PythFeed {
+    pub mint: String,
+    pub symbol: String,
+    pub price_account: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryResponse {
+    s: String,
+    #[serde(default)]
+    t: Vec<i64>,
+    #[serde(default)]
+    o: Vec<f64>,
+    #[serde(default)]
+    h: Vec<f64>,
+    #[serde(default)]
+    l: Vec<f64>,
+    #[serde(default)]
+    c: Vec<f64>,
+    // Only some feeds report volume
+    #[serde(default)]
+    v: Vec<f64>,
+}
+
+// Pyth's archive of past aggregate prices, read through its TradingView
+// history endpoint
+pub struct PythBenchmarks {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl PythBenchmarks {
+    pub fn new(url: String) -> Result<Self> {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
+        Ok(Self { http, url })
+    }
+
+    // PYTH_BENCHMARKS_URL=<url> replaces the public endpoint
+    pub fn from_env() -> Result<Self> {
+        Self::new(std::env::var("PYTH_BENCHMARKS_URL").unwrap_or_else(|_| DEFAULT_BENCHMARKS_URL.to_string()))
+    }
+
+    // Candles for the feed `symbol` opening in `[start, end)`, labelled with `mint`
+    pub async fn get_candles(
+        &self,
+        symbol: &str,
+        mint: &str,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let resolution_param = match resolution {
+            Resolution::Minute => "1",
+            Resolution::Hour => "60",
+            Resolution::Day => "1D",
+        };
+        // `to` is inclusive
+        let response: HistoryResponse = self
+            .http
+            .get(format!(
+                "{}/v1/shims/tradingview/history",
+                self.url.trim_end_matches('/')
+            ))
+            .query(&[
+                ("symbol", format!("Crypto.{}", symbol)),
+                ("resolution", resolution_param.to_string()),
+                ("from", start.timestamp().to_string()),
+                ("to", (end.timestamp() - 1).to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        match response.s.as_str() {
+            "ok" => {}
+            "no_data" => return Ok(Vec::new()),
+            status => return Err(anyhow!("Pyth history for {} failed: {}", symbol, status)),
+        }
+        let candles = response
+            .t
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &time)| {
+                Some(Candle {
+                    mint: mint.to_string(),
+                    resolution,
+                    open_time: DateTime::from_timestamp(time, 0)?,
+                    open: *response.o.get(i)?,
+                    high: *response.h.get(i)?,
+                    low: *response.l.get(i)?,
+                    close: *response.c.get(i)?,
+                    volume: response.v.get(i).copied().unwrap_or_default(),
+                })
+            })
+            .filter(|candle| candle.open_time >= start && candle.open_time < end)
+            .collect();
+        Ok(candles)
+    }
+}
+
+struct Feed {
+    symbol: String,
+    account: Pubkey,
+}
+
+// Prices mints from the Pyth price accounts listed for them, and serves
+// their history from Pyth Benchmarks. Stale or uncertain prices are left out.
+pub struct PythPriceProvider {
+    client: Arc<SolanaClient>,
+    benchmarks: PythBenchmarks,
+    feeds: HashMap<String, Feed>,
+    limits: PriceLimits,
+}
+
+impl PythPriceProvider {
+    pub fn new(client: Arc<SolanaClient>, benchmarks: PythBenchmarks, feeds: Vec<PythFeed>) -> Result<Self> {
+        let feeds = feeds
+            .into_iter()
+            .map(|feed| {
+                let account = Pubkey::from_str(&feed.price_account)
+                    .with_context(|| format!("Invalid price account for {}", feed.symbol))?;
+                Ok((
+                    feed.mint,
+                    Feed {
+                        symbol: feed.symbol,
+                        account,
+                    },
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            client,
+            benchmarks,
+            feeds,
+            limits: PriceLimits::default(),
+        })
+    }
+
+    pub fn with_limits(mut self, limits: PriceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn from_file(client: Arc<SolanaClient>, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read Pyth feeds {}", path.display()))?;
+        Self::from_json(client, &contents).with_context(|| format!("Failed to parse Pyth feeds {}", path.display()))
+    }
+
+    // PYTH_FEEDS=<path> replaces the bundled feed list
+    pub fn from_env(client: Arc<SolanaClient>) -> Result<Self> {
+        match std::env::var("PYTH_FEEDS") {
+            Ok(path) => Self::from_file(client, path),
+            Err(_) => Self::from_json(client, BUNDLED_FEEDS),
+        }
+    }
+
+    fn from_json(client: Arc<SolanaClient>, json: &str) -> Result<Self> {
+        let file: FeedFile = serde_json::from_str(json)?;
+        Ok(Self::new(client, PythBenchmarks::from_env()?, file.feeds)?.with_limits(PriceLimits::from_env()?))
+    }
+}
+
+#[async_trait]
+impl PriceProvider for PythPriceProvider {
+    async fn get_prices(&self, mints: &[String]) -> Result<HashMap<String, Price>> {
+        let feeds: Vec<(&String, Pubkey)> = mints
+            .iter()
+            .filter_map(|mint| Some((mint, self.feeds.get(mint)?.account)))
+            .collect();
+        if feeds.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let accounts: Vec<Pubkey> = feeds.iter().map(|(_, account)| *account).collect();
+        let accounts = self.client.get_multiple_accounts(&accounts).await?;
+
+        let now = Utc::now();
+        let mut prices = HashMap::new();
+        for ((mint, address), account) in feeds.into_iter().zip(accounts) {
+            let Some(account) = account else {
+                warn!("Pyth price account {} for {} not found", address, mint);
+                continue;
+            };
+            match parse_price_account(&account.data) {
+                Ok(price) if price.is_trading() => match self.limits.reject(&price, now) {
+                    Some(reason) => warn!("Ignoring Pyth price for {}: {}", mint, reason),
+                    None => {
+                        prices.insert(mint.clone(), price.to_price(mint));
+                    }
+                },
+                // Halted or unknown feeds have no usable price
+                Ok(_) => {}
+                Err(e) => warn!("Failed to decode Pyth price account {}: {}", address, e),
+            }
+        }
+        Ok(prices)
+    }
+
+    async fn get_candles(
+        &self,
+        mint: &str,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        match self.feeds.get(mint) {
+            Some(feed) => {
+                self.benchmarks
+                    .get_candles(&feed.symbol, mint, resolution, start, end)
+                    .await
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn price_account(price: i64, confidence: u64, status: u32) -> Vec<u8> {
+        let mut data = vec![0; 240];
+        data[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        data[ACCOUNT_TYPE_OFFSET..ACCOUNT_TYPE_OFFSET + 4].copy_from_slice(&ACCOUNT_TYPE_PRICE.to_le_bytes());
+        data[EXPONENT_OFFSET..EXPONENT_OFFSET + 4].copy_from_slice(&(-8i32).to_le_bytes());
+        data[PUBLISH_TIME_OFFSET..PUBLISH_TIME_OFFSET + 8].copy_from_slice(&1_700_000_000i64.to_le_bytes());
+        data[AGGREGATE_OFFSET..AGGREGATE_OFFSET + 8].copy_from_slice(&price.to_le_bytes());
+        data[AGGREGATE_OFFSET + 8..AGGREGATE_OFFSET + 16].copy_from_slice(&confidence.to_le_bytes());
+        data[AGGREGATE_OFFSET + 16..AGGREGATE_OFFSET + 20].copy_from_slice(&status.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_parse_price_account() {
+        let price = parse_price_account(&price_account(15_012_345_678, 7_500_000, STATUS_TRADING)).unwrap();
+        assert!(price.is_trading());
+
+        let price = price.to_price("sol");
+        assert!((price.price_usd - 150.12345678).abs() < 1e-9);
+        assert!((price.confidence - 0.075).abs() < 1e-9);
+        assert_eq!(price.publish_time, from_unix_timestamp(1_700_000_000));
+
+        // Halted
+        assert!(!parse_price_account(&price_account(15_000_000_000, 0, 2))
+            .unwrap()
+            .is_trading());
+    }
+
+    fn trading(price: i64, confidence: u64) -> PythPrice {
+        PythPrice {
+            price,
+            confidence,
+            exponent: -8,
+            status: STATUS_TRADING,
+            publish_time: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_limits_reject_stale_prices() {
+        let limits = PriceLimits::default();
+        let price = trading(15_000_000_000, 1_000_000);
+        assert_eq!(limits.reject(&price, from_unix_timestamp(1_700_000_060)), None);
+        assert!(limits.reject(&price, from_unix_timestamp(1_700_000_061)).is_some());
+    }
+
+    #[test]
+    fn test_limits_reject_uncertain_prices() {
+        let limits = PriceLimits::default();
+        let now = from_unix_timestamp(1_700_000_010);
+        // 2% of $150 is $3
+        assert_eq!(limits.reject(&trading(15_000_000_000, 300_000_000), now), None);
+        assert!(limits.reject(&trading(15_000_000_000, 300_000_001), now).is_some());
+        assert!(limits.reject(&trading(0, 0), now).is_some());
+    }
+
+    #[test]
+    fn test_rejects_other_accounts() {
+        let mut data = price_account(1, 1, STATUS_TRADING);
+        data[ACCOUNT_TYPE_OFFSET] = 2;
+        assert!(parse_price_account(&data).is_err());
+        assert!(parse_price_account(&[0; 240]).is_err());
+        assert!(parse_price_account(&price_account(1, 1, STATUS_TRADING)[..200]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_benchmarks_history() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/shims/tradingview/history"))
+            .and(query_param("symbol", "Crypto.SOL/USD"))
+            .and(query_param("resolution", "60"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "s": "ok",
+                "t": [1_700_000_000, 1_700_003_600],
+                "o": [50.0, 51.0],
+                "h": [52.0, 53.0],
+                "l": [49.0, 50.5],
+                "c": [51.0, 52.5],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("symbol", "Crypto.USDC/USD"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "s": "no_data" })))
+            .mount(&server)
+            .await;
+
+        let benchmarks = PythBenchmarks::new(server.uri()).unwrap();
+        let start = from_unix_timestamp(1_700_000_000);
+        let end = from_unix_timestamp(1_700_007_200);
+
+        let candles = benchmarks
+            .get_candles("SOL/USD", "sol", Resolution::Hour, start, end)
+            .await
+            .unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[1].open_time, from_unix_timestamp(1_700_003_600));
+        assert_eq!(
+            (candles[1].high, candles[1].close, candles[1].volume),
+            (53.0, 52.5, 0.0)
+        );
+
+        assert!(benchmarks
+            .get_candles("USDC/USD", "usdc", Resolution::Hour, start, end)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::{Price, PriceProvider};
+use crate::models::{Candle, Resolution};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PriceFile {
+    prices: Vec<Price>,
+    candles: Vec<Candle>,
+}
+
+// Serves the same prices and price history for every request
+#[derive(Debug, Default)]
+pub struct StaticPriceProvider {
+    prices: HashMap<String, Price>,
+    candles: Vec<Candle>,
+}
+
+impl StaticPriceProvider {
+    pub fn new(prices: impl IntoIterator<Item = Price>) -> Self {
+        Self {
+            prices: prices.into_iter().map(|price| (price.mint.clone(), price)).collect(),
+            candles: Vec::new(),
+        }
+    }
+
+    pub fn with_candles(mut self, mut candles: Vec<Candle>) -> Self {
+        candles.sort_by_key(|candle| candle.open_time);
+        self.candles = candles;
+        self
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read price file {}", path.display()))?;
+        let file: PriceFile = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse price file {}", path.display()))?;
+        Ok(Self::new(file.prices).with_candles(file.candles))
+    }
+}
+
+#[async_trait]
+impl PriceProvider for StaticPriceProvider {
+    async fn get_prices(&self, mints: &[String]) -> Result<HashMap<String, Price>> {
+        Ok(mints
+            .iter()
+            .filter_map(|mint| Some((mint.clone(), self.prices.get(mint)?.clone())))
+            .collect())
+    }
+
+    async fn get_candles(
+        &self,
+        mint: &str,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        Ok(self
+            .candles
+            .iter()
+            .filter(|candle| candle.mint == mint && candle.resolution == resolution)
+            .filter(|candle| candle.open_time >= start && candle.open_time < end)
+            .cloned()
+            .collect())
+    }
+}
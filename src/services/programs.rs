@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::store::Store;
+use crate::models::{Commitment, EventKind, Transaction};
+use crate::services::blockchain::{NATIVE_DECIMALS, NATIVE_MINT};
+use crate::utils::helpers::format_token_amount;
+
+// Shipped registry, used unless PROTOCOL_REGISTRY points at another file
+const BUNDLED_REGISTRY: &str = include_str!("../../data/protocols.json");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolCategory {
+    Core,
+    Dex,
+    Staking,
+    Nft,
+    Lending,
+    Perps,
+    Bridge,
+    #[serde(other)]
+    Other,
+}
+
+impl ProtocolCategory {
+    // How much activity in this category adds to a wallet's risk, from 0 to 1
+    pub fn risk_weight(&self) -> f64 {
+        match self {
+            ProtocolCategory::Core => 0.0,
+            ProtocolCategory::Staking => 0.1,
+            ProtocolCategory::Dex => 0.2,
+            ProtocolCategory::Nft => 0.3,
+            ProtocolCategory::Lending | ProtocolCategory::Bridge | ProtocolCategory::Other => 0.5,
+            ProtocolCategory::Perps => 0.8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Protocol {
+    pub program_id: String,
+    pub name: String,
+    pub category: ProtocolCategory,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegistryFile {
+    programs: Vec<Protocol>,
+}
+
+// Human-readable names for program IDs, read from a JSON registry file
+#[derive(Debug, Default)]
+pub struct ProtocolRegistry {
+    protocols: HashMap<String, Protocol>,
+}
+
+impl ProtocolRegistry {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: RegistryFile = serde_json::from_str(json)?;
+        Ok(Self {
+            protocols: file
+                .programs
+                .into_iter()
+                .map(|protocol| (protocol.program_id.clone(), protocol))
+                .collect(),
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read protocol registry {}", path.display()))?;
+        Self::from_json(&contents).with_context(|| format!("Failed to parse protocol registry {}", path.display()))
+    }
+
+    // PROTOCOL_REGISTRY=<path> replaces the bundled registry
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("PROTOCOL_REGISTRY") {
+            Ok(path) => Self::from_file(path),
+            Err(_) => Self::from_json(BUNDLED_REGISTRY),
+        }
+    }
+
+    pub fn get(&self, program_id: &str) -> Option<&Protocol> {
+        self.protocols.get(program_id)
+    }
+}
+
+// Everything the wallet did with one program. `calls` are instructions the
+// wallet's transactions invoked directly; `cpi_calls` are invocations made
+// by other programs on its behalf.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProgramInteraction {
+    pub program_id: String,
+    pub protocol: Option<String>,
+    pub category: Option<ProtocolCategory>,
+    pub first_interaction: DateTime<Utc>,
+    pub last_interaction: DateTime<Utc>,
+    pub calls: usize,
+    pub cpi_calls: usize,
+    pub transactions: usize,
+    // SOL the wallet traded or moved in transactions that invoked the
+    // program; see `sol_volume`
+    pub volume_sol: f64,
+}
+
+// Program activity features fed into risk scoring
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProfileFeatures {
+    pub protocol_count: usize,
+    // Share of direct calls that went to programs missing from the registry
+    pub unknown_call_share: f64,
+    // Call-weighted category risk, with unknown programs at full weight
+    pub protocol_risk: f64,
+    pub active_days: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProgramProfile {
+    pub address: String,
+    pub transactions: usize,
+    // Most called first
+    pub programs: Vec<ProgramInteraction>,
+    pub features: ProfileFeatures,
+}
+
+pub struct ProgramProfileService {
+    db: Arc<dyn Store>,
+    registry: Arc<ProtocolRegistry>,
+}
+
+impl ProgramProfileService {
+    pub fn new(db: Arc<dyn Store>, registry: Arc<ProtocolRegistry>) -> Self {
+        Self { db, registry }
+    }
+
+    pub async fn build_profile(&self, address: &str, commitment: Commitment) -> Result<ProgramProfile> {
+        let transactions = self.db.get_all_wallet_transactions(address, commitment).await?;
+        Ok(build_profile(address, &transactions, &self.registry))
+    }
+}
+
+// Profiles the transactions the wallet paid for, which are the ones it
+// submitted itself rather than ones it merely appears in
+pub fn build_profile(address: &str, transactions: &[Transaction], registry: &ProtocolRegistry) -> ProgramProfile {
+    let mut programs: HashMap<&str, ProgramInteraction> = HashMap::new();
+    let mut transaction_count = 0;
+
+    for transaction in transactions
+        .iter()
+        .filter(|transaction| transaction.account_keys.first().map(String::as_str) == Some(address))
+    {
+        transaction_count += 1;
+        let volume = sol_volume(address, transaction);
+        let mut seen: Vec<&str> = Vec::new();
+
+        for event in &transaction.events {
+            let interaction = programs.entry(event.program_id.as_str()).or_insert_with(|| {
+                let protocol = registry.get(&event.program_id);
+                ProgramInteraction {
+                    program_id: event.program_id.clone(),
+                    protocol: protocol.map(|protocol| protocol.name.clone()),
+                    category: protocol.map(|protocol| protocol.category),
+                    first_interaction: transaction.block_time,
+                    last_interaction: transaction.block_time,
+                    calls: 0,
+                    cpi_calls: 0,
+                    transactions: 0,
+                    volume_sol: 0.0,
+                }
+            });
+            if event.inner_index.is_none() {
+                interaction.calls += 1;
+            } else {
+                interaction.cpi_calls += 1;
+            }
+
+            if !seen.contains(&event.program_id.as_str()) {
+                seen.push(event.program_id.as_str());
+                interaction.transactions += 1;
+                interaction.volume_sol += volume;
+                interaction.first_interaction = interaction.first_interaction.min(transaction.block_time);
+                interaction.last_interaction = interaction.last_interaction.max(transaction.block_time);
+            }
+        }
+    }
+
+    let mut programs: Vec<ProgramInteraction> = programs.into_values().collect();
+    programs.sort_by(|a, b| {
+        (b.calls, b.cpi_calls)
+            .cmp(&(a.calls, a.cpi_calls))
+            .then_with(|| a.program_id.cmp(&b.program_id))
+    });
+
+    ProgramProfile {
+        address: address.to_string(),
+        transactions: transaction_count,
+        features: profile_features(&programs),
+        programs,
+    }
+}
+
+fn profile_features(programs: &[ProgramInteraction]) -> ProfileFeatures {
+    let total_calls: usize = programs.iter().map(|program| program.calls).sum();
+    if total_calls == 0 {
+        return ProfileFeatures::default();
+    }
+
+    let unknown_calls: usize = programs
+        .iter()
+        .filter(|program| program.category.is_none())
+        .map(|program| program.calls)
+        .sum();
+    let weighted_risk: f64 = programs
+        .iter()
+        .map(|program| program.calls as f64 * program.category.map_or(1.0, |category| category.risk_weight()))
+        .sum();
+    let first = programs.iter().map(|program| program.first_interaction).min();
+    let last = programs.iter().map(|program| program.last_interaction).max();
+
+    ProfileFeatures {
+        protocol_count: programs.iter().filter(|program| program.protocol.is_some()).count(),
+        unknown_call_share: unknown_calls as f64 / total_calls as f64,
+        protocol_risk: weighted_risk / total_calls as f64,
+        active_days: first
+            .zip(last)
+            .map(|(first, last)| (last - first).num_days())
+            .unwrap_or_default(),
+    }
+}
+
+// SOL a transaction moved for `address`. Swaps count the SOL side of each
+// swap the wallet made, so a round trip through SOL counts both legs; other
+// transactions count the wallet's SOL and wrapped SOL balance change, fee
+// excluded. Token-for-token swaps have no SOL side and count nothing.
+pub fn sol_volume(address: &str, transaction: &Transaction) -> f64 {
+    let native_mint = NATIVE_MINT.to_string();
+    let is_sol = |mint: &Option<String>| mint.as_deref() == Some(native_mint.as_str());
+
+    let swapped: Vec<u64> = transaction
+        .events
+        .iter()
+        .filter_map(|event| match &event.kind {
+            EventKind::Swap { trader, input, output, .. } if trader == address => {
+                if is_sol(&input.mint) {
+                    Some(input.amount)
+                } else if is_sol(&output.mint) {
+                    Some(output.amount)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+        .collect();
+    if !swapped.is_empty() {
+        return format_token_amount(swapped.iter().sum(), NATIVE_DECIMALS);
+    }
+
+    let mut delta: i128 = transaction
+        .balance_changes
+        .iter()
+        .filter(|change| change.owner == address && (change.mint.is_none() || is_sol(&change.mint)))
+        .map(|change| change.post as i128 - change.pre as i128)
+        .sum();
+    if transaction.account_keys.first().map(String::as_str) == Some(address) {
+        delta += transaction.fee as i128;
+    }
+    format_token_amount(delta.unsigned_abs() as u64, NATIVE_DECIMALS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::store::MemoryStore;
+    use crate::models::{BalanceChange, SwapLeg, TransactionEvent};
+    use crate::utils::helpers::from_unix_timestamp;
+
+    const WALLET: &str = "wallet";
+    const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+    const RAYDIUM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+    const UNKNOWN: &str = "Unknown1111111111111111111111111111111111111";
+
+    fn event(program_id: &str, inner_index: Option<usize>) -> TransactionEvent {
+        TransactionEvent {
+            program_id: program_id.to_string(),
+            instruction_index: 0,
+            inner_index,
+            kind: EventKind::Unknown { data_len: 0 },
+        }
+    }
+
+    fn sol_change(owner: &str, mint: Option<&str>, pre: u64, post: u64) -> BalanceChange {
+        BalanceChange {
+            account: format!("{}-{}", owner, mint.unwrap_or("sol")),
+            owner: owner.to_string(),
+            mint: mint.map(str::to_string),
+            decimals: NATIVE_DECIMALS,
+            pre,
+            post,
+        }
+    }
+
+    // `payer` sends `sol` SOL, plus the fee
+    fn transaction(day: i64, payer: &str, sol: f64, events: Vec<TransactionEvent>) -> Transaction {
+        let sent = (sol * 1e9) as u64 + 5000;
+        Transaction {
+            signature: format!("sig{}", day),
+            block_time: from_unix_timestamp(1_700_000_000 + day * 86_400),
+            events,
+            balance_changes: vec![sol_change(payer, None, 10_000_000_000, 10_000_000_000 - sent)],
+            ..Transaction::paid_by(day as u64, payer)
+        }
+    }
+
+    fn swap(trader: &str, input: (&str, u64), output: (&str, u64)) -> TransactionEvent {
+        let leg = |(mint, amount): (&str, u64)| SwapLeg {
+            account: format!("{}-{}", trader, mint),
+            mint: Some(mint.to_string()),
+            decimals: Some(9),
+            amount,
+        };
+        TransactionEvent {
+            kind: EventKind::Swap {
+                dex: "Jupiter".to_string(),
+                trader: trader.to_string(),
+                pool: None,
+                route: Vec::new(),
+                input: leg(input),
+                output: leg(output),
+                price: None,
+            },
+            ..event(JUPITER, None)
+        }
+    }
+
+    #[test]
+    fn test_bundled_registry_parses() {
+        let registry = ProtocolRegistry::from_json(BUNDLED_REGISTRY).unwrap();
+        assert_eq!(
+            registry.get(JUPITER).map(|protocol| protocol.name.as_str()),
+            Some("Jupiter")
+        );
+        assert!(registry.get(UNKNOWN).is_none());
+    }
+
+    #[test]
+    fn test_build_profile() {
+        let registry = ProtocolRegistry::from_json(BUNDLED_REGISTRY).unwrap();
+        let transactions = vec![
+            transaction(0, WALLET, 1.0, vec![event(JUPITER, None), event(RAYDIUM, Some(0))]),
+            transaction(10, WALLET, 2.0, vec![event(JUPITER, None), event(UNKNOWN, None)]),
+            // Paid for by someone else
+            transaction(20, "other", 5.0, vec![event(UNKNOWN, None)]),
+        ];
+
+        let profile = build_profile(WALLET, &transactions, &registry);
+        assert_eq!(profile.transactions, 2);
+        assert_eq!(profile.programs.len(), 3);
+
+        let jupiter = &profile.programs[0];
+        assert_eq!(jupiter.protocol.as_deref(), Some("Jupiter"));
+        assert_eq!((jupiter.calls, jupiter.cpi_calls, jupiter.transactions), (2, 0, 2));
+        assert_eq!(jupiter.volume_sol, 3.0);
+        assert_eq!(jupiter.first_interaction, from_unix_timestamp(1_700_000_000));
+        assert_eq!(
+            jupiter.last_interaction,
+            from_unix_timestamp(1_700_000_000 + 10 * 86_400)
+        );
+
+        let raydium = profile
+            .programs
+            .iter()
+            .find(|program| program.program_id == RAYDIUM)
+            .unwrap();
+        assert_eq!((raydium.calls, raydium.cpi_calls), (0, 1));
+
+        // Two Jupiter calls at 0.2 and one unknown call at 1.0
+        assert_eq!(profile.features.protocol_count, 2);
+        assert_eq!(profile.features.unknown_call_share, 1.0 / 3.0);
+        assert!((profile.features.protocol_risk - 1.4 / 3.0).abs() < 1e-9);
+        assert_eq!(profile.features.active_days, 10);
+    }
+
+    #[test]
+    fn test_sol_volume() {
+        let wsol = NATIVE_MINT.to_string();
+
+        // Swapped out of wrapped SOL: the native balance only pays the fee
+        let mut swapped = transaction(0, WALLET, 0.0, vec![swap(WALLET, (&wsol, 2_000_000_000), ("usdc", 1))]);
+        swapped.balance_changes.push(sol_change(WALLET, Some(&wsol), 2_000_000_000, 0));
+        assert_eq!(sol_volume(WALLET, &swapped), 2.0);
+
+        // A round trip through SOL nets out but traded both legs
+        swapped.events = vec![
+            swap(WALLET, (&wsol, 1_000_000_000), ("usdc", 1)),
+            swap(WALLET, ("usdc", 1), (&wsol, 1_500_000_000)),
+        ];
+        assert_eq!(sol_volume(WALLET, &swapped), 2.5);
+
+        // Token for token, and someone else's swap
+        swapped.events = vec![swap(WALLET, ("usdc", 1), ("bonk", 1)), swap("other", (&wsol, 1), ("usdc", 1))];
+        swapped.balance_changes.truncate(1);
+        assert_eq!(sol_volume(WALLET, &swapped), 0.0);
+
+        // No swaps: the SOL sent, fee left out
+        assert_eq!(sol_volume(WALLET, &transaction(0, WALLET, 1.5, Vec::new())), 1.5);
+    }
+
+    #[tokio::test]
+    async fn test_profile_covers_the_whole_history() {
+        let store = Arc::new(MemoryStore::default());
+        store
+            .transactions
+            .lock()
+            .unwrap()
+            .extend((1..=1_500).map(|day| transaction(day, WALLET, 0.0, vec![event(JUPITER, None)])));
+
+        let profile = ProgramProfileService::new(store, Arc::new(ProtocolRegistry::default()))
+            .build_profile(WALLET, Commitment::Confirmed)
+            .await
+            .unwrap();
+        assert_eq!(profile.transactions, 1_500);
+        assert_eq!(profile.programs[0].calls, 1_500);
+    }
+}
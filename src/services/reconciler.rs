@@ -0,0 +1,237 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::TransactionStatus;
+use tracing::{info, warn};
+
+use crate::db::mongodb::MongoDB;
+use crate::models::{Commitment, TokenBalance};
+use crate::services::blockchain::SolanaClient;
+
+// Transactions checked per pass; anything left over is picked up next pass
+const RECONCILE_BATCH: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconciliation {
+    // Rooted, possibly in a different slot than first observed
+    Promote { slot: u64 },
+    // Observed in a slot that was skipped and not seen on the finalized fork
+    Drop,
+    Pending,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+    pub transactions_promoted: usize,
+    pub transactions_dropped: usize,
+    pub wallets_promoted: usize,
+    pub wallets_refreshed: usize,
+}
+
+// Settles data stored at confirmed commitment. Once the finalized slot has
+// passed a record's slot, the record is either promoted to finalized or, if
+// that slot never made it onto the finalized fork, removed. Wallet balances
+// read from a skipped slot are re-read at finalized commitment.
+pub struct Reconciler {
+    client: Arc<SolanaClient>,
+    db: Arc<MongoDB>,
+}
+
+impl Reconciler {
+    pub fn new(client: Arc<SolanaClient>, db: Arc<MongoDB>) -> Self {
+        Self { client, db }
+    }
+
+    pub async fn run(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match self.reconcile().await {
+                Ok(report) if report != ReconcileReport::default() => info!("Reconciled: {:?}", report),
+                Ok(_) => {}
+                Err(e) => warn!("Reconciliation failed: {}", e),
+            }
+        }
+    }
+
+    pub async fn reconcile(&self) -> Result<ReconcileReport> {
+        let finalized_slot = self.client.get_finalized_slot().await?;
+        let transactions = self
+            .db
+            .get_unfinalized_transactions(finalized_slot, RECONCILE_BATCH)
+            .await?;
+        let wallets = self.db.get_wallets_with_unfinalized_balances(finalized_slot).await?;
+
+        let slots: Vec<u64> = transactions
+            .iter()
+            .map(|transaction| transaction.slot)
+            .chain(
+                wallets
+                    .iter()
+                    .flat_map(|wallet| pending_slots(&wallet.tokens, finalized_slot)),
+            )
+            .collect();
+        if slots.is_empty() {
+            return Ok(ReconcileReport::default());
+        }
+        let finalized_blocks = self.client.get_finalized_blocks(&slots).await?;
+
+        let mut report = ReconcileReport::default();
+
+        let signatures: Vec<String> = transactions.iter().map(|t| t.signature.clone()).collect();
+        let statuses = self.client.get_signature_statuses(&signatures).await?;
+        for (transaction, status) in transactions.iter().zip(&statuses) {
+            match reconcile_transaction(transaction.slot, status.as_ref(), &finalized_blocks) {
+                Reconciliation::Promote { slot } => {
+                    self.db.finalize_transaction(&transaction.signature, slot).await?;
+                    report.transactions_promoted += 1;
+                }
+                Reconciliation::Drop => {
+                    warn!(
+                        "Dropping transaction {}: slot {} was skipped",
+                        transaction.signature, transaction.slot
+                    );
+                    self.db.delete_transaction(&transaction.signature).await?;
+                    report.transactions_dropped += 1;
+                }
+                Reconciliation::Pending => {}
+            }
+        }
+
+        for mut wallet in wallets {
+            if promote_balances(&mut wallet.tokens, finalized_slot, &finalized_blocks) {
+                report.wallets_promoted += 1;
+            } else {
+                warn!(
+                    "Balances for {} were read from a skipped slot, refreshing",
+                    wallet.address
+                );
+                wallet.tokens = self
+                    .client
+                    .get_wallet_tokens(&wallet.address, Commitment::Finalized)
+                    .await?;
+                report.wallets_refreshed += 1;
+            }
+            wallet.updated_at = Utc::now();
+            self.db.save_wallet(&wallet).await?;
+        }
+
+        Ok(report)
+    }
+}
+
+fn pending_slots(tokens: &[TokenBalance], finalized_slot: u64) -> impl Iterator<Item = u64> + '_ {
+    tokens
+        .iter()
+        .filter(move |token| {
+            token.commitment != Commitment::Finalized && token.slot > 0 && token.slot <= finalized_slot
+        })
+        .map(|token| token.slot)
+}
+
+// `observed_slot` must be at or below the finalized slot, and
+// `finalized_blocks` must have been read for it
+pub fn reconcile_transaction(
+    observed_slot: u64,
+    status: Option<&TransactionStatus>,
+    finalized_blocks: &HashSet<u64>,
+) -> Reconciliation {
+    match status {
+        Some(status) if status.satisfies_commitment(CommitmentConfig::finalized()) => {
+            Reconciliation::Promote { slot: status.slot }
+        }
+        // Landed again on another fork; it will be promoted from there
+        Some(_) => Reconciliation::Pending,
+        None if !finalized_blocks.contains(&observed_slot) => Reconciliation::Drop,
+        // The slot is rooted but the status lookup came back empty, which
+        // points at the node rather than the transaction; retry next pass
+        None => Reconciliation::Pending,
+    }
+}
+
+// Promotes balances whose slot is rooted. Returns false if any pending
+// balance was read from a skipped slot, in which case the whole set is stale.
+pub fn promote_balances(tokens: &mut [TokenBalance], finalized_slot: u64, finalized_blocks: &HashSet<u64>) -> bool {
+    let settled = |token: &TokenBalance| {
+        token.commitment == Commitment::Finalized || token.slot == 0 || token.slot > finalized_slot
+    };
+    if tokens
+        .iter()
+        .any(|token| !settled(token) && !finalized_blocks.contains(&token.slot))
+    {
+        return false;
+    }
+
+    for token in tokens.iter_mut().filter(|token| !settled(token)) {
+        token.commitment = Commitment::Finalized;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_transaction_status::TransactionConfirmationStatus;
+
+    fn status(slot: u64, finalized: bool) -> TransactionStatus {
+        TransactionStatus {
+            slot,
+            confirmations: if finalized { None } else { Some(10) },
+            status: Ok(()),
+            err: None,
+            confirmation_status: Some(if finalized {
+                TransactionConfirmationStatus::Finalized
+            } else {
+                TransactionConfirmationStatus::Confirmed
+            }),
+        }
+    }
+
+    fn balance(slot: u64, commitment: Commitment) -> TokenBalance {
+        TokenBalance {
+            slot,
+            commitment,
+            ..TokenBalance::new("mint".to_string(), 1, 0)
+        }
+    }
+
+    #[test]
+    fn test_reconcile_transaction() {
+        let blocks: HashSet<u64> = [100, 101, 103].into_iter().collect();
+
+        assert_eq!(
+            reconcile_transaction(100, Some(&status(100, true)), &blocks),
+            Reconciliation::Promote { slot: 100 }
+        );
+        // Observed in a skipped slot, then re-included and rooted at 103
+        assert_eq!(
+            reconcile_transaction(102, Some(&status(103, true)), &blocks),
+            Reconciliation::Promote { slot: 103 }
+        );
+        assert_eq!(reconcile_transaction(102, None, &blocks), Reconciliation::Drop);
+        assert_eq!(reconcile_transaction(101, None, &blocks), Reconciliation::Pending);
+        assert_eq!(
+            reconcile_transaction(102, Some(&status(104, false)), &blocks),
+            Reconciliation::Pending
+        );
+    }
+
+    #[test]
+    fn test_promote_balances() {
+        let blocks: HashSet<u64> = [100, 101, 103].into_iter().collect();
+
+        let mut tokens = vec![balance(100, Commitment::Confirmed), balance(105, Commitment::Confirmed)];
+        assert!(promote_balances(&mut tokens, 103, &blocks));
+        assert_eq!(tokens[0].commitment, Commitment::Finalized);
+        // Beyond the finalized slot, so not settled yet
+        assert_eq!(tokens[1].commitment, Commitment::Confirmed);
+
+        let mut skipped = vec![balance(100, Commitment::Confirmed), balance(102, Commitment::Confirmed)];
+        assert!(!promote_balances(&mut skipped, 103, &blocks));
+        assert!(skipped.iter().all(|token| token.commitment == Commitment::Confirmed));
+    }
+}
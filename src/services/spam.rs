@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::store::Store;
+use crate::models::{Commitment, EventKind, Token, TokenBalance, Transaction};
+use crate::services::blockchain::NATIVE_MINT;
+
+const SPAM_HISTORY_LIMIT: i64 = 10_000;
+
+// Words that turn a link in token metadata into a lure
+const CLAIM_KEYWORDS: [&str; 8] = [
+    "claim", "airdrop", "reward", "redeem", "voucher", "bonus", "gift", "visit",
+];
+const URL_SUFFIXES: [&str; 10] = [
+    ".com", ".io", ".xyz", ".app", ".net", ".org", ".site", ".fun", ".pro", ".info",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SpamSignal {
+    // Listed in the local denylist
+    Denylisted,
+    // Sent to the wallet by someone else, and never traded or moved by it
+    Unsolicited,
+    // Name, symbol or metadata URI links to a claim page
+    ClaimLink { text: String },
+    // A market data source reported no pooled liquidity
+    NoLiquidity,
+}
+
+impl SpamSignal {
+    // Denylisted mints and claim links are spam on their own; the other
+    // signals also match plenty of legitimate long-tail tokens
+    fn is_conclusive(&self) -> bool {
+        matches!(self, SpamSignal::Denylisted | SpamSignal::ClaimLink { .. })
+    }
+}
+
+// A holding left out of portfolio value and scoring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuspiciousAsset {
+    pub balance: TokenBalance,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub signals: Vec<SpamSignal>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DenylistFile {
+    mints: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct SpamClassifier {
+    denylist: HashSet<String>,
+}
+
+impl SpamClassifier {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: DenylistFile = serde_json::from_str(json)?;
+        Ok(Self {
+            denylist: file.mints.into_iter().collect(),
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read spam denylist {}", path.display()))?;
+        Self::from_json(&contents).with_context(|| format!("Failed to parse spam denylist {}", path.display()))
+    }
+
+    // SPAM_DENYLIST=<path> loads a denylist; without one no mint is denylisted
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("SPAM_DENYLIST") {
+            Ok(path) => Self::from_file(path),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    // Every signal that applies to a holding. Unsolicited receipt is judged
+    // from stored history, so a wallet we have not indexed yet never raises
+    // it. Liquidity is only judged when a source has reported it; a token
+    // nobody prices is not evidence of spam.
+    pub fn signals(
+        &self,
+        address: &str,
+        balance: &TokenBalance,
+        token: Option<&Token>,
+        transactions: &[Transaction],
+    ) -> Vec<SpamSignal> {
+        let mut signals = Vec::new();
+        if self.denylist.contains(&balance.token_address) {
+            signals.push(SpamSignal::Denylisted);
+        }
+        if is_unsolicited(address, &balance.token_address, transactions) {
+            signals.push(SpamSignal::Unsolicited);
+        }
+        if let Some(token) = token {
+            let texts = [Some(&token.name), Some(&token.symbol), token.uri.as_ref()];
+            if let Some(text) = texts.into_iter().flatten().find(|text| is_claim_link(text)) {
+                signals.push(SpamSignal::ClaimLink { text: text.clone() });
+            }
+            if token.liquidity_usd.is_some_and(|liquidity| liquidity <= 0.0) {
+                signals.push(SpamSignal::NoLiquidity);
+            }
+        }
+        signals
+    }
+
+    // Flags holdings with a conclusive signal, or with both weak ones
+    pub fn classify(
+        &self,
+        address: &str,
+        balance: &TokenBalance,
+        token: Option<&Token>,
+        transactions: &[Transaction],
+    ) -> Option<SuspiciousAsset> {
+        // SOL and liquid staking tokens are never spam
+        if balance.token_address == NATIVE_MINT.to_string() || balance.sol_exchange_rate.is_some() {
+            return None;
+        }
+
+        let signals = self.signals(address, balance, token, transactions);
+        let suspicious = signals.iter().any(SpamSignal::is_conclusive)
+            || (signals.contains(&SpamSignal::Unsolicited) && signals.contains(&SpamSignal::NoLiquidity));
+        suspicious.then(|| SuspiciousAsset {
+            balance: balance.clone(),
+            name: token.map(|token| token.name.clone()),
+            symbol: token.map(|token| token.symbol.clone()),
+            signals,
+        })
+    }
+}
+
+// The wallet received the mint in a transaction someone else paid for, and
+// none of the transactions it paid for touch the mint
+fn is_unsolicited(address: &str, mint: &str, transactions: &[Transaction]) -> bool {
+    let mut received = false;
+    for transaction in transactions {
+        let paid_by_wallet = transaction.account_keys.first().map(String::as_str) == Some(address);
+        for event in &transaction.events {
+            let touches_mint = match &event.kind {
+                EventKind::TokenTransfer {
+                    mint: Some(transferred),
+                    destination_owner,
+                    ..
+                } if transferred == mint => {
+                    received |= !paid_by_wallet && destination_owner.as_deref() == Some(address);
+                    true
+                }
+                EventKind::Swap { input, output, .. } => {
+                    input.mint.as_deref() == Some(mint) || output.mint.as_deref() == Some(mint)
+                }
+                _ => false,
+            };
+            if paid_by_wallet && touches_mint {
+                return false;
+            }
+        }
+    }
+    received
+}
+
+fn is_claim_link(text: &str) -> bool {
+    let text = text.to_lowercase();
+    let has_url = text.split_whitespace().any(|word| {
+        let word = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+        word.contains("://")
+            || word.starts_with("www.")
+            || URL_SUFFIXES
+                .iter()
+                .any(|suffix| word.ends_with(suffix) || word.contains(&format!("{}/", suffix)))
+    });
+    has_url && CLAIM_KEYWORDS.iter().any(|keyword| text.contains(keyword))
+}
+
+pub struct SpamFilter {
+    db: Arc<dyn Store>,
+    classifier: Arc<SpamClassifier>,
+}
+
+impl SpamFilter {
+    pub fn new(db: Arc<dyn Store>, classifier: Arc<SpamClassifier>) -> Self {
+        Self { db, classifier }
+    }
+
+    // Splits holdings into the ones to keep and the suspicious ones.
+    // `tokens` holds whatever token info is known, keyed by mint.
+    pub async fn screen(
+        &self,
+        address: &str,
+        balances: Vec<TokenBalance>,
+        tokens: &HashMap<String, Token>,
+        commitment: Commitment,
+    ) -> Result<(Vec<TokenBalance>, Vec<SuspiciousAsset>)> {
+        // Only transactions that moved a screened mint bear on it, and the
+        // newest come first so recent airdrops are never cut off
+        let mints: Vec<String> = balances.iter().map(|balance| balance.token_address.clone()).collect();
+        let transactions = self
+            .db
+            .get_mint_transactions(address, &mints, SPAM_HISTORY_LIMIT, commitment)
+            .await?;
+
+        let mut kept = Vec::new();
+        let mut suspicious = Vec::new();
+        for balance in balances {
+            let token = tokens.get(&balance.token_address);
+            match self.classifier.classify(address, &balance, token, &transactions) {
+                Some(asset) => suspicious.push(asset),
+                None => kept.push(balance),
+            }
+        }
+        Ok((kept, suspicious))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::store::MemoryStore;
+    use crate::models::TransactionEvent;
+
+    const WALLET: &str = "wallet";
+    const SCAM_MINT: &str = "Scam111111111111111111111111111111111111111";
+
+    fn token(name: &str, uri: Option<&str>, liquidity_usd: Option<f64>) -> Token {
+        Token {
+            address: SCAM_MINT.to_string(),
+            symbol: "SCAM".to_string(),
+            name: name.to_string(),
+            decimals: 6,
+            total_supply: 1_000_000_000,
+            price_usd: 0.0,
+            market_cap_usd: 0.0,
+            volume_24h: 0.0,
+            price_change_24h: 0.0,
+            mint_authority: None,
+            freeze_authority: None,
+            uri: uri.map(str::to_string),
+            liquidity_usd,
+            refreshed_at: None,
+        }
+    }
+
+    fn airdrop(slot: u64, payer: &str) -> Transaction {
+        Transaction {
+            to_address: WALLET.to_string(),
+            token_address: Some(SCAM_MINT.to_string()),
+            events: vec![TransactionEvent {
+                program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".to_string(),
+                instruction_index: 0,
+                inner_index: None,
+                kind: EventKind::TokenTransfer {
+                    source: "source".to_string(),
+                    destination: "destination".to_string(),
+                    authority: payer.to_string(),
+                    mint: Some(SCAM_MINT.to_string()),
+                    amount: 1_000_000,
+                    decimals: Some(6),
+                    destination_owner: Some(WALLET.to_string()),
+                },
+            }],
+            ..Transaction::paid_by(slot, payer)
+        }
+    }
+
+    #[test]
+    fn test_claim_links() {
+        assert!(is_claim_link("Visit jup-rewards.io to claim"));
+        assert!(is_claim_link("https://claim-airdrop.xyz/meta.json"));
+        assert!(!is_claim_link("https://arweave.net/bonk.json"));
+        assert!(!is_claim_link("Reward Token"));
+    }
+
+    #[test]
+    fn test_classify() {
+        let classifier = SpamClassifier::from_json(&format!(r#"{{ "mints": ["{}"] }}"#, "Denied1111")).unwrap();
+        let balance = TokenBalance::new(SCAM_MINT.to_string(), 1_000_000, 6);
+        let dropped = vec![airdrop(1, "spammer")];
+
+        // Unsolicited alone is not enough; unsolicited and illiquid is
+        let liquid = token("Scam", None, Some(50_000.0));
+        assert!(classifier.classify(WALLET, &balance, Some(&liquid), &dropped).is_none());
+        // Unpriced with no liquidity report says nothing about liquidity
+        let unknown = token("Scam", None, None);
+        assert!(classifier.classify(WALLET, &balance, Some(&unknown), &dropped).is_none());
+        let illiquid = token("Scam", None, Some(0.0));
+        let asset = classifier
+            .classify(WALLET, &balance, Some(&illiquid), &dropped)
+            .unwrap();
+        assert_eq!(asset.signals, vec![SpamSignal::Unsolicited, SpamSignal::NoLiquidity]);
+
+        // Moving the token from the wallet's own transactions makes it solicited
+        let traded = vec![airdrop(1, "spammer"), airdrop(2, WALLET)];
+        assert!(classifier
+            .classify(WALLET, &balance, Some(&illiquid), &traded)
+            .is_none());
+
+        // A claim link flags the token on its own
+        let lure = token("Scam", Some("https://claim-airdrop.xyz/meta.json"), Some(50_000.0));
+        assert!(classifier.classify(WALLET, &balance, Some(&lure), &[]).is_some());
+
+        let denied = TokenBalance::new("Denied1111".to_string(), 1, 0);
+        let asset = classifier.classify(WALLET, &denied, None, &[]).unwrap();
+        assert_eq!(asset.signals, vec![SpamSignal::Denylisted]);
+    }
+
+    #[tokio::test]
+    async fn test_screen_sees_recent_airdrops_past_a_long_history() {
+        let store = Arc::new(MemoryStore::default());
+        {
+            // A long history of the wallet trading something else, then the airdrop
+            let mut transactions = store.transactions.lock().unwrap();
+            transactions.extend((1..=SPAM_HISTORY_LIMIT as u64).map(|slot| Transaction {
+                token_address: Some("Other111111111111111111111111111111111111111".to_string()),
+                ..Transaction::paid_by(slot, WALLET)
+            }));
+            transactions.push(Transaction {
+                wallet: WALLET.to_string(),
+                ..airdrop(SPAM_HISTORY_LIMIT as u64 + 1, "spammer")
+            });
+        }
+        let filter = SpamFilter::new(store, Arc::new(SpamClassifier::default()));
+
+        let balance = TokenBalance::new(SCAM_MINT.to_string(), 1_000_000, 6);
+        let tokens = HashMap::from([(SCAM_MINT.to_string(), token("Scam", None, Some(0.0)))]);
+        let (kept, suspicious) = filter
+            .screen(WALLET, vec![balance], &tokens, Commitment::Confirmed)
+            .await
+            .unwrap();
+        assert!(kept.is_empty());
+        assert_eq!(
+            suspicious[0].signals,
+            vec![SpamSignal::Unsolicited, SpamSignal::NoLiquidity]
+        );
+    }
+}
@@ -0,0 +1,615 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::stream::{BoxStream, SelectAll};
+use futures::StreamExt;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcTransactionLogsConfig,
+    RpcTransactionLogsFilter,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::{mpsc, Notify, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::db::mongodb::MongoDB;
+use crate::models::{Commitment, EventKind, Transaction};
+use crate::services::blockchain::{
+    aggregate_balances, parse_token_account, NativeBalance, SolanaClient, TokenAccountBalance,
+    TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID,
+};
+use crate::utils::helpers::redact_url;
+
+// Offset of the owner field in an SPL token account
+const TOKEN_ACCOUNT_OWNER_OFFSET: usize = 32;
+const TOKEN_ACCOUNT_LEN: u64 = 165;
+// A logs notification can arrive before the node serves the transaction at
+// the same commitment; fetches are retried this many times, doubling the delay
+const TRANSACTION_FETCH_RETRIES: u32 = 5;
+const TRANSACTION_FETCH_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalletUpdate {
+    // Emitted after every (re)connect once all subscriptions are live.
+    // Anything that happened while disconnected has been missed, so
+    // consumers should treat their state for these wallets as stale.
+    Subscribed {
+        wallets: Vec<String>,
+    },
+    NativeBalance {
+        wallet: String,
+        lamports: u64,
+        slot: u64,
+    },
+    TokenAccount {
+        wallet: String,
+        account: TokenAccountBalance,
+        slot: u64,
+    },
+    Activity {
+        wallet: String,
+        signature: String,
+        success: bool,
+        slot: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+// Watches tracked wallets over the PubSub websocket and emits WalletUpdates.
+// Each wallet gets an account subscription (native SOL), a program
+// subscription per token program filtered to accounts it owns, and a logs
+// subscription for any transaction mentioning it.
+pub struct WalletStreamer {
+    ws_url: String,
+    tracked: RwLock<HashSet<String>>,
+    changed: Notify,
+    policy: ReconnectPolicy,
+    commitment: Commitment,
+}
+
+impl WalletStreamer {
+    pub fn new(ws_url: String, wallets: impl IntoIterator<Item = String>, policy: ReconnectPolicy) -> Self {
+        Self {
+            ws_url,
+            tracked: RwLock::new(wallets.into_iter().collect()),
+            changed: Notify::new(),
+            policy,
+            commitment: Commitment::default(),
+        }
+    }
+
+    // Notifications are only sent once a change reaches this commitment
+    pub fn with_commitment(mut self, commitment: Commitment) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    // SOLANA_WS_URL, falling back to the RPC URL with a websocket scheme
+    pub fn ws_url_from_env() -> Result<String> {
+        if let Ok(url) = std::env::var("SOLANA_WS_URL") {
+            return Ok(url);
+        }
+        let rpc_url = std::env::var("SOLANA_RPC_URL")?;
+        Ok(rpc_url
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1))
+    }
+
+    pub async fn track(&self, wallet: String) {
+        if self.tracked.write().await.insert(wallet) {
+            self.changed.notify_one();
+        }
+    }
+
+    pub async fn untrack(&self, wallet: &str) {
+        if self.tracked.write().await.remove(wallet) {
+            self.changed.notify_one();
+        }
+    }
+
+    // Runs until the receiving side of `updates` is dropped
+    pub async fn run(&self, updates: mpsc::Sender<WalletUpdate>) {
+        let mut backoff = self.policy.initial_backoff;
+
+        loop {
+            match self.run_session(&updates, &mut backoff).await {
+                Ok(()) => debug!("Tracked wallets changed, resubscribing"),
+                Err(e) => warn!("Wallet stream disconnected: {}", e),
+            }
+
+            if updates.is_closed() {
+                return;
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+        }
+    }
+
+    // One websocket connection. Returns Ok when the tracked set changes and
+    // Err when the connection drops or a subscription fails.
+    async fn run_session(
+        &self,
+        updates: &mpsc::Sender<WalletUpdate>,
+        backoff: &mut Duration,
+    ) -> Result<()> {
+        let client = PubsubClient::new(&self.ws_url).await?;
+        let wallets: Vec<String> = self.tracked.read().await.iter().cloned().collect();
+
+        let mut streams: SelectAll<BoxStream<'_, WalletUpdate>> = SelectAll::new();
+        for wallet in &wallets {
+            self.subscribe_wallet(&client, wallet, &mut streams).await?;
+        }
+
+        info!("Streaming {} wallets from {}", wallets.len(), redact_url(&self.ws_url));
+        *backoff = self.policy.initial_backoff;
+        let idle = wallets.is_empty();
+        if updates.send(WalletUpdate::Subscribed { wallets }).await.is_err() {
+            return Ok(());
+        }
+
+        if idle {
+            self.changed.notified().await;
+            *backoff = Duration::ZERO;
+            return Ok(());
+        }
+
+        loop {
+            tokio::select! {
+                _ = self.changed.notified() => {
+                    // Immediate resubscribe; the loop in `run` would back off otherwise
+                    *backoff = Duration::ZERO;
+                    return Ok(());
+                }
+                update = streams.next() => match update {
+                    Some(update) => {
+                        if updates.send(update).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                    None => return Err(anyhow!("websocket closed by {}", redact_url(&self.ws_url))),
+                },
+            }
+        }
+    }
+
+    async fn subscribe_wallet<'a>(
+        &self,
+        client: &'a PubsubClient,
+        wallet: &str,
+        streams: &mut SelectAll<BoxStream<'a, WalletUpdate>>,
+    ) -> Result<()> {
+        let owner = Pubkey::from_str(wallet)?;
+
+        let (accounts, _) = client
+            .account_subscribe(
+                &owner,
+                Some(RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(self.commitment.into()),
+                    ..Default::default()
+                }),
+            )
+            .await?;
+        let address = wallet.to_string();
+        streams.push(
+            accounts
+                .map(move |response| WalletUpdate::NativeBalance {
+                    wallet: address.clone(),
+                    lamports: response.value.lamports,
+                    slot: response.context.slot,
+                })
+                .boxed(),
+        );
+
+        for program_id in [TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID] {
+            let mut filters = vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                TOKEN_ACCOUNT_OWNER_OFFSET,
+                owner.as_ref(),
+            ))];
+            // Token-2022 accounts grow with extensions, so only classic SPL
+            // accounts have a fixed size to filter on
+            if program_id == TOKEN_PROGRAM_ID {
+                filters.push(RpcFilterType::DataSize(TOKEN_ACCOUNT_LEN));
+            }
+
+            let (token_accounts, _) = client
+                .program_subscribe(
+                    &program_id,
+                    Some(RpcProgramAccountsConfig {
+                        filters: Some(filters),
+                        account_config: RpcAccountInfoConfig {
+                            encoding: Some(UiAccountEncoding::JsonParsed),
+                            commitment: Some(self.commitment.into()),
+                            ..Default::default()
+                        },
+                        with_context: Some(true),
+                    }),
+                )
+                .await?;
+            let address = wallet.to_string();
+            streams.push(
+                token_accounts
+                    .filter_map(move |response| {
+                        let update = parse_token_account(&response.value.account.data).map(
+                            |(mint, raw_amount, decimals)| WalletUpdate::TokenAccount {
+                                wallet: address.clone(),
+                                account: TokenAccountBalance {
+                                    address: response.value.pubkey.clone(),
+                                    mint,
+                                    raw_amount,
+                                    decimals,
+                                    slot: response.context.slot,
+                                },
+                                slot: response.context.slot,
+                            },
+                        );
+                        futures::future::ready(update)
+                    })
+                    .boxed(),
+            );
+        }
+
+        let (logs, _) = client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![wallet.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(self.commitment.into()),
+                },
+            )
+            .await?;
+        let address = wallet.to_string();
+        streams.push(
+            logs.map(move |response| WalletUpdate::Activity {
+                wallet: address.clone(),
+                signature: response.value.signature,
+                success: response.value.err.is_none(),
+                slot: response.context.slot,
+            })
+            .boxed(),
+        );
+
+        Ok(())
+    }
+}
+
+// Changes the set of wallets a running subscription watches
+#[async_trait]
+pub trait WalletTracker: Send + Sync {
+    async fn track(&self, wallet: String);
+
+    async fn untrack(&self, wallet: &str);
+}
+
+#[async_trait]
+impl WalletTracker for WalletStreamer {
+    async fn track(&self, wallet: String) {
+        WalletStreamer::track(self, wallet).await
+    }
+
+    async fn untrack(&self, wallet: &str) {
+        WalletStreamer::untrack(self, wallet).await
+    }
+}
+
+// Applies WalletUpdates to the stored Wallet documents. Token account
+// balances are kept per account so that several accounts holding the same
+// mint merge correctly; they are re-seeded from RPC after every reconnect.
+// Closing an account sends no token account update, so closed accounts are
+// dropped when the wallet's transaction closing them comes in.
+// Balances and transactions are tagged with the client's commitment, which
+// should match the streamer's.
+pub struct WalletSync {
+    client: Arc<SolanaClient>,
+    db: Arc<MongoDB>,
+    native: HashMap<String, NativeBalance>,
+    token_accounts: HashMap<String, HashMap<String, TokenAccountBalance>>,
+}
+
+impl WalletSync {
+    pub fn new(client: Arc<SolanaClient>, db: Arc<MongoDB>) -> Self {
+        Self {
+            client,
+            db,
+            native: HashMap::new(),
+            token_accounts: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self, mut updates: mpsc::Receiver<WalletUpdate>) {
+        // Transactions are fetched in the background so retries do not hold
+        // up other wallets' updates
+        let (fetched_sender, mut fetched) = mpsc::channel(64);
+        loop {
+            let result = tokio::select! {
+                update = updates.recv() => match update {
+                    Some(update) => self.apply(update, &fetched_sender).await,
+                    None => return,
+                },
+                Some((wallet, transaction)) = fetched.recv() => self.record(&wallet, transaction).await,
+            };
+            if let Err(e) = result {
+                warn!("Failed to apply wallet update: {}", e);
+            }
+        }
+    }
+
+    fn fetch(&self, wallet: String, signature: String, fetched: mpsc::Sender<(String, Transaction)>) {
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            match fetch_transaction(&client, &signature, &wallet).await {
+                Ok(transaction) => {
+                    let _ = fetched.send((wallet, transaction)).await;
+                }
+                Err(e) => warn!("Failed to fetch transaction {} for {}: {}", signature, wallet, e),
+            }
+        });
+    }
+
+    async fn record(&mut self, wallet: &str, transaction: Transaction) -> Result<()> {
+        self.db.save_transaction(&transaction).await?;
+
+        let closed = closed_token_accounts(&transaction);
+        let Some(accounts) = self.token_accounts.get_mut(wallet) else {
+            return Ok(());
+        };
+        let tracked = accounts.len();
+        accounts.retain(|address, _| !closed.contains(address.as_str()));
+        if accounts.len() != tracked {
+            self.write(wallet).await?;
+        }
+        Ok(())
+    }
+
+    // Activity only starts a fetch; the transaction is recorded once
+    // `fetched` delivers it
+    async fn apply(&mut self, update: WalletUpdate, fetched: &mpsc::Sender<(String, Transaction)>) -> Result<()> {
+        match update {
+            WalletUpdate::Subscribed { wallets } => {
+                for wallet in wallets {
+                    self.seed(&wallet).await?;
+                    self.write(&wallet).await?;
+                }
+            }
+            WalletUpdate::NativeBalance { wallet, lamports, slot } => {
+                self.native.insert(wallet.clone(), NativeBalance { lamports, slot });
+                self.write(&wallet).await?;
+            }
+            WalletUpdate::TokenAccount { wallet, account, .. } => {
+                self.token_accounts
+                    .entry(wallet.clone())
+                    .or_default()
+                    .insert(account.address.clone(), account);
+                self.write(&wallet).await?;
+            }
+            WalletUpdate::Activity { wallet, signature, .. } => {
+                self.fetch(wallet, signature, fetched.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn seed(&mut self, wallet: &str) -> Result<()> {
+        let commitment = self.client.commitment();
+        let accounts = self.client.get_token_accounts(wallet, commitment).await?;
+        let native = self.client.get_native_balance(wallet, commitment).await?;
+
+        self.native.insert(wallet.to_string(), native);
+        self.token_accounts.insert(
+            wallet.to_string(),
+            accounts
+                .into_iter()
+                .map(|account| (account.address.clone(), account))
+                .collect(),
+        );
+        Ok(())
+    }
+
+    async fn write(&self, wallet: &str) -> Result<()> {
+        let Some(mut stored) = self.db.get_wallet_by_address(wallet).await? else {
+            return Ok(());
+        };
+
+        let native = self.native.get(wallet).copied().unwrap_or_default();
+        let accounts = self.token_accounts.get(wallet);
+        // NFTs confirmed when the wallet was last read stay out of the balances
+        let nfts: HashSet<String> = stored.nfts.iter().map(|nft| nft.mint.clone()).collect();
+        stored.tokens = aggregate_balances(
+            accounts.into_iter().flat_map(|a| a.values()),
+            native,
+            self.client.commitment(),
+            &nfts,
+        );
+        stored.updated_at = Utc::now();
+
+        self.db.save_wallet(&stored).await
+    }
+}
+
+async fn fetch_transaction(client: &SolanaClient, signature: &str, wallet: &str) -> Result<Transaction> {
+    let mut delay = TRANSACTION_FETCH_DELAY;
+    for _ in 0..TRANSACTION_FETCH_RETRIES {
+        match client.get_transaction(signature, wallet, client.commitment()).await {
+            Ok(transaction) => return Ok(transaction),
+            Err(e) => debug!("Transaction {} not available yet, retrying in {:?}: {}", signature, delay, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+    client.get_transaction(signature, wallet, client.commitment()).await
+}
+
+// Token accounts a successful transaction closed
+fn closed_token_accounts(transaction: &Transaction) -> HashSet<&str> {
+    if !transaction.success {
+        return HashSet::new();
+    }
+    transaction
+        .events
+        .iter()
+        .filter_map(|event| match &event.kind {
+            EventKind::TokenAccountClosed { account, .. } => Some(account.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TransactionEvent;
+    use futures::SinkExt;
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    // Answers every *Subscribe request with a fresh subscription id, then
+    // pushes one account notification and drops the connection. Handles
+    // `connections` connections in sequence.
+    async fn mock_pubsub_server(connections: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            for connection in 0..connections {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                let mut next_subscription = 100;
+                let mut account_subscription = None;
+                let mut subscriptions = 0;
+
+                while let Some(Ok(Message::Text(text))) = ws.next().await {
+                    let request: Value = serde_json::from_str(&text).unwrap();
+                    let id = request["id"].clone();
+                    let method = request["method"].as_str().unwrap_or_default().to_string();
+
+                    let response = if method.ends_with("Subscribe") {
+                        next_subscription += 1;
+                        subscriptions += 1;
+                        if method == "accountSubscribe" {
+                            account_subscription = Some(next_subscription);
+                        }
+                        json!({ "jsonrpc": "2.0", "result": next_subscription, "id": id })
+                    } else {
+                        json!({
+                            "jsonrpc": "2.0",
+                            "error": { "code": -32601, "message": "Method not found" },
+                            "id": id
+                        })
+                    };
+                    ws.send(Message::Text(response.to_string())).await.unwrap();
+
+                    // account + two token programs + logs
+                    if subscriptions == 4 {
+                        break;
+                    }
+                }
+
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "accountNotification",
+                    "params": {
+                        "result": {
+                            "context": { "slot": 1000 + connection },
+                            "value": {
+                                "lamports": 5_000_000_000u64 + connection as u64,
+                                "data": ["", "base64"],
+                                "owner": "11111111111111111111111111111111",
+                                "executable": false,
+                                "rentEpoch": 0,
+                                "space": 0
+                            }
+                        },
+                        "subscription": account_subscription.unwrap()
+                    }
+                });
+                ws.send(Message::Text(notification.to_string())).await.unwrap();
+                ws.close(None).await.ok();
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn test_streamer_resubscribes_after_disconnect() {
+        let url = mock_pubsub_server(2).await;
+        let wallet = Pubkey::new_unique().to_string();
+        let streamer = Arc::new(WalletStreamer::new(
+            url,
+            vec![wallet.clone()],
+            ReconnectPolicy {
+                initial_backoff: Duration::from_millis(10),
+                max_backoff: Duration::from_millis(50),
+            },
+        ));
+
+        let (sender, mut receiver) = mpsc::channel(16);
+        let task = tokio::spawn({
+            let streamer = streamer.clone();
+            async move { streamer.run(sender).await }
+        });
+
+        for connection in 0..2u64 {
+            let subscribed = receiver.recv().await.unwrap();
+            assert_eq!(subscribed, WalletUpdate::Subscribed { wallets: vec![wallet.clone()] });
+
+            let balance = receiver.recv().await.unwrap();
+            assert_eq!(
+                balance,
+                WalletUpdate::NativeBalance {
+                    wallet: wallet.clone(),
+                    lamports: 5_000_000_000 + connection,
+                    slot: 1000 + connection,
+                }
+            );
+        }
+
+        drop(receiver);
+        task.abort();
+    }
+
+    #[test]
+    fn test_closed_token_accounts() {
+        let closed = |account: &str| TransactionEvent {
+            program_id: TOKEN_PROGRAM_ID.to_string(),
+            instruction_index: 0,
+            inner_index: None,
+            kind: EventKind::TokenAccountClosed {
+                account: account.to_string(),
+                destination: "wallet".to_string(),
+                authority: "wallet".to_string(),
+            },
+        };
+        let mut transaction = Transaction {
+            events: vec![closed("a"), closed("b")],
+            ..Transaction::paid_by(1, "wallet")
+        };
+        assert_eq!(closed_token_accounts(&transaction), HashSet::from(["a", "b"]));
+
+        // A failed transaction closed nothing
+        transaction.success = false;
+        assert!(closed_token_accounts(&transaction).is_empty());
+    }
+}
@@ -0,0 +1,2 @@
+// src/utils/mod.rs
+pub mod helpers;
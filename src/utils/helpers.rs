@@ -1,6 +1,10 @@
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
 use std::collections::HashMap;
+use std::str::FromStr;
 
 // Common error type for the application
 #[derive(Debug, thiserror::Error)]
@@ -21,11 +25,46 @@ pub enum AppError {
     NotFound(String),
 }
 
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Database(_) | AppError::Blockchain(_) | AppError::AIService(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+// Address-related helper functions
+pub fn parse_address(address: &str) -> Result<Pubkey, AppError> {
+    Pubkey::from_str(address.trim())
+        .map_err(|_| AppError::InvalidInput(format!("{} is not a base58 encoded 32-byte address", address)))
+}
+
+// RPC and websocket URLs often carry an API key in the path or query
+// string; only the scheme, host and port are safe to log or export
+pub fn redact_url(url: &str) -> String {
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return "<invalid url>".to_string();
+    };
+    let mut redacted = format!("{}://{}", parsed.scheme(), parsed.host_str().unwrap_or_default());
+    if let Some(port) = parsed.port() {
+        redacted.push_str(&format!(":{}", port));
+    }
+    redacted
+}
+
 // Time-related helper functions
 pub fn now() -> DateTime<Utc> {
     Utc::now()
 }
 
+pub fn from_unix_timestamp(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default()
+}
+
 pub fn format_timestamp(timestamp: DateTime<Utc>) -> String {
     timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()
 }